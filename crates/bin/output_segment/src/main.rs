@@ -6,7 +6,7 @@ use cairo_vm::types::layout_name::LayoutName;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
 use cairo_vm::Felt252;
 use clap::Parser;
-use prove_block::{debug_prove_error, get_memory_segment, prove_block};
+use prove_block::{debug_prove_error, diff_pies, prove_block, OUTPUT_SEGMENT_INDEX};
 
 const DEFAULT_COMPILED_OS: &[u8] = include_bytes!("../../../../build/os_latest.json");
 
@@ -55,19 +55,18 @@ async fn main() {
 
     snos_pie.run_validity_checks().expect("Valid SNOS PIE");
 
-    // While initializing cairo-vm, the first segment is the the one containing the program instructions. The second one is the execution segment.
-    // After that, the builtins are loaded in order. The first one is always the output builtin
-    // References:
-    // https://github.com/lambdaclass/cairo-vm/blob/159f67da19964cc54a95423a69470a26e534a13d/vm/src/vm/runners/cairo_runner.rs#L249-L279
-    // https://github.com/lambdaclass/cairo-vm/blob/159f67da19964cc54a95423a69470a26e534a13d/vm/src/vm/runners/cairo_runner.rs#L456-L466
-    // cairo-vm test output segment:
-    // https://github.com/lambdaclass/cairo-vm/blob/159f67da19964cc54a95423a69470a26e534a13d/cairo1-run/src/cairo_run.rs#L1732
-    let output_segment_index = 2;
+    let differences = diff_pies(&reference_pie, &snos_pie).expect("Failed to compare PIEs");
+    let (output_differences, other_differences): (Vec<_>, Vec<_>) =
+        differences.into_iter().partition(|difference| difference.is_in_output());
 
-    assert_eq!(
-        get_memory_segment(&reference_pie, output_segment_index),
-        get_memory_segment(&snos_pie, output_segment_index)
-    );
+    // The reference PIE can come from another prover, only the output must match
+    for difference in &other_differences {
+        log::warn!("{}", difference);
+    }
+    for difference in &output_differences {
+        log::error!("{}", difference);
+    }
+    assert!(output_differences.is_empty(), "{} differences in the output of the PIEs", output_differences.len());
 
     log::info!("✅ SNOS Pie has the same output as reference pie");
 }
@@ -75,7 +74,7 @@ async fn main() {
 fn get_pie_block_number(cairo_pie: &CairoPie) -> Felt252 {
     // We know that current block number is on position (2,3)
     // Output segment, position 3.
-    let output_segment_index = 2_usize;
+    let output_segment_index = OUTPUT_SEGMENT_INDEX;
     let current_block_index = 3_usize;
     let block_number = cairo_pie
         .memory
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rpc_client::pathfinder::proofs::{PathfinderClassProof, PathfinderProof, TrieNode};
+use rpc_replay::casm_cache::CasmCache;
+use rpc_replay::class_cache::ClassCache;
+use starknet_os::config::STORED_BLOCK_HASH_BUFFER;
+use starknet_types_core::felt::Felt;
+
+/// Data shared between consecutive `prove_block` runs.
+///
+/// Everything stored here is immutable once the corresponding block is finalized, so a single
+/// cache can be used to prove any number of blocks, in any order. Proving consecutive blocks
+/// with the same cache avoids most of the redundant work, ex: the proofs fetched at block N
+/// to prove block N are also the "previous" proofs required to prove block N + 1.
+/// The data of older blocks is kept until `evict_before` is called.
+#[derive(Default)]
+pub struct ProveBlockCache {
+    /// Compiled classes, shared with the blockifier state readers.
+    class_cache: Arc<ClassCache>,
+    /// Class proofs, indexed by (block number, class hash).
+    class_proofs: Mutex<HashMap<(u64, Felt), PathfinderClassProof>>,
+    /// Block hashes, indexed by block number.
+    block_hashes: Mutex<HashMap<u64, Felt>>,
+    /// Contract proofs without storage proofs, indexed by (block number, contract address).
+    contract_proofs: Mutex<HashMap<(u64, Felt), PathfinderProof>>,
+    /// Storage proofs, indexed by (block number, contract address, storage key).
+    storage_proofs: Mutex<HashMap<(u64, Felt, Felt), Vec<TrieNode>>>,
+}
+
+impl ProveBlockCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the CASM of Sierra classes from `casm_cache` instead of compiling them when possible.
+    pub fn with_casm_cache(casm_cache: CasmCache) -> Self {
+        Self { class_cache: Arc::new(ClassCache::new().with_casm_cache(casm_cache)), ..Self::default() }
+    }
+
+    pub(crate) fn class_cache(&self) -> &Arc<ClassCache> {
+        &self.class_cache
+    }
+
+    /// Total time spent compiling classes, reported in the metrics.
+    pub(crate) fn compilation_time(&self) -> Duration {
+        self.class_cache.compilation_time()
+    }
+
+    /// Removes the data of the blocks below `block_number`. Compiled classes are kept, they do not
+    /// depend on the block.
+    /// Proving block N only requires the proofs of blocks N - 1 and N, and the hashes of blocks
+    /// N - 1 and N - `STORED_BLOCK_HASH_BUFFER`: call this with N - 1 before proving block N to keep
+    /// the memory used by the cache constant over a range of blocks. The hashes of the last
+    /// `STORED_BLOCK_HASH_BUFFER` blocks are kept, so that each hash is fetched only once.
+    pub fn evict_before(&self, block_number: u64) {
+        let oldest_block_hash = block_number.saturating_sub(STORED_BLOCK_HASH_BUFFER);
+        self.class_proofs.lock().unwrap().retain(|(block, _), _| *block >= block_number);
+        self.block_hashes.lock().unwrap().retain(|block, _| *block >= oldest_block_hash);
+        self.contract_proofs.lock().unwrap().retain(|(block, _), _| *block >= block_number);
+        self.storage_proofs.lock().unwrap().retain(|(block, _, _), _| *block >= block_number);
+    }
+
+    pub(crate) fn get_class_proof(&self, block_number: u64, class_hash: &Felt) -> Option<PathfinderClassProof> {
+        self.class_proofs.lock().unwrap().get(&(block_number, *class_hash)).cloned()
+    }
+
+    pub(crate) fn insert_class_proof(&self, block_number: u64, class_hash: Felt, proof: PathfinderClassProof) {
+        self.class_proofs.lock().unwrap().insert((block_number, class_hash), proof);
+    }
+
+    pub(crate) fn get_block_hash(&self, block_number: u64) -> Option<Felt> {
+        self.block_hashes.lock().unwrap().get(&block_number).copied()
+    }
+
+    pub(crate) fn insert_block_hash(&self, block_number: u64, block_hash: Felt) {
+        self.block_hashes.lock().unwrap().insert(block_number, block_hash);
+    }
+
+    pub(crate) fn contains_contract_proof(&self, block_number: u64, contract_address: Felt) -> bool {
+        self.contract_proofs.lock().unwrap().contains_key(&(block_number, contract_address))
+    }
+
+    /// Returns the keys for which no storage proof is cached for this contract and block.
+    /// If the contract proof itself is not cached, all the keys are returned.
+    pub(crate) fn missing_storage_keys(&self, block_number: u64, contract_address: Felt, keys: &[Felt]) -> Vec<Felt> {
+        if !self.contains_contract_proof(block_number, contract_address) {
+            return keys.to_vec();
+        }
+
+        let storage_proofs = self.storage_proofs.lock().unwrap();
+        keys.iter()
+            .filter(|key| !storage_proofs.contains_key(&(block_number, contract_address, **key)))
+            .copied()
+            .collect()
+    }
+
+    /// Stores a proof returned by the node for the specified keys.
+    /// `keys` must be in the same order as the keys passed to the RPC call.
+    pub(crate) fn insert_storage_proof(
+        &self,
+        block_number: u64,
+        contract_address: Felt,
+        keys: &[Felt],
+        mut proof: PathfinderProof,
+    ) {
+        if let Some(contract_data) = proof.contract_data.as_mut() {
+            let mut storage_proofs = self.storage_proofs.lock().unwrap();
+            for (key, storage_proof) in keys.iter().zip(std::mem::take(&mut contract_data.storage_proofs)) {
+                storage_proofs.insert((block_number, contract_address, *key), storage_proof);
+            }
+        }
+        self.contract_proofs.lock().unwrap().insert((block_number, contract_address), proof);
+    }
+
+    /// Builds the proof of the specified keys from cached data.
+    /// Returns `None` if some of the data is missing, see `missing_storage_keys`.
+    pub(crate) fn get_storage_proof(
+        &self,
+        block_number: u64,
+        contract_address: Felt,
+        keys: &[Felt],
+    ) -> Option<PathfinderProof> {
+        let mut proof = self.contract_proofs.lock().unwrap().get(&(block_number, contract_address)).cloned()?;
+
+        if let Some(contract_data) = proof.contract_data.as_mut() {
+            let storage_proofs = self.storage_proofs.lock().unwrap();
+            contract_data.storage_proofs = keys
+                .iter()
+                .map(|key| storage_proofs.get(&(block_number, contract_address, *key)).cloned())
+                .collect::<Option<Vec<_>>>()?;
+        }
+
+        Some(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rpc_client::pathfinder::proofs::{ContractData, EdgePath};
+
+    use super::*;
+
+    fn edge(value: u64) -> Vec<TrieNode> {
+        vec![TrieNode::Edge { child: Felt::from(value), path: EdgePath { len: 1, value: Felt::ONE } }]
+    }
+
+    fn proof_with_storage_proofs(storage_proofs: Vec<Vec<TrieNode>>) -> PathfinderProof {
+        PathfinderProof {
+            state_commitment: Felt::ONE,
+            class_commitment: Some(Felt::TWO),
+            contract_proof: vec![],
+            contract_data: Some(ContractData { root: Felt::THREE, storage_proofs }),
+        }
+    }
+
+    #[test]
+    fn test_storage_proofs_are_reassembled_in_key_order() {
+        let cache = ProveBlockCache::new();
+        let contract_address = Felt::from(0x1234);
+        let keys = [Felt::from(1), Felt::from(2), Felt::from(3)];
+
+        assert_eq!(cache.missing_storage_keys(10, contract_address, &keys), keys.to_vec());
+
+        cache.insert_storage_proof(10, contract_address, &keys[..2], proof_with_storage_proofs(vec![edge(1), edge(2)]));
+        assert_eq!(cache.missing_storage_keys(10, contract_address, &keys), vec![keys[2]]);
+        assert!(cache.get_storage_proof(10, contract_address, &keys).is_none());
+        // Proofs are cached per block
+        assert_eq!(cache.missing_storage_keys(11, contract_address, &keys), keys.to_vec());
+
+        cache.insert_storage_proof(10, contract_address, &keys[2..], proof_with_storage_proofs(vec![edge(3)]));
+        let proof = cache.get_storage_proof(10, contract_address, &[keys[2], keys[0]]).unwrap();
+        assert_eq!(proof.contract_data.unwrap().storage_proofs, vec![edge(3), edge(1)]);
+    }
+
+    #[test]
+    fn test_evict_before() {
+        let cache = ProveBlockCache::new();
+        let contract_address = Felt::from(0x1234);
+        let keys = [Felt::from(1)];
+        for block_number in [9, 10, 11] {
+            cache.insert_storage_proof(block_number, contract_address, &keys, proof_with_storage_proofs(vec![edge(1)]));
+        }
+        for block_number in 0..=11 {
+            cache.insert_block_hash(block_number, Felt::from(block_number));
+        }
+
+        cache.evict_before(10);
+        assert!(cache.get_storage_proof(9, contract_address, &keys).is_none());
+        // Needed as the old block hash of the next blocks
+        assert_eq!(
+            cache.get_block_hash(10 - STORED_BLOCK_HASH_BUFFER),
+            Some(Felt::from(10 - STORED_BLOCK_HASH_BUFFER))
+        );
+        assert_eq!(cache.get_block_hash(9), Some(Felt::from(9)));
+        assert_eq!(cache.get_block_hash(10), Some(Felt::from(10)));
+        assert!(cache.get_storage_proof(10, contract_address, &keys).is_some());
+        assert!(cache.get_storage_proof(11, contract_address, &keys).is_some());
+    }
+}
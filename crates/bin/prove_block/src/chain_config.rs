@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use blockifier::context::{ChainInfo, FeeTokenAddresses};
+use rpc_replay::block_context::starknet_fee_token_addresses;
+use serde::{Deserialize, Serialize};
+use starknet_api::core::{ChainId, ContractAddress};
+use starknet_os::config::StarknetOsConfig;
+
+use crate::ProveBlockError;
+
+/// Chain-level parameters used to re-execute and prove blocks: the chain ID and fee token
+/// addresses. They feed both the blockifier `ChainInfo` and the `StarknetOsConfig` of the OS.
+///
+/// Presets are available for the public Starknet networks, appchains can load their own
+/// configuration from a JSON file with `ChainConfig::from_file`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: ChainId,
+    pub strk_fee_token_address: ContractAddress,
+    pub eth_fee_token_address: ContractAddress,
+}
+
+impl ChainConfig {
+    fn public_network(chain_id: ChainId) -> Self {
+        let fee_token_addresses = starknet_fee_token_addresses();
+        Self {
+            chain_id,
+            strk_fee_token_address: fee_token_addresses.strk_fee_token_address,
+            eth_fee_token_address: fee_token_addresses.eth_fee_token_address,
+        }
+    }
+
+    pub fn mainnet() -> Self {
+        Self::public_network(ChainId::Mainnet)
+    }
+
+    pub fn sepolia() -> Self {
+        Self::public_network(ChainId::Sepolia)
+    }
+
+    /// Returns the preset of the specified chain, if it is a public Starknet network.
+    pub fn from_chain_id(chain_id: &ChainId) -> Option<Self> {
+        match chain_id {
+            ChainId::Mainnet => Some(Self::mainnet()),
+            ChainId::Sepolia => Some(Self::sepolia()),
+            _ => None,
+        }
+    }
+
+    /// Reads a configuration from a JSON file, e.g.
+    /// `{"chain_id": "MY_APPCHAIN", "strk_fee_token_address": "0x...", "eth_fee_token_address": "0x..."}`.
+    pub fn from_file(path: &Path) -> Result<Self, ProveBlockError> {
+        let read_config = || -> Result<Self, std::io::Error> {
+            let file = File::open(path)?;
+            Ok(serde_json::from_reader(BufReader::new(file))?)
+        };
+        read_config().map_err(|source| ProveBlockError::ChainConfigFileError { path: path.to_path_buf(), source })
+    }
+
+    pub fn chain_info(&self) -> ChainInfo {
+        ChainInfo {
+            chain_id: self.chain_id.clone(),
+            fee_token_addresses: FeeTokenAddresses {
+                strk_fee_token_address: self.strk_fee_token_address,
+                eth_fee_token_address: self.eth_fee_token_address,
+            },
+        }
+    }
+
+    /// The OS refers to the STRK token as the fee token and to the ETH token as the deprecated one.
+    pub fn os_config(&self) -> StarknetOsConfig {
+        StarknetOsConfig {
+            chain_id: self.chain_id.clone(),
+            fee_token_address: self.strk_fee_token_address,
+            deprecated_fee_token_address: self.eth_fee_token_address,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_config_from_json() {
+        let config: ChainConfig = serde_json::from_str(
+            r#"{"chain_id": "MY_APPCHAIN", "strk_fee_token_address": "0x1234", "eth_fee_token_address": "0x5678"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.chain_id, ChainId::Other("MY_APPCHAIN".to_string()));
+        assert!(ChainConfig::from_chain_id(&config.chain_id).is_none());
+
+        let os_config = config.os_config();
+        assert_eq!(os_config.fee_token_address, config.strk_fee_token_address);
+        assert_eq!(os_config.deprecated_fee_token_address, config.eth_fee_token_address);
+        assert_eq!(config.chain_info().fee_token_addresses.eth_fee_token_address, config.eth_fee_token_address);
+
+        assert_eq!(ChainConfig::from_chain_id(&ChainId::Sepolia), Some(ChainConfig::sepolia()));
+    }
+}
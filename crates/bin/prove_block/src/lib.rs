@@ -1,36 +1,47 @@
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::Instant;
 
 use blockifier::state::cached_state::CachedState;
+use blockifier::transaction::errors::TransactionExecutionError;
 use cairo_vm::types::layout_name::LayoutName;
 use cairo_vm::types::relocatable::MaybeRelocatable;
 use cairo_vm::vm::errors::cairo_run_errors::CairoRunError;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
 use cairo_vm::Felt252;
-use reexecute::{reexecute_transactions_with_blockifier, ProverPerContractStorage};
-use rpc_client::pathfinder::proofs::{PathfinderClassProof, ProofVerificationError};
+use reexecute::reexecute_transactions_with_blockifier;
+use rpc_client::client::is_retryable_provider_error;
+use rpc_client::pathfinder::client::ClientError;
+use rpc_client::pathfinder::proofs::{MultiProof, PathfinderClassProof, PathfinderProof, ProofOutcome};
 use rpc_client::RpcClient;
 use rpc_replay::block_context::build_block_context;
-use rpc_replay::rpc_state_reader::AsyncRpcStateReader;
+use rpc_replay::rpc_state_reader::{AsyncRpcStateReader, PrefetchError, PrefetchKeys};
 use rpc_replay::transactions::{starknet_rs_to_blockifier, ToBlockifierError};
 use rpc_replay::utils::FeltConversionError;
-use rpc_utils::{get_class_proofs, get_storage_proofs};
-use starknet::core::types::{BlockId, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs, StarknetError};
+use rpc_utils::{get_class_proofs, get_contract_classes_and_nonces, get_storage_proofs};
+use starknet::core::types::{
+    BlockId, MaybePendingBlockWithReceipts, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs, TransactionReceipt,
+};
 use starknet::providers::{Provider, ProviderError};
+use starknet_api::core::{ChainId, ContractAddress, PatriciaKey};
+use starknet_api::state::StorageKey;
 use starknet_api::StarknetApiError;
-use starknet_os::config::{StarknetGeneralConfig, StarknetOsConfig, STORED_BLOCK_HASH_BUFFER};
+use starknet_os::config::{StarknetGeneralConfig, STORED_BLOCK_HASH_BUFFER};
 use starknet_os::crypto::pedersen::PedersenHash;
 use starknet_os::crypto::poseidon::PoseidonHash;
 use starknet_os::error::SnOsError::{self};
-use starknet_os::execution::helper::{ContractStorageMap, ExecutionHelperWrapper};
+use starknet_os::execution::helper::ContractStorageMap;
+use starknet_os::io::bundle::OsRunBundle;
 use starknet_os::io::input::StarknetOsInput;
 use starknet_os::io::output::StarknetOsOutput;
-use starknet_os::run_os;
 use starknet_os::starknet::business_logic::fact_state::contract_state_objects::ContractState;
 use starknet_os::starknet::starknet_storage::CommitmentInfo;
 use starknet_os::starkware_utils::commitment_tree::base_types::Height;
 use starknet_os::starkware_utils::commitment_tree::errors::TreeError;
 use starknet_os::starkware_utils::commitment_tree::patricia_tree::patricia_tree::PatriciaTree;
+use starknet_os::{get_os_pie, run_os_vm};
 use starknet_os_types::chain_id::chain_id_from_felt;
 use starknet_os_types::error::ContractClassError;
 use starknet_os_types::starknet_core_addons::LegacyContractDecompressionError;
@@ -38,15 +49,30 @@ use starknet_types_core::felt::Felt;
 use state_utils::get_formatted_state_update;
 use thiserror::Error;
 
-use crate::reexecute::format_commitment_facts;
-use crate::rpc_utils::get_starknet_version;
+pub use crate::cache::ProveBlockCache;
+pub use crate::chain_config::ChainConfig;
+use crate::metrics::MetricsRecorder;
+pub use crate::metrics::{Phase, PhaseMetrics, ProveBlockMetrics};
+pub use crate::pie_diff::{diff_os_outputs, diff_pies, OsOutputDifference, PieDifference, OUTPUT_SEGMENT_INDEX};
+use crate::reexecute::prefetch_storage_values;
+pub use crate::reexecute::ProverPerContractStorage;
 use crate::types::starknet_rs_tx_to_internal_tx;
-
+use crate::utils::get_all_accessed_keys;
+use crate::verify::verify_reexecution;
+pub use crate::verify::{Divergence, DivergenceKind};
+pub use crate::versions::{HintSet, VersionConfig, VersionRegistry};
+
+mod cache;
+mod chain_config;
+mod metrics;
+mod pie_diff;
 mod reexecute;
 mod rpc_utils;
 mod state_utils;
 mod types;
 mod utils;
+mod verify;
+mod versions;
 
 #[derive(Debug, Error)]
 pub enum ProveBlockError {
@@ -68,48 +94,194 @@ pub enum ProveBlockError {
     ToBlockifierError(#[from] ToBlockifierError),
     #[error("Felt Conversion Error: {0}")]
     FeltConversionError(#[from] FeltConversionError),
+    #[error("Block {block_id:?} is still pending")]
+    PendingBlock { block_id: BlockId },
+    #[error(
+        "Unsupported Starknet version {version} (block {block_number}), versioned constants for this version must be \
+         registered in the version registry"
+    )]
+    UnsupportedStarknetVersion { block_number: u64, version: String },
+    #[error("Failed to load chain config from {}: {source}", path.display())]
+    ChainConfigFileError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("No preset for chain {chain_id}, a chain config must be provided")]
+    UnknownChain { chain_id: ChainId },
+    #[error("The RPC node is on chain {actual} but the chain config is for chain {expected}")]
+    ChainIdMismatch { expected: ChainId, actual: ChainId },
+    #[error("Failed to load versioned constants from {}: {source}", path.display())]
+    VersionedConstantsFileError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Block {block_number} has {n_txs} transactions but {n_traces} traces")]
+    TraceCountMismatch { block_number: u64, n_txs: usize, n_traces: usize },
+    #[error("Transaction 0x{tx_hash:x} (index {tx_index}) of block {block_number} failed in blockifier: {source}")]
+    TransactionExecutionError {
+        block_number: u64,
+        tx_index: usize,
+        tx_hash: Felt,
+        #[source]
+        source: TransactionExecutionError,
+    },
+    #[error("Failed to fetch storage proof for contract 0x{contract_address:x} at block {block_number}: {source}")]
+    StorageProofFetchError {
+        block_number: u64,
+        contract_address: Felt,
+        #[source]
+        source: ClientError,
+    },
+    #[error(
+        "Failed to fetch the storage proofs of contracts {} at block {block_number}: {source}",
+        contract_addresses.iter().map(|address| format!("0x{address:x}")).collect::<Vec<_>>().join(", ")
+    )]
+    StorageProofBatchFetchError {
+        block_number: u64,
+        contract_addresses: Vec<Felt>,
+        #[source]
+        source: ClientError,
+    },
+    #[error("Failed to fetch storage values at block {block_number}: {source}")]
+    StorageFetchError {
+        block_number: u64,
+        #[source]
+        source: ClientError,
+    },
+    #[error("Failed to prefetch the state of block {block_number}: {source}")]
+    StatePrefetchError {
+        block_number: u64,
+        #[source]
+        source: PrefetchError,
+    },
+    #[error("Invalid storage proof for contract 0x{contract_address:x} at block {block_number}: {reason}")]
+    StorageProofVerificationError { block_number: u64, contract_address: Felt, reason: String },
+    #[error("Missing storage proof for contract 0x{contract_address:x} at block {block_number}")]
+    MissingStorageProof { block_number: u64, contract_address: Felt },
+    #[error("Failed to fetch class proof for class 0x{class_hash:x} at block {block_number}: {source}")]
+    ClassProofFetchError {
+        block_number: u64,
+        class_hash: Felt,
+        #[source]
+        source: ClientError,
+    },
+    #[error(
+        "Failed to fetch the class proofs of classes {} at block {block_number}: {source}",
+        class_hashes.iter().map(|class_hash| format!("0x{class_hash:x}")).collect::<Vec<_>>().join(", ")
+    )]
+    ClassProofBatchFetchError {
+        block_number: u64,
+        class_hashes: Vec<Felt>,
+        #[source]
+        source: ClientError,
+    },
+    #[error("Invalid class proof for class 0x{class_hash:x} at block {block_number}: {reason}")]
+    ClassProofVerificationError { block_number: u64, class_hash: Felt, reason: String },
+    #[error(
+        "Storage trie of contract 0x{contract_address:x} at block {block_number} still has gaps after {iterations} \
+         rounds of fetching"
+    )]
+    StorageTrieGapFillingError { block_number: u64, contract_address: Felt, iterations: usize },
+    #[error("Class trie at block {block_number} still has gaps after {iterations} rounds of fetching")]
+    ClassTrieGapFillingError { block_number: u64, iterations: usize },
+    #[error("Missing class proof for class 0x{class_hash:x} at block {block_number}")]
+    MissingClassProof { block_number: u64, class_hash: Felt },
+    #[error(
+        "Re-execution of block {block_number} diverges from the node in {} places, first one: {}",
+        divergences.len(),
+        divergences[0]
+    )]
+    ReExecutionMismatch { block_number: u64, divergences: Vec<Divergence> },
 }
 
-fn compute_class_commitment(
-    previous_class_proofs: &HashMap<Felt, PathfinderClassProof>,
-    class_proofs: &HashMap<Felt, PathfinderClassProof>,
-    previous_root: Felt,
-    updated_root: Felt,
-) -> CommitmentInfo {
-    for (class_hash, previous_class_proof) in previous_class_proofs {
-        if let Err(e) = previous_class_proof.verify(*class_hash) {
-            match e {
-                ProofVerificationError::NonExistenceProof { .. } => {}
-                _ => panic!("Previous class proof verification failed"),
-            }
+impl ProveBlockError {
+    /// Whether retrying the same operation could succeed, i.e. the error was caused by the node
+    /// or the network rather than by the block data itself.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RpcError(e) => is_retryable_provider_error(e),
+            Self::StorageFetchError { source, .. }
+            | Self::StorageProofFetchError { source, .. }
+            | Self::StorageProofBatchFetchError { source, .. }
+            | Self::ClassProofFetchError { source, .. }
+            | Self::ClassProofBatchFetchError { source, .. } => source.is_retryable(),
+            Self::StatePrefetchError { source, .. } => source.is_retryable(),
+            _ => false,
         }
     }
+}
 
-    for (class_hash, class_proof) in class_proofs {
-        if let Err(e) = class_proof.verify(*class_hash) {
-            match e {
-                ProofVerificationError::NonExistenceProof { .. } => {}
-                _ => panic!("Current class proof verification failed"),
+/// Returns the storage proof of a contract, or an error if the proof was not fetched.
+fn get_storage_proof(
+    storage_proofs: &HashMap<Felt, PathfinderProof>,
+    block_number: u64,
+    contract_address: Felt,
+) -> Result<&PathfinderProof, ProveBlockError> {
+    storage_proofs.get(&contract_address).ok_or(ProveBlockError::MissingStorageProof { block_number, contract_address })
+}
+
+/// Verifies the contract proofs of a block against its global state root, see
+/// `PathfinderProof::verify_contract`. `contract_class_and_nonce` returns the class hash and nonce
+/// of a contract at this block.
+fn verify_contract_proofs(
+    storage_proofs: &HashMap<Felt, PathfinderProof>,
+    block_number: u64,
+    state_root: Felt,
+    contract_class_and_nonce: impl Fn(Felt) -> (Felt, Felt),
+) -> Result<(), ProveBlockError> {
+    for (contract_address, storage_proof) in storage_proofs {
+        let (class_hash, nonce) = contract_class_and_nonce(*contract_address);
+        // The storage proofs were already verified when they were fetched
+        storage_proof.verify_contract(state_root, *contract_address, class_hash, nonce, &[]).map_err(|e| {
+            ProveBlockError::StorageProofVerificationError {
+                block_number,
+                contract_address: *contract_address,
+                reason: e.to_string(),
             }
-        }
+        })?;
     }
 
-    let previous_class_proofs: Vec<_> = previous_class_proofs.values().cloned().collect();
-    let class_proofs: Vec<_> = class_proofs.values().cloned().collect();
+    Ok(())
+}
+
+fn verify_class_proofs(
+    class_proofs: &HashMap<Felt, PathfinderClassProof>,
+    block_number: u64,
+) -> Result<(), ProveBlockError> {
+    for (class_hash, class_proof) in class_proofs {
+        if let ProofOutcome::Invalid(e) = class_proof.verify(*class_hash) {
+            return Err(ProveBlockError::ClassProofVerificationError {
+                block_number,
+                class_hash: *class_hash,
+                reason: e.to_string(),
+            });
+        }
+    }
 
-    let previous_class_proofs: Vec<_> = previous_class_proofs.into_iter().map(|proof| proof.class_proof).collect();
-    let class_proofs: Vec<_> = class_proofs.into_iter().map(|proof| proof.class_proof).collect();
+    Ok(())
+}
 
-    let previous_class_commitment_facts = format_commitment_facts::<PoseidonHash>(&previous_class_proofs);
-    let current_class_commitment_facts = format_commitment_facts::<PoseidonHash>(&class_proofs);
+fn compute_class_commitment(
+    previous_class_proofs: &HashMap<Felt, PathfinderClassProof>,
+    class_proofs: &HashMap<Felt, PathfinderClassProof>,
+    previous_root: Felt,
+    updated_root: Felt,
+    block_number: u64,
+) -> Result<CommitmentInfo, ProveBlockError> {
+    verify_class_proofs(previous_class_proofs, block_number - 1)?;
+    verify_class_proofs(class_proofs, block_number)?;
 
-    let class_commitment_facts: HashMap<_, _> =
-        previous_class_commitment_facts.into_iter().chain(current_class_commitment_facts).collect();
+    let class_commitment_facts = MultiProof::<PoseidonHash>::from_proofs(
+        previous_class_proofs.values().chain(class_proofs.values()).map(|proof| proof.class_proof.as_slice()),
+    )
+    .commitment_facts();
 
     tracing::debug!("previous class trie root: {}", previous_root.to_hex_string());
     tracing::debug!("current class trie root: {}", updated_root.to_hex_string());
 
-    CommitmentInfo { previous_root, updated_root, tree_height: 251, commitment_facts: class_commitment_facts }
+    Ok(CommitmentInfo { previous_root, updated_root, tree_height: 251, commitment_facts: class_commitment_facts })
 }
 
 pub async fn prove_block(
@@ -119,31 +291,193 @@ pub async fn prove_block(
     layout: LayoutName,
     full_output: bool,
 ) -> Result<(CairoPie, StarknetOsOutput), ProveBlockError> {
+    let rpc_client = RpcClient::new(rpc_provider);
+    prove_block_with_client(compiled_os, block_number, &rpc_client, layout, full_output).await
+}
+
+/// Same as `prove_block`, using the specified RPC client.
+/// This allows to record the RPC responses used to prove the block or to prove a block
+/// offline from such a recording, see `RpcClient::new_recording` and `RpcClient::from_recording`.
+pub async fn prove_block_with_client(
+    compiled_os: &[u8],
+    block_number: u64,
+    rpc_client: &RpcClient,
+    layout: LayoutName,
+    full_output: bool,
+) -> Result<(CairoPie, StarknetOsOutput), ProveBlockError> {
+    let versions = VersionRegistry::new(compiled_os);
+    let cache = ProveBlockCache::new();
+    let options = ProveBlockOptions::new(layout, full_output);
+    let proved_block = prove_block_with_cache(&versions, block_number, rpc_client, &cache, &options).await?;
+
+    Ok((proved_block.pie, proved_block.os_output))
+}
+
+/// Default maximum number of RPC requests sent concurrently when fetching proofs,
+/// class hashes and nonces.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// Options of `prove_block_with_cache` and `prove_block_range`.
+#[derive(Debug, Clone)]
+pub struct ProveBlockOptions {
+    /// Layout used to run the OS.
+    pub layout: LayoutName,
+    /// Whether the OS output includes the previous values of all the state changes.
+    pub full_output: bool,
+    /// Maximum number of RPC requests sent concurrently when fetching proofs, class hashes and nonces.
+    pub max_concurrent_requests: usize,
+    /// Whether to check the re-execution of the block against the state update, traces and receipts
+    /// of the node before running the OS. Divergences are reported with
+    /// `ProveBlockError::ReExecutionMismatch`.
+    pub verify_reexecution: bool,
+    /// Chain ID and fee token addresses of the chain. If not set, the preset matching the chain ID
+    /// of the RPC node is used, see `ChainConfig::from_chain_id`.
+    pub chain_config: Option<ChainConfig>,
+}
+
+impl ProveBlockOptions {
+    pub fn new(layout: LayoutName, full_output: bool) -> Self {
+        Self {
+            layout,
+            full_output,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            verify_reexecution: false,
+            chain_config: None,
+        }
+    }
+}
+
+/// Everything needed to run the OS on a block, see `prepare_os_input`.
+pub struct PreparedOsInput {
+    pub block_number: u64,
+    /// The OS input, block context, re-execution results and storage of the accessed contracts.
+    /// The bundle can be saved with `OsRunBundle::dump` to run the OS again without a node,
+    /// see `starknet_os::run_os_from_bundle`.
+    pub bundle: OsRunBundle<ProverPerContractStorage>,
+    /// Configuration of the Starknet version of the block, including the OS program to run.
+    pub version_config: VersionConfig,
+    /// Time and resources spent preparing the input.
+    pub metrics: ProveBlockMetrics,
+}
+
+/// Everything produced while proving a block.
+pub struct ProvedBlock {
+    pub block_number: u64,
+    pub pie: CairoPie,
+    pub os_output: StarknetOsOutput,
+    /// The input fed to the OS, useful to re-run or debug the OS without an RPC node.
+    pub os_input: Rc<StarknetOsInput>,
+    /// Time and resources spent in each phase.
+    pub metrics: ProveBlockMetrics,
+}
+
+/// Proves all the blocks in the specified range, in order.
+///
+/// Consecutive blocks share most of their data (the proofs at block N are the previous proofs
+/// of block N + 1, compiled classes, block hashes), so the blocks are proved with a single
+/// `ProveBlockCache` to avoid fetching and compiling the same data several times.
+/// Stops at the first block that fails to be proved.
+/// Each block is proved with the configuration registered in `versions` for its Starknet version.
+pub async fn prove_block_range(
+    versions: &VersionRegistry,
+    blocks: RangeInclusive<u64>,
+    rpc_client: &RpcClient,
+    options: &ProveBlockOptions,
+) -> Result<Vec<ProvedBlock>, ProveBlockError> {
+    let cache = ProveBlockCache::new();
+    let mut results = Vec::new();
+
+    for block_number in blocks {
+        tracing::info!("Proving block {}", block_number);
+        // Only the data of the previous block and the recent block hashes can be reused
+        cache.evict_before(block_number.saturating_sub(1));
+        let proved_block = prove_block_with_cache(versions, block_number, rpc_client, &cache, options).await?;
+        results.push(proved_block);
+    }
+
+    Ok(results)
+}
+
+/// Returns the hash of the specified block, from the cache if possible.
+async fn get_block_hash(
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    block_number: u64,
+) -> Result<Felt, ProveBlockError> {
+    if let Some(block_hash) = cache.get_block_hash(block_number) {
+        return Ok(block_hash);
+    }
+
     let block_id = BlockId::Number(block_number);
-    let previous_block_id = BlockId::Number(block_number - 1);
+    let block_hash = match rpc_client.starknet_rpc().get_block_with_tx_hashes(block_id).await? {
+        MaybePendingBlockWithTxHashes::Block(block_with_tx_hashes) => block_with_tx_hashes.block_hash,
+        MaybePendingBlockWithTxHashes::PendingBlock(_) => {
+            return Err(ProveBlockError::PendingBlock { block_id });
+        }
+    };
+    cache.insert_block_hash(block_number, block_hash);
 
-    let rpc_client = RpcClient::new(rpc_provider);
+    Ok(block_hash)
+}
+
+/// Same as `prove_block_with_client`, reusing the data stored in the cache from previous runs.
+/// See `prove_block_range`.
+pub async fn prove_block_with_cache(
+    versions: &VersionRegistry,
+    block_number: u64,
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    options: &ProveBlockOptions,
+) -> Result<ProvedBlock, ProveBlockError> {
+    let prepared = prepare_os_input(versions, block_number, rpc_client, cache, options).await?;
+    run_prepared_os(prepared, options.layout)
+}
+
+/// Fetches, re-executes and assembles everything the OS needs to prove a block, without running it.
+///
+/// This is the only step that requires an RPC node: the result can be inspected or modified
+/// before being passed to `run_prepared_os`.
+/// Proofs, class hashes and nonces are fetched with at most `max_concurrent_requests` requests
+/// in flight.
+pub async fn prepare_os_input(
+    versions: &VersionRegistry,
+    block_number: u64,
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    options: &ProveBlockOptions,
+) -> Result<PreparedOsInput, ProveBlockError> {
+    // A limit of 0 would never complete any request
+    let max_concurrent_requests = options.max_concurrent_requests.max(1);
+    let mut metrics = MetricsRecorder::new(rpc_client, cache, block_number);
+
+    let block_id = BlockId::Number(block_number);
+    let previous_block_id = BlockId::Number(block_number - 1);
 
     // Step 1: build the block context
+    let rpc_fetch_timer = metrics.start_phase();
     let chain_id = chain_id_from_felt(rpc_client.starknet_rpc().chain_id().await?);
     tracing::debug!("provider's chain_id: {}", chain_id);
+    let chain_config = match &options.chain_config {
+        Some(chain_config) if chain_config.chain_id != chain_id => {
+            return Err(ProveBlockError::ChainIdMismatch { expected: chain_config.chain_id.clone(), actual: chain_id });
+        }
+        Some(chain_config) => chain_config.clone(),
+        None => ChainConfig::from_chain_id(&chain_id).ok_or(ProveBlockError::UnknownChain { chain_id })?,
+    };
 
     let block_with_txs = match rpc_client.starknet_rpc().get_block_with_txs(block_id).await? {
         MaybePendingBlockWithTxs::Block(block_with_txs) => block_with_txs,
         MaybePendingBlockWithTxs::PendingBlock(_) => {
-            panic!("Block is still pending!");
+            return Err(ProveBlockError::PendingBlock { block_id });
         }
     };
 
-    let starknet_version = get_starknet_version(&block_with_txs);
-    tracing::debug!("Starknet version: {:?}", starknet_version);
+    cache.insert_block_hash(block_number, block_with_txs.block_hash);
 
-    let previous_block = match rpc_client.starknet_rpc().get_block_with_tx_hashes(previous_block_id).await? {
-        MaybePendingBlockWithTxHashes::Block(block_with_txs) => block_with_txs,
-        MaybePendingBlockWithTxHashes::PendingBlock(_) => {
-            panic!("Block is still pending!");
-        }
-    };
+    tracing::debug!("Starknet version: {}", block_with_txs.starknet_version);
+    let version_config = versions.get(block_number, &block_with_txs.starknet_version)?;
+
+    let previous_block_hash = get_block_hash(rpc_client, cache, block_number - 1).await?;
 
     // We only need to get the older block number and hash. No need to fetch all the txs
     // This is a workaorund to catch the case where the block number is less than the buffer and still preserve the check
@@ -151,57 +485,127 @@ pub async fn prove_block(
     let older_block_number =
         if block_number <= STORED_BLOCK_HASH_BUFFER { 1 } else { block_number - STORED_BLOCK_HASH_BUFFER };
 
-    let older_block =
-        match rpc_client.starknet_rpc().get_block_with_tx_hashes(BlockId::Number(older_block_number)).await? {
-            MaybePendingBlockWithTxHashes::Block(block_with_txs_hashes) => block_with_txs_hashes,
-            MaybePendingBlockWithTxHashes::PendingBlock(_) => {
-                panic!("Block is still pending!");
-            }
-        };
-    let old_block_number = Felt252::from(older_block.block_number);
-    let old_block_hash = older_block.block_hash;
-    let block_context = build_block_context(chain_id.clone(), &block_with_txs, starknet_version)?;
+    let old_block_number = Felt252::from(older_block_number);
+    let old_block_hash = get_block_hash(rpc_client, cache, older_block_number).await?;
+    let block_context =
+        build_block_context(chain_config.chain_info(), &block_with_txs, &version_config.versioned_constants)?;
 
     // TODO: nasty clone, the conversion fns don't take references
     let transactions: Vec<_> =
         block_with_txs.transactions.clone().into_iter().map(starknet_rs_tx_to_internal_tx).collect();
 
-    let (processed_state_update, traces) = get_formatted_state_update(&rpc_client, previous_block_id, block_id).await?;
+    let (processed_state_update, traces) =
+        get_formatted_state_update(rpc_client, cache, previous_block_id, block_id).await?;
 
     let class_hash_to_compiled_class_hash = processed_state_update.class_hash_to_compiled_class_hash;
 
-    let blockifier_state_reader = AsyncRpcStateReader::new(rpc_client.clone(), BlockId::Number(block_number - 1));
+    // The classes compiled for the OS input are shared with the state reader
+    let blockifier_state_reader = AsyncRpcStateReader::new(rpc_client.clone(), BlockId::Number(block_number - 1))
+        .with_class_cache(cache.class_cache().clone());
+
+    // Fetch the state read by the block at once instead of one value at a time during the execution.
+    // Most of the storage keys written by the block are read before.
+    let mut prefetch_keys = PrefetchKeys::from_traces(&traces).map_err(ProveBlockError::StarknetApiError)?;
+    // The traces of some nodes do not include state diffs, the state update always does
+    for storage_diff in &processed_state_update.state_diff.storage_diffs {
+        let contract_address =
+            ContractAddress(PatriciaKey::try_from(storage_diff.address).map_err(ProveBlockError::StarknetApiError)?);
+        for storage_entry in &storage_diff.storage_entries {
+            let key = StorageKey(PatriciaKey::try_from(storage_entry.key).map_err(ProveBlockError::StarknetApiError)?);
+            prefetch_keys.storage.push((contract_address, key));
+        }
+    }
+    prefetch_keys.storage.sort();
+    prefetch_keys.storage.dedup();
+    blockifier_state_reader
+        .prefetch(&prefetch_keys, max_concurrent_requests)
+        .await
+        .map_err(|source| ProveBlockError::StatePrefetchError { block_number: block_number - 1, source })?;
 
     let mut blockifier_state = CachedState::new(blockifier_state_reader);
 
-    assert_eq!(block_with_txs.transactions.len(), traces.len(), "Transactions and traces must have the same length");
+    if block_with_txs.transactions.len() != traces.len() {
+        return Err(ProveBlockError::TraceCountMismatch {
+            block_number,
+            n_txs: block_with_txs.transactions.len(),
+            n_traces: traces.len(),
+        });
+    }
     let mut txs = Vec::new();
     for (tx, trace) in block_with_txs.transactions.iter().zip(traces.iter()) {
-        let transaction =
-            starknet_rs_to_blockifier(tx, trace, &block_context.block_info().gas_prices, &rpc_client, block_number)
-                .await?;
+        let transaction = starknet_rs_to_blockifier(
+            tx,
+            trace,
+            &block_context.block_info().gas_prices,
+            rpc_client,
+            cache.class_cache(),
+            block_number,
+        )
+        .await?;
         txs.push(transaction);
     }
+    // Receipts are only used to verify the re-execution
+    let receipts: Vec<TransactionReceipt> = if options.verify_reexecution {
+        match rpc_client.starknet_rpc().get_block_with_receipts(block_id).await? {
+            MaybePendingBlockWithReceipts::Block(block_with_receipts) => {
+                block_with_receipts.transactions.into_iter().map(|tx| tx.receipt).collect()
+            }
+            MaybePendingBlockWithReceipts::PendingBlock(_) => {
+                return Err(ProveBlockError::PendingBlock { block_id });
+            }
+        }
+    } else {
+        vec![]
+    };
+    metrics.end_phase(Phase::RpcFetch, rpc_fetch_timer);
+
+    let reexecution_timer = metrics.start_phase();
     let tx_execution_infos =
         reexecute_transactions_with_blockifier(&mut blockifier_state, &block_context, old_block_hash, txs)?;
-
-    let storage_proofs = get_storage_proofs(&rpc_client, block_number, &tx_execution_infos, old_block_number)
-        .await
-        .expect("Failed to fetch storage proofs");
-
-    let previous_storage_proofs =
-        get_storage_proofs(&rpc_client, block_number - 1, &tx_execution_infos, old_block_number)
-            .await
-            .expect("Failed to fetch storage proofs");
-
+    metrics.add_transaction_resources(&tx_execution_infos);
+
+    if options.verify_reexecution {
+        verify_reexecution(
+            block_number,
+            &mut blockifier_state,
+            &processed_state_update.state_diff,
+            &tx_execution_infos,
+            &traces,
+            &receipts,
+        )?;
+    }
+    metrics.end_phase(Phase::Reexecution, reexecution_timer);
+
+    let proof_fetch_timer = metrics.start_phase();
+
+    let storage_proofs = get_storage_proofs(
+        rpc_client,
+        cache,
+        block_number,
+        &tx_execution_infos,
+        old_block_number,
+        max_concurrent_requests,
+    )
+    .await?;
+
+    let previous_storage_proofs = get_storage_proofs(
+        rpc_client,
+        cache,
+        block_number - 1,
+        &tx_execution_infos,
+        old_block_number,
+        max_concurrent_requests,
+    )
+    .await?;
+
+    metrics.end_phase(Phase::ProofFetch, proof_fetch_timer);
+
+    let input_assembly_timer = metrics.start_phase();
     let default_general_config = StarknetGeneralConfig::default();
 
     let general_config = StarknetGeneralConfig {
-        starknet_os_config: StarknetOsConfig {
-            chain_id,
-            fee_token_address: block_context.chain_info().fee_token_addresses.strk_fee_token_address,
-            deprecated_fee_token_address: block_context.chain_info().fee_token_addresses.eth_fee_token_address,
-        },
+        starknet_os_config: chain_config.os_config(),
+        sequencer_address: block_context.block_info().sequencer_address,
         ..default_general_config
     };
 
@@ -209,10 +613,51 @@ pub async fn prove_block(
     let mut contract_storages = ContractStorageMap::new();
     let mut contract_address_to_class_hash = HashMap::new();
 
+    // Fetch class hashes and nonces for all the contracts at once. The system contracts (0x0
+    // and 0x1) do not have a class.
+    let mut contract_addresses: Vec<Felt> = storage_proofs
+        .keys()
+        .filter(|contract_address| ![Felt252::ZERO, Felt252::ONE].contains(contract_address))
+        .copied()
+        .collect();
+    contract_addresses.sort();
+    let contract_classes_and_nonces: HashMap<_, _> = contract_addresses
+        .iter()
+        .copied()
+        .zip(
+            get_contract_classes_and_nonces(
+                rpc_client,
+                previous_block_id,
+                block_id,
+                &contract_addresses,
+                max_concurrent_requests,
+            )
+            .await?,
+        )
+        .collect();
+
+    // Make sure that the proofs match the state roots of the blocks before using them as OS input.
+    // The system contracts have no class hash nor nonce.
+    let nonces: HashMap<_, _> =
+        processed_state_update.state_diff.nonces.iter().map(|nonce| (nonce.contract_address, nonce.nonce)).collect();
+    verify_contract_proofs(&previous_storage_proofs, block_number - 1, processed_state_update.old_root, |address| {
+        contract_classes_and_nonces
+            .get(&address)
+            .map(|class_and_nonce| (class_and_nonce.previous_class_hash, class_and_nonce.previous_nonce))
+            .unwrap_or_default()
+    })?;
+    verify_contract_proofs(&storage_proofs, block_number, processed_state_update.new_root, |address| {
+        contract_classes_and_nonces
+            .get(&address)
+            .map(|class_and_nonce| {
+                (class_and_nonce.class_hash, nonces.get(&address).copied().unwrap_or(class_and_nonce.previous_nonce))
+            })
+            .unwrap_or_default()
+    })?;
+
     // TODO: remove this clone()
     for (contract_address, storage_proof) in storage_proofs.clone() {
-        let previous_storage_proof =
-            previous_storage_proofs.get(&contract_address).expect("failed to find previous storage proof");
+        let previous_storage_proof = get_storage_proof(&previous_storage_proofs, block_number - 1, contract_address)?;
         let contract_storage_root = previous_storage_proof
             .contract_data
             .as_ref()
@@ -238,26 +683,12 @@ pub async fn prove_block(
         )?;
         contract_storages.insert(contract_address, contract_storage);
 
-        let (previous_class_hash, previous_nonce) = if [Felt252::ZERO, Felt252::ONE].contains(&contract_address) {
-            (Felt252::ZERO, Felt252::ZERO)
-        } else {
-            let previous_class_hash =
-                match rpc_client.starknet_rpc().get_class_hash_at(previous_block_id, contract_address).await {
-                    Ok(class_hash) => Ok(class_hash),
-                    Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(Felt252::ZERO),
-                    Err(e) => Err(e),
-                }?;
-
-            let previous_nonce = match rpc_client.starknet_rpc().get_nonce(previous_block_id, contract_address).await {
-                Ok(nonce) => Ok(nonce),
-                Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(Felt252::ZERO),
-                Err(e) => Err(e),
-            }?;
-
-            let class_hash = rpc_client.starknet_rpc().get_class_hash_at(block_id, contract_address).await?;
-            contract_address_to_class_hash.insert(contract_address, class_hash);
-
-            (previous_class_hash, previous_nonce)
+        let (previous_class_hash, previous_nonce) = match contract_classes_and_nonces.get(&contract_address) {
+            Some(contract_class_and_nonce) => {
+                contract_address_to_class_hash.insert(contract_address, contract_class_and_nonce.class_hash);
+                (contract_class_and_nonce.previous_class_hash, contract_class_and_nonce.previous_nonce)
+            }
+            None => (Felt252::ZERO, Felt252::ZERO),
         };
 
         let contract_state = ContractState {
@@ -269,6 +700,20 @@ pub async fn prove_block(
         contract_states.insert(contract_address, contract_state);
     }
 
+    // Fetch the storage values read by the OS now, so that the OS can run without a node.
+    // The OS also writes the hash of the old block to the block hash contract.
+    let accessed_keys = get_all_accessed_keys(&tx_execution_infos).into_iter().flat_map(|(contract_address, keys)| {
+        keys.into_iter().map(move |storage_key| (*contract_address.key(), *storage_key.key()))
+    });
+    prefetch_storage_values(
+        rpc_client,
+        previous_block_id,
+        &mut contract_storages,
+        accessed_keys.chain([(Felt252::ONE, old_block_number)]),
+    )
+    .await
+    .map_err(|source| ProveBlockError::StorageFetchError { block_number: block_number - 1, source })?;
+
     let compiled_classes = processed_state_update.compiled_classes;
     let deprecated_compiled_classes = processed_state_update.deprecated_compiled_classes;
     let declared_class_hash_component_hashes: HashMap<_, _> = processed_state_update
@@ -278,14 +723,21 @@ pub async fn prove_block(
         .collect();
 
     // query storage proofs for each accessed contract
-    let class_hashes: Vec<&Felt252> = class_hash_to_compiled_class_hash.keys().collect();
-    // TODO: we fetch proofs here for block-1, but we probably also need to fetch at the current
-    //       block, likely for contracts that are deployed in this block
+    // The class trie is modified by the classes declared in the block, `class_hash_to_compiled_class_hash`
+    // also contains these classes. Their proofs at the previous block are proofs of non-membership,
+    // `get_class_proofs` fetches the additional nodes required to insert them in the trie.
+    let mut class_hashes: Vec<Felt252> = class_hash_to_compiled_class_hash.keys().copied().collect();
+    class_hashes.sort();
+    metrics.end_phase(Phase::InputAssembly, input_assembly_timer);
+
+    let proof_fetch_timer = metrics.start_phase();
     let class_proofs =
-        get_class_proofs(&rpc_client, block_number, &class_hashes[..]).await.expect("Failed to fetch class proofs");
-    let previous_class_proofs = get_class_proofs(&rpc_client, block_number - 1, &class_hashes[..])
-        .await
-        .expect("Failed to fetch previous class proofs");
+        get_class_proofs(rpc_client, cache, block_number, &class_hashes, max_concurrent_requests).await?;
+    let previous_class_proofs =
+        get_class_proofs(rpc_client, cache, block_number - 1, &class_hashes, max_concurrent_requests).await?;
+    metrics.end_phase(Phase::ProofFetch, proof_fetch_timer);
+
+    let input_assembly_timer = metrics.start_phase();
 
     let visited_pcs: HashMap<Felt252, Vec<Felt252>> = blockifier_state
         .visited_pcs
@@ -296,11 +748,8 @@ pub async fn prove_block(
         .collect();
 
     // We can extract data from any storage proof, use the one of the block hash contract
-    let block_hash_storage_proof =
-        storage_proofs.get(&Felt::ONE).expect("there should be a storage proof for the block hash contract");
-    let previous_block_hash_storage_proof = previous_storage_proofs
-        .get(&Felt::ONE)
-        .expect("there should be a previous storage proof for the block hash contract");
+    let block_hash_storage_proof = get_storage_proof(&storage_proofs, block_number, Felt::ONE)?;
+    let previous_block_hash_storage_proof = get_storage_proof(&previous_storage_proofs, block_number - 1, Felt::ONE)?;
 
     // The root of the class commitment tree for previous and current block
     // Using requested storage proof instead of getting them from class proofs
@@ -309,17 +758,14 @@ pub async fn prove_block(
     let updated_root = block_hash_storage_proof.class_commitment.unwrap_or(Felt::ZERO);
     let previous_root = previous_block_hash_storage_proof.class_commitment.unwrap_or(Felt::ZERO);
 
-    let previous_contract_trie_root = previous_block_hash_storage_proof.contract_proof[0].hash::<PedersenHash>();
-    let current_contract_trie_root = block_hash_storage_proof.contract_proof[0].hash::<PedersenHash>();
-
-    let previous_contract_proofs: Vec<_> =
-        previous_storage_proofs.values().map(|proof| proof.contract_proof.clone()).collect();
-    let previous_state_commitment_facts = format_commitment_facts::<PedersenHash>(&previous_contract_proofs);
-    let current_contract_proofs: Vec<_> = storage_proofs.values().map(|proof| proof.contract_proof.clone()).collect();
-    let current_state_commitment_facts = format_commitment_facts::<PedersenHash>(&current_contract_proofs);
+    let previous_contract_trie_root = previous_block_hash_storage_proof.contract_root();
+    let current_contract_trie_root = block_hash_storage_proof.contract_root();
 
-    let global_state_commitment_facts: HashMap<_, _> =
-        previous_state_commitment_facts.into_iter().chain(current_state_commitment_facts).collect();
+    // The contract proofs of both blocks share most of their nodes
+    let global_state_commitment_facts = MultiProof::<PedersenHash>::from_proofs(
+        previous_storage_proofs.values().chain(storage_proofs.values()).map(|proof| proof.contract_proof.as_slice()),
+    )
+    .commitment_facts();
 
     let contract_state_commitment_info = CommitmentInfo {
         previous_root: previous_contract_trie_root,
@@ -329,9 +775,9 @@ pub async fn prove_block(
     };
 
     let contract_class_commitment_info =
-        compute_class_commitment(&previous_class_proofs, &class_proofs, previous_root, updated_root);
+        compute_class_commitment(&previous_class_proofs, &class_proofs, previous_root, updated_root, block_number)?;
 
-    let os_input = Rc::new(StarknetOsInput {
+    let os_input = StarknetOsInput {
         contract_state_commitment_info,
         contract_class_commitment_info,
         deprecated_compiled_classes,
@@ -344,18 +790,52 @@ pub async fn prove_block(
         transactions,
         declared_class_hash_to_component_hashes: declared_class_hash_component_hashes,
         new_block_hash: block_with_txs.block_hash,
-        prev_block_hash: previous_block.block_hash,
-        full_output,
-    });
-    let execution_helper = ExecutionHelperWrapper::<ProverPerContractStorage>::new(
-        contract_storages,
-        tx_execution_infos,
-        &block_context,
-        Some(os_input.clone()),
-        (old_block_number, old_block_hash),
-    );
-
-    Ok(run_os(compiled_os, layout, os_input, block_context, execution_helper)?)
+        prev_block_hash: previous_block_hash,
+        full_output: options.full_output,
+    };
+
+    metrics.end_phase(Phase::InputAssembly, input_assembly_timer);
+
+    Ok(PreparedOsInput {
+        block_number,
+        bundle: OsRunBundle::new(
+            block_with_txs.starknet_version.clone(),
+            os_input,
+            block_context,
+            tx_execution_infos,
+            old_block_number,
+            old_block_hash,
+            contract_storages,
+        ),
+        version_config: version_config.clone(),
+        metrics: metrics.finish(),
+    })
+}
+
+/// Runs the OS on a block prepared with `prepare_os_input` and extracts the PIE.
+/// The time spent in the OS is added to the metrics of the preparation.
+pub fn run_prepared_os(prepared: PreparedOsInput, layout: LayoutName) -> Result<ProvedBlock, ProveBlockError> {
+    let PreparedOsInput { block_number, bundle, version_config, mut metrics } = prepared;
+    let start = Instant::now();
+
+    let (os_input, block_context, execution_helper) = bundle.into_run_args();
+
+    let os_run_start = Instant::now();
+    // `SnosHintProcessor` implements the hints of the v0.13.2 OS, other hint sets will get their own processor
+    let (cairo_runner, os_output) = match version_config.hint_set {
+        HintSet::V0_13_2 => {
+            run_os_vm(&version_config.compiled_os, layout, os_input.clone(), block_context, execution_helper)?
+        }
+    };
+    metrics.add_phase_duration(Phase::OsRun, os_run_start.elapsed());
+
+    let pie_extraction_start = Instant::now();
+    let pie = get_os_pie(&cairo_runner)?;
+    metrics.add_phase_duration(Phase::PieExtraction, pie_extraction_start.elapsed());
+    metrics.add_execution_resources(Phase::OsRun, &pie.execution_resources);
+    metrics.duration_secs += start.elapsed().as_secs_f64();
+
+    Ok(ProvedBlock { block_number, pie, os_output, os_input, metrics })
 }
 
 pub fn debug_prove_error(err: ProveBlockError) -> ProveBlockError {
@@ -382,3 +862,54 @@ pub fn get_memory_segment(pie: &CairoPie, index: usize) -> Vec<(usize, &MaybeRel
     segment.sort_by(|(offset1, _), (offset2, _)| offset1.cmp(offset2));
     segment
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use starknet::core::types::StarknetError;
+    use starknet::providers::jsonrpc::JsonRpcClientError;
+
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        let fetch_error = ProveBlockError::StorageProofFetchError {
+            block_number: 1,
+            contract_address: Felt::ONE,
+            source: ClientError::HttpError { status: StatusCode::SERVICE_UNAVAILABLE, body: String::new() },
+        };
+        assert!(fetch_error.is_retryable());
+
+        let not_found = ProveBlockError::ClassProofFetchError {
+            block_number: 1,
+            class_hash: Felt::ONE,
+            source: ClientError::HttpError { status: StatusCode::NOT_FOUND, body: String::new() },
+        };
+        assert!(!not_found.is_retryable());
+
+        let rate_limited = ProveBlockError::RpcError(ProviderError::RateLimited);
+        assert!(rate_limited.is_retryable());
+
+        let contract_not_found =
+            ProveBlockError::RpcError(ProviderError::StarknetError(StarknetError::ContractNotFound));
+        assert!(!contract_not_found.is_retryable());
+
+        let invalid_response = ProveBlockError::RpcError(ProviderError::Other(Box::new(
+            JsonRpcClientError::TransportError(ClientError::JsonRpcError { code: -32603, message: String::new() }),
+        )));
+        assert!(!invalid_response.is_retryable());
+
+        let prefetch_error = ProveBlockError::StatePrefetchError {
+            block_number: 1,
+            source: PrefetchError::StorageFetchError(ClientError::HttpError {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                body: String::new(),
+            }),
+        };
+        assert!(prefetch_error.is_retryable());
+
+        let unsupported_version =
+            ProveBlockError::UnsupportedStarknetVersion { block_number: 1, version: "0.12.3".to_string() };
+        assert!(!unsupported_version.is_retryable());
+    }
+}
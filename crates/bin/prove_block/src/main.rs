@@ -1,18 +1,233 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
 use cairo_vm::types::layout_name::LayoutName;
-use clap::Parser;
-use prove_block::debug_prove_error;
+use cairo_vm::vm::runners::cairo_runner::ExecutionResources;
+use cairo_vm::Felt252;
+use clap::{Parser, ValueEnum};
+use prove_block::{
+    debug_prove_error, prepare_os_input, run_prepared_os, ChainConfig, HintSet, ProveBlockCache, ProveBlockOptions,
+    ProvedBlock, ProverPerContractStorage, VersionRegistry, DEFAULT_MAX_CONCURRENT_REQUESTS,
+};
+use rpc_client::disk_cache::DiskCache;
+use rpc_client::proof_provider::ProofApi;
+use rpc_client::recording::RpcRecording;
+use rpc_client::{DiskCacheConfig, RpcClient, RpcClientConfig, RpcSpecVersion};
+use rpc_replay::casm_cache::CasmCache;
+use serde::Serialize;
+use starknet::providers::Provider;
+use starknet_os::io::bundle::OsRunBundle;
+use starknet_os::run_os_from_bundle;
+
+/// Default size limit of the RPC cache, in MiB.
+const DEFAULT_RPC_CACHE_MAX_SIZE_MIB: u64 = 10 * 1024;
+
+/// Number of blocks below the latest block of the node beyond which blocks are considered final
+/// by the RPC cache, if `--rpc-cache-max-block` is not set.
+const DEFAULT_RPC_CACHE_FINALITY_DEPTH: u64 = 100;
 
 const DEFAULT_COMPILED_OS: &[u8] = include_bytes!("../../../../build/os_latest.json");
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ProofApiArg {
+    /// `pathfinder_getProof` / `pathfinder_getClassProof`, Pathfinder only.
+    Pathfinder,
+    /// `starknet_getStorageProof`, RPC v0.8 nodes (Juno, Madara, ...).
+    Starknet,
+}
+
+impl From<ProofApiArg> for ProofApi {
+    fn from(proof_api: ProofApiArg) -> Self {
+        match proof_api {
+            ProofApiArg::Pathfinder => ProofApi::Pathfinder,
+            ProofApiArg::Starknet => ProofApi::Starknet,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ChainArg {
+    Mainnet,
+    Sepolia,
+}
+
+impl From<ChainArg> for ChainConfig {
+    fn from(chain: ChainArg) -> Self {
+        match chain {
+            ChainArg::Mainnet => ChainConfig::mainnet(),
+            ChainArg::Sepolia => ChainConfig::sepolia(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum LayoutArg {
+    Plain,
+    Small,
+    Dex,
+    Recursive,
+    Starknet,
+    StarknetWithKeccak,
+    RecursiveLargeOutput,
+    RecursiveWithPoseidon,
+    AllSolidity,
+    AllCairo,
+    Dynamic,
+}
+
+impl From<LayoutArg> for LayoutName {
+    fn from(layout: LayoutArg) -> Self {
+        match layout {
+            LayoutArg::Plain => LayoutName::plain,
+            LayoutArg::Small => LayoutName::small,
+            LayoutArg::Dex => LayoutName::dex,
+            LayoutArg::Recursive => LayoutName::recursive,
+            LayoutArg::Starknet => LayoutName::starknet,
+            LayoutArg::StarknetWithKeccak => LayoutName::starknet_with_keccak,
+            LayoutArg::RecursiveLargeOutput => LayoutName::recursive_large_output,
+            LayoutArg::RecursiveWithPoseidon => LayoutName::recursive_with_poseidon,
+            LayoutArg::AllSolidity => LayoutName::all_solidity,
+            LayoutArg::AllCairo => LayoutName::all_cairo,
+            LayoutArg::Dynamic => LayoutName::dynamic,
+        }
+    }
+}
+
+/// Parses a `<VERSION>=<PATH>` argument.
+fn parse_versioned_constants_arg(arg: &str) -> Result<(String, PathBuf), String> {
+    let (version, path) = arg.split_once('=').ok_or_else(|| format!("expected <VERSION>=<PATH>, got {arg}"))?;
+    Ok((version.to_string(), PathBuf::from(path)))
+}
+
 #[derive(Parser, Debug)]
 struct Args {
     /// Block to prove.
-    #[arg(long = "block-number")]
-    block_number: u64,
+    #[arg(long = "block-number", required_unless_present = "from_bundle")]
+    block_number: Option<u64>,
+
+    /// If set, prove all the blocks from `block-number` to this block (included).
+    #[arg(long = "end-block-number")]
+    end_block_number: Option<u64>,
 
     /// RPC endpoint to use for fact fetching
     #[arg(long = "rpc-provider", default_value = "http://localhost:9545")]
     rpc_provider: String,
+
+    /// Version of the Starknet RPC spec implemented by the node: 0.6, 0.7 or 0.8.
+    #[arg(long = "rpc-spec-version", default_value_t = RpcSpecVersion::V0_7)]
+    rpc_spec_version: RpcSpecVersion,
+
+    /// URL of the Starknet RPC endpoint, if not `<rpc-provider>/rpc/v0_<spec version>`.
+    #[arg(long = "starknet-rpc-url")]
+    starknet_rpc_url: Option<String>,
+
+    /// URL of the Pathfinder RPC endpoint, if not `<rpc-provider>/rpc/pathfinder/v0.1`.
+    #[arg(long = "pathfinder-rpc-url")]
+    pathfinder_rpc_url: Option<String>,
+
+    /// URL of the endpoint serving `starknet_getStorageProof`, if not `<rpc-provider>/rpc/v0_8`.
+    #[arg(long = "storage-proof-rpc-url")]
+    storage_proof_rpc_url: Option<String>,
+
+    /// RPC API used to fetch storage and class proofs.
+    #[arg(long = "proof-api", value_enum, default_value_t = ProofApiArg::Pathfinder)]
+    proof_api: ProofApiArg,
+
+    /// Chain preset. Defaults to the preset matching the chain ID of the RPC node.
+    #[arg(long = "chain", value_enum, conflicts_with = "chain_config")]
+    chain: Option<ChainArg>,
+
+    /// JSON file with the chain ID and fee token addresses of the chain, for chains without a preset.
+    #[arg(long = "chain-config")]
+    chain_config: Option<PathBuf>,
+
+    /// Layout used to run the OS.
+    #[arg(long = "layout", value_enum, default_value_t = LayoutArg::AllCairo)]
+    layout: LayoutArg,
+
+    /// Whether the OS output includes the previous values of all the state changes.
+    #[arg(long = "full-output", default_value_t = true, action = clap::ArgAction::Set)]
+    full_output: bool,
+
+    /// Compiled OS program to run, defaults to the OS embedded in the binary.
+    #[arg(long = "os-program")]
+    os_program: Option<PathBuf>,
+
+    /// Versioned constants (blockifier JSON format) to use for blocks of a Starknet version,
+    /// as `<VERSION>=<PATH>`. Can be repeated.
+    #[arg(long = "versioned-constants", value_parser = parse_versioned_constants_arg)]
+    versioned_constants: Vec<(String, PathBuf)>,
+
+    /// If set, write the PIE, the OS output, the OS input of each block and a summary of the run
+    /// to this directory.
+    #[arg(long = "output-dir")]
+    output_dir: Option<PathBuf>,
+
+    /// If set, write the time, RPC traffic and Cairo resources of each phase of each block to this
+    /// JSON file.
+    #[arg(long = "metrics")]
+    metrics: Option<PathBuf>,
+
+    /// Maximum number of RPC requests to send concurrently.
+    #[arg(long = "max-concurrent-requests", default_value_t = DEFAULT_MAX_CONCURRENT_REQUESTS)]
+    max_concurrent_requests: usize,
+
+    /// Timeout of each RPC request, in seconds.
+    #[arg(long = "rpc-timeout")]
+    rpc_timeout: Option<u64>,
+
+    /// Number of times an RPC request is retried after a transient failure (timeout, connection
+    /// failure, HTTP 429 or 5xx).
+    #[arg(long = "rpc-max-retries")]
+    rpc_max_retries: Option<u32>,
+
+    /// Cache the responses to requests for immutable data (blocks, state updates, storage
+    /// values and proofs of a given block) in this directory, to reuse them across runs.
+    #[arg(long = "rpc-cache-dir")]
+    rpc_cache_dir: Option<PathBuf>,
+
+    /// Only cache the responses for blocks up to this number, more recent blocks could still be
+    /// reverted. Defaults to the latest block of the node minus 100.
+    #[arg(long = "rpc-cache-max-block", requires = "rpc_cache_dir")]
+    rpc_cache_max_block: Option<u64>,
+
+    /// Size limit of the RPC cache, in MiB. The oldest entries are removed beyond this size.
+    #[arg(long = "rpc-cache-max-size", default_value_t = DEFAULT_RPC_CACHE_MAX_SIZE_MIB)]
+    rpc_cache_max_size: u64,
+
+    /// Remove all the entries of the RPC cache before proving.
+    #[arg(long = "rpc-cache-clear", requires = "rpc_cache_dir")]
+    rpc_cache_clear: bool,
+
+    /// Store the CASM compiled from Sierra classes in this directory, to avoid compiling the same
+    /// classes again in later runs. The cache can be shared between chains.
+    #[arg(long = "casm-cache-dir")]
+    casm_cache_dir: Option<PathBuf>,
+
+    /// Check the re-execution of each block against the state update, traces and receipts of the
+    /// node before running the OS.
+    #[arg(long = "verify-reexecution")]
+    verify_reexecution: bool,
+
+    /// Record all the RPC responses used to prove the block in the specified archive.
+    #[arg(long = "record", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Prove the block offline, using the RPC responses from the specified archive.
+    #[arg(long = "replay")]
+    replay: Option<PathBuf>,
+
+    /// Write everything the OS consumes for each block to `<DIR>/<BLOCK>-bundle.json` before
+    /// running it, to reproduce failures without a node with `--from-bundle`.
+    #[arg(long = "bundle-dir")]
+    bundle_dir: Option<PathBuf>,
+
+    /// Run the OS on a bundle written with `--bundle-dir` instead of proving blocks from a node.
+    #[arg(long = "from-bundle")]
+    from_bundle: Option<PathBuf>,
 }
 
 fn init_logging() {
@@ -23,16 +238,286 @@ fn init_logging() {
         .expect("Failed to configure env_logger");
 }
 
+/// Machine-readable summary of a run, written to `<output-dir>/summary.json`.
+#[derive(Serialize)]
+struct RunSummary {
+    layout: LayoutArg,
+    full_output: bool,
+    /// `None` if the embedded OS was used.
+    os_program: Option<PathBuf>,
+    success: bool,
+    blocks: Vec<BlockSummary>,
+}
+
+#[derive(Serialize)]
+struct BlockSummary {
+    block_number: u64,
+    success: bool,
+    error: Option<String>,
+    duration_secs: f64,
+    execution_resources: Option<ExecutionResources>,
+    initial_root: Option<Felt252>,
+    final_root: Option<Felt252>,
+    pie: Option<PathBuf>,
+    os_output: Option<PathBuf>,
+    os_input: Option<PathBuf>,
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) {
+    let file = File::create(path).unwrap_or_else(|e| panic!("Failed to create {}: {e}", path.display()));
+    serde_json::to_writer_pretty(BufWriter::new(file), value)
+        .unwrap_or_else(|e| panic!("Failed to write {}: {e}", path.display()));
+}
+
+/// Writes the PIE, OS output and OS input of the block to the output directory.
+/// Returns the paths of the files, in this order.
+fn write_block_outputs(output_dir: &Path, proved_block: &ProvedBlock) -> (PathBuf, PathBuf, PathBuf) {
+    let block_number = proved_block.block_number;
+
+    let pie_path = output_dir.join(format!("{block_number}.zip"));
+    proved_block.pie.write_zip_file(&pie_path).expect("Failed to write PIE");
+
+    let os_output_path = output_dir.join(format!("{block_number}-os-output.json"));
+    write_json(&os_output_path, &proved_block.os_output);
+
+    let os_input_path = output_dir.join(format!("{block_number}-os-input.json"));
+    write_json(&os_input_path, proved_block.os_input.as_ref());
+
+    (pie_path, os_output_path, os_input_path)
+}
+
+/// Returns the most recent block considered final by the RPC cache, see
+/// `DEFAULT_RPC_CACHE_FINALITY_DEPTH`. If the latest block cannot be fetched, no block number is
+/// considered final: only the requests targeting a block hash are cached.
+async fn latest_final_block(rpc_provider: &str, proof_api: ProofApi, rpc_config: RpcClientConfig) -> Option<u64> {
+    let rpc_client = RpcClient::new_with_config(rpc_provider, proof_api, rpc_config);
+    match rpc_client.starknet_rpc().block_number().await {
+        Ok(latest_block) => latest_block.checked_sub(DEFAULT_RPC_CACHE_FINALITY_DEPTH),
+        Err(e) => {
+            log::warn!("Failed to fetch the latest block number, block numbers will not be cached: {}", e);
+            None
+        }
+    }
+}
+
+/// Runs the OS on a bundle written with `--bundle-dir`, without a node.
+/// The bundle is run with the versioned constants and the OS program registered for its version.
+fn run_bundle(versions: &VersionRegistry, layout: LayoutName, bundle_path: &Path) {
+    let bundle: OsRunBundle<ProverPerContractStorage> =
+        OsRunBundle::load(bundle_path).expect("Failed to load OS run bundle");
+    let block_number = bundle.block_context.block_info().block_number.0;
+    log::info!(
+        "Running the OS on block {} (Starknet {}) from {}",
+        block_number,
+        bundle.starknet_version,
+        bundle_path.display()
+    );
+
+    let version_config = versions.get(block_number, &bundle.starknet_version).expect("Unsupported bundle version");
+    let bundle = bundle.with_versioned_constants(version_config.versioned_constants.clone());
+    // `SnosHintProcessor` implements the hints of the v0.13.2 OS, other hint sets will get their own processor
+    let (pie, os_output) = match version_config.hint_set {
+        HintSet::V0_13_2 => run_os_from_bundle(&version_config.compiled_os, layout, bundle).expect("OS run"),
+    };
+    pie.run_validity_checks().expect("Valid PIE");
+    log::info!("Initial root: 0x{:x}, final root: 0x{:x}", os_output.initial_root, os_output.final_root);
+}
+
 #[tokio::main]
 async fn main() {
     init_logging();
 
     let args = Args::parse();
 
-    let block_number = args.block_number;
-    let layout = LayoutName::all_cairo;
+    let layout = LayoutName::from(args.layout);
+    let proof_api = ProofApi::from(args.proof_api);
 
-    let result = prove_block::prove_block(DEFAULT_COMPILED_OS, block_number, &args.rpc_provider, layout, true).await;
-    let (pie, _snos_output) = result.map_err(debug_prove_error).expect("Block proven");
-    pie.run_validity_checks().expect("Valid PIE");
+    let compiled_os = match &args.os_program {
+        Some(os_program_path) => std::fs::read(os_program_path).expect("Failed to read OS program"),
+        None => DEFAULT_COMPILED_OS.to_vec(),
+    };
+
+    let mut versions = VersionRegistry::new(&compiled_os);
+    for (version, path) in &args.versioned_constants {
+        versions.register_versioned_constants_file(version, path).expect("Failed to load versioned constants");
+    }
+
+    if let Some(bundle_path) = &args.from_bundle {
+        run_bundle(&versions, layout, bundle_path);
+        return;
+    }
+
+    let block_number = args.block_number.expect("--block-number is required");
+    let end_block_number = args.end_block_number.unwrap_or(block_number);
+
+    for dir in [&args.output_dir, &args.bundle_dir].into_iter().flatten() {
+        std::fs::create_dir_all(dir).expect("Failed to create output directory");
+    }
+
+    let default_rpc_config = RpcClientConfig::default();
+    let mut rpc_config = RpcClientConfig {
+        spec_version: args.rpc_spec_version,
+        starknet_rpc_url: args.starknet_rpc_url.clone(),
+        pathfinder_rpc_url: args.pathfinder_rpc_url.clone(),
+        storage_proof_rpc_url: args.storage_proof_rpc_url.clone(),
+        request_timeout: args.rpc_timeout.map(Duration::from_secs).or(default_rpc_config.request_timeout),
+        max_retries: args.rpc_max_retries.unwrap_or(default_rpc_config.max_retries),
+        max_concurrent_requests: Some(args.max_concurrent_requests),
+        ..default_rpc_config
+    };
+
+    rpc_config.disk_cache = match (&args.rpc_cache_dir, &args.replay) {
+        // Replayed runs do not reach the node
+        (Some(dir), None) => {
+            let max_cacheable_block = match args.rpc_cache_max_block {
+                Some(max_cacheable_block) => Some(max_cacheable_block),
+                None => latest_final_block(&args.rpc_provider, proof_api, rpc_config.clone()).await,
+            };
+            log::info!("Caching the RPC responses up to block {:?}", max_cacheable_block);
+            Some(DiskCacheConfig {
+                dir: dir.clone(),
+                max_size_bytes: args.rpc_cache_max_size * 1024 * 1024,
+                max_cacheable_block,
+            })
+        }
+        _ => None,
+    };
+    if let (Some(dir), true) = (&args.rpc_cache_dir, args.rpc_cache_clear) {
+        log::info!("Clearing RPC cache {}", dir.display());
+        DiskCache::open(dir, args.rpc_cache_max_size * 1024 * 1024)
+            .and_then(|disk_cache| disk_cache.clear())
+            .expect("Failed to clear RPC cache");
+    }
+
+    let rpc_client = match (&args.record, &args.replay) {
+        (_, Some(replay_path)) => {
+            let recording = RpcRecording::load(replay_path).expect("Failed to load RPC recording");
+            RpcClient::from_recording(recording, proof_api)
+        }
+        (Some(_), None) => RpcClient::new_recording_with_config(&args.rpc_provider, proof_api, rpc_config),
+        (None, None) => RpcClient::new_with_config(&args.rpc_provider, proof_api, rpc_config),
+    };
+
+    let chain_config = match (&args.chain, &args.chain_config) {
+        (_, Some(chain_config_path)) => {
+            Some(ChainConfig::from_file(chain_config_path).expect("Failed to load chain config"))
+        }
+        (Some(chain), None) => Some(ChainConfig::from(*chain)),
+        (None, None) => None,
+    };
+
+    let options = ProveBlockOptions {
+        layout,
+        full_output: args.full_output,
+        max_concurrent_requests: args.max_concurrent_requests,
+        verify_reexecution: args.verify_reexecution,
+        chain_config,
+    };
+
+    // Blocks are proved one by one instead of with `prove_block_range` to write the outputs
+    // of each block as soon as it is proven.
+    let cache = match &args.casm_cache_dir {
+        Some(dir) => ProveBlockCache::with_casm_cache(CasmCache::open(dir).expect("Failed to open CASM cache")),
+        None => ProveBlockCache::new(),
+    };
+    let mut block_summaries = vec![];
+    let mut block_metrics = vec![];
+    let mut error = None;
+
+    for block_number in block_number..=end_block_number {
+        log::info!("Proving block {}", block_number);
+        // Only the data of the previous block and the recent block hashes can be reused
+        cache.evict_before(block_number.saturating_sub(1));
+        let start = Instant::now();
+        let result = match prepare_os_input(&versions, block_number, &rpc_client, &cache, &options).await {
+            Ok(prepared) => {
+                if let Some(bundle_dir) = &args.bundle_dir {
+                    let bundle_path = bundle_dir.join(format!("{block_number}-bundle.json"));
+                    prepared.bundle.dump(&bundle_path).expect("Failed to write OS run bundle");
+                    log::info!("Saved OS run bundle to {}", bundle_path.display());
+                }
+                run_prepared_os(prepared, layout)
+            }
+            Err(e) => Err(e),
+        };
+
+        let mut block_summary = BlockSummary {
+            block_number,
+            success: false,
+            error: None,
+            duration_secs: start.elapsed().as_secs_f64(),
+            execution_resources: None,
+            initial_root: None,
+            final_root: None,
+            pie: None,
+            os_output: None,
+            os_input: None,
+        };
+
+        match result {
+            Ok(proved_block) => {
+                let validity_check = proved_block.pie.run_validity_checks();
+
+                block_summary.success = validity_check.is_ok();
+                block_summary.error = validity_check.err().map(|e| format!("Invalid PIE: {e}"));
+                block_summary.execution_resources = Some(proved_block.pie.execution_resources.clone());
+                block_summary.initial_root = Some(proved_block.os_output.initial_root);
+                block_summary.final_root = Some(proved_block.os_output.final_root);
+
+                for (phase, phase_metrics) in &proved_block.metrics.phases {
+                    log::info!(
+                        "{:?}: {:.3}s, {} RPC requests",
+                        phase,
+                        phase_metrics.duration_secs,
+                        phase_metrics.rpc.requests
+                    );
+                }
+
+                if let Some(output_dir) = &args.output_dir {
+                    let (pie_path, os_output_path, os_input_path) = write_block_outputs(output_dir, &proved_block);
+                    block_summary.pie = Some(pie_path);
+                    block_summary.os_output = Some(os_output_path);
+                    block_summary.os_input = Some(os_input_path);
+                }
+                block_metrics.push(proved_block.metrics);
+            }
+            Err(e) => {
+                block_summary.error = Some(e.to_string());
+                error = Some(e);
+            }
+        }
+
+        let success = block_summary.success;
+        block_summaries.push(block_summary);
+        if !success {
+            break;
+        }
+    }
+
+    // Save the recording even if proving failed, it is useful to reproduce the issue.
+    if let (Some(record_path), Some(recording)) = (&args.record, rpc_client.recording()) {
+        recording.save(record_path).expect("Failed to save RPC recording");
+        log::info!("Saved {} RPC responses to {}", recording.len(), record_path.display());
+    }
+
+    if let Some(metrics_path) = &args.metrics {
+        write_json(metrics_path, &block_metrics);
+    }
+
+    let success = block_summaries.iter().all(|block_summary| block_summary.success);
+    if let Some(output_dir) = &args.output_dir {
+        let summary = RunSummary {
+            layout: args.layout,
+            full_output: args.full_output,
+            os_program: args.os_program.clone(),
+            success,
+            blocks: block_summaries,
+        };
+        write_json(&output_dir.join("summary.json"), &summary);
+    }
+
+    if let Some(e) = error {
+        Err::<(), _>(e).map_err(debug_prove_error).expect("Blocks proven");
+    }
+    assert!(success, "Valid PIE");
 }
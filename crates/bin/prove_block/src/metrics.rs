@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use blockifier::transaction::objects::TransactionExecutionInfo;
+use cairo_vm::vm::runners::cairo_runner::ExecutionResources;
+use rpc_client::stats::RpcStatsSnapshot;
+use rpc_client::RpcClient;
+use serde::Serialize;
+
+use crate::cache::ProveBlockCache;
+
+/// Steps of a `prove_block` run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Block, state update, traces and classes.
+    RpcFetch,
+    /// Compilation of Sierra classes to CASM.
+    ClassCompilation,
+    /// Re-execution of the transactions with blockifier, including state reads from the node.
+    Reexecution,
+    /// Storage and class proofs.
+    ProofFetch,
+    /// Assembly of the OS input, including the fetching of the class hashes, nonces and storage values
+    /// of the contracts.
+    InputAssembly,
+    /// Run of the OS in the Cairo VM.
+    OsRun,
+    /// Extraction of the PIE from the Cairo VM.
+    PieExtraction,
+}
+
+/// Resources used by a phase.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PhaseMetrics {
+    pub duration_secs: f64,
+    pub rpc: RpcStatsSnapshot,
+    /// Cairo steps and builtins, only set for the phases that run Cairo code:
+    /// `Phase::Reexecution` and `Phase::OsRun`.
+    pub execution_resources: Option<ExecutionResources>,
+}
+
+/// Per-phase metrics of a `prove_block` run, to find out which blocks are expensive and why.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProveBlockMetrics {
+    pub block_number: u64,
+    pub duration_secs: f64,
+    pub phases: BTreeMap<Phase, PhaseMetrics>,
+}
+
+impl ProveBlockMetrics {
+    pub(crate) fn add_phase_duration(&mut self, phase: Phase, duration: Duration) {
+        self.phases.entry(phase).or_default().duration_secs += duration.as_secs_f64();
+    }
+
+    pub(crate) fn add_execution_resources(&mut self, phase: Phase, execution_resources: &ExecutionResources) {
+        let phase_metrics = self.phases.entry(phase).or_default();
+        phase_metrics.execution_resources = Some(match &phase_metrics.execution_resources {
+            Some(previous) => previous + execution_resources,
+            None => execution_resources.clone(),
+        });
+    }
+}
+
+/// State of the counters at the start of a phase.
+pub(crate) struct PhaseTimer {
+    start: Instant,
+    rpc_stats: RpcStatsSnapshot,
+    compilation_time: Duration,
+}
+
+/// Builds the `ProveBlockMetrics` of a run from the RPC client counters and the cache.
+pub(crate) struct MetricsRecorder<'a> {
+    rpc_client: &'a RpcClient,
+    cache: &'a ProveBlockCache,
+    start: Instant,
+    metrics: ProveBlockMetrics,
+}
+
+impl<'a> MetricsRecorder<'a> {
+    pub(crate) fn new(rpc_client: &'a RpcClient, cache: &'a ProveBlockCache, block_number: u64) -> Self {
+        let metrics = ProveBlockMetrics { block_number, ..Default::default() };
+        Self { rpc_client, cache, start: Instant::now(), metrics }
+    }
+
+    pub(crate) fn start_phase(&self) -> PhaseTimer {
+        PhaseTimer {
+            start: Instant::now(),
+            rpc_stats: self.rpc_client.stats(),
+            compilation_time: self.cache.compilation_time(),
+        }
+    }
+
+    /// Adds the time and RPC traffic since `timer` was started to `phase`.
+    /// Classes compiled in the meantime are accounted for in `Phase::ClassCompilation` instead.
+    pub(crate) fn end_phase(&mut self, phase: Phase, timer: PhaseTimer) {
+        let compilation_time = self.cache.compilation_time().saturating_sub(timer.compilation_time);
+        let duration = timer.start.elapsed().saturating_sub(compilation_time);
+        let rpc_stats = self.rpc_client.stats() - timer.rpc_stats;
+
+        self.metrics.add_phase_duration(phase, duration);
+        let phase_metrics = self.metrics.phases.entry(phase).or_default();
+        phase_metrics.rpc = phase_metrics.rpc + rpc_stats;
+
+        if !compilation_time.is_zero() {
+            self.metrics.add_phase_duration(Phase::ClassCompilation, compilation_time);
+        }
+    }
+
+    pub(crate) fn add_execution_resources(&mut self, phase: Phase, execution_resources: &ExecutionResources) {
+        self.metrics.add_execution_resources(phase, execution_resources);
+    }
+
+    /// Adds the Cairo resources used to run the transactions of the block.
+    pub(crate) fn add_transaction_resources(&mut self, tx_execution_infos: &[TransactionExecutionInfo]) {
+        for tx_execution_info in tx_execution_infos {
+            // The resources of a call include the ones of its inner calls
+            for call_info in [
+                &tx_execution_info.validate_call_info,
+                &tx_execution_info.execute_call_info,
+                &tx_execution_info.fee_transfer_call_info,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                self.add_execution_resources(Phase::Reexecution, &call_info.resources);
+            }
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> ProveBlockMetrics {
+        self.metrics.duration_secs = self.start.elapsed().as_secs_f64();
+        self.metrics
+    }
+}
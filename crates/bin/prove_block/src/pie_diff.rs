@@ -0,0 +1,642 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use cairo_vm::types::relocatable::MaybeRelocatable;
+use cairo_vm::vm::runners::cairo_pie::CairoPie;
+use cairo_vm::Felt252;
+use serde_json::Value;
+use starknet_os::io::output::{deserialize_os_output, kzg_data_offsets, ContractChanges, StarknetOsOutput};
+
+/// Index of the output builtin segment in the PIEs of the OS.
+///
+/// While initializing cairo-vm, the first segment is the one containing the program instructions.
+/// The second one is the execution segment. After that, the builtins are loaded in order. The first
+/// one is always the output builtin.
+/// References:
+/// https://github.com/lambdaclass/cairo-vm/blob/159f67da19964cc54a95423a69470a26e534a13d/vm/src/vm/runners/cairo_runner.rs#L249-L279
+/// https://github.com/lambdaclass/cairo-vm/blob/159f67da19964cc54a95423a69470a26e534a13d/vm/src/vm/runners/cairo_runner.rs#L456-L466
+/// cairo-vm test output segment:
+/// https://github.com/lambdaclass/cairo-vm/blob/159f67da19964cc54a95423a69470a26e534a13d/cairo1-run/src/cairo_run.rs#L1732
+pub const OUTPUT_SEGMENT_INDEX: usize = 2;
+
+/// A difference between the OS outputs of two PIEs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsOutputDifference {
+    Header {
+        field: &'static str,
+        expected: Felt252,
+        actual: Felt252,
+    },
+    MessagesToL1 {
+        expected: Vec<Felt252>,
+        actual: Vec<Felt252>,
+    },
+    MessagesToL2 {
+        expected: Vec<Felt252>,
+        actual: Vec<Felt252>,
+    },
+    /// The contract is only part of one of the outputs.
+    Contract {
+        contract_address: Felt252,
+        in_expected: bool,
+    },
+    Nonce {
+        contract_address: Felt252,
+        expected: Felt252,
+        actual: Felt252,
+    },
+    ClassHash {
+        contract_address: Felt252,
+        expected: Option<Felt252>,
+        actual: Option<Felt252>,
+    },
+    Storage {
+        contract_address: Felt252,
+        key: Felt252,
+        expected: Option<Felt252>,
+        actual: Option<Felt252>,
+    },
+    DeclaredClass {
+        class_hash: Felt252,
+        expected: Option<Felt252>,
+        actual: Option<Felt252>,
+    },
+}
+
+/// A difference between two PIEs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PieDifference {
+    /// A value of the metadata, execution resources, additional data or version of the PIEs,
+    /// e.g. `metadata.builtin_segments.output.size`.
+    Value {
+        path: String,
+        expected: Option<Value>,
+        actual: Option<Value>,
+    },
+    SegmentSize {
+        segment_index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    Memory {
+        segment_index: usize,
+        offset: usize,
+        expected: Option<MaybeRelocatable>,
+        actual: Option<MaybeRelocatable>,
+    },
+    /// A difference in the decoded OS output, reported instead of the memory of the output segment.
+    Output(OsOutputDifference),
+}
+
+impl PieDifference {
+    /// Whether the difference affects the output of the OS.
+    pub fn is_in_output(&self) -> bool {
+        match self {
+            PieDifference::Output(_) => true,
+            PieDifference::SegmentSize { segment_index, .. } | PieDifference::Memory { segment_index, .. } => {
+                *segment_index == OUTPUT_SEGMENT_INDEX
+            }
+            PieDifference::Value { .. } => false,
+        }
+    }
+}
+
+struct OptionalDisplay<'a, T>(&'a Option<T>);
+
+impl<T: fmt::Display> fmt::Display for OptionalDisplay<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{value}"),
+            None => write!(f, "nothing"),
+        }
+    }
+}
+
+fn format_felts(felts: &[Felt252]) -> String {
+    format!("[{}]", felts.iter().map(|felt| format!("0x{felt:x}")).collect::<Vec<_>>().join(", "))
+}
+
+fn format_optional_felt(felt: &Option<Felt252>) -> String {
+    match felt {
+        Some(felt) => format!("0x{felt:x}"),
+        None => "nothing".to_string(),
+    }
+}
+
+impl fmt::Display for OsOutputDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OsOutputDifference::Header { field, expected, actual } => {
+                write!(f, "{field} differs: expected 0x{expected:x}, got 0x{actual:x}")
+            }
+            OsOutputDifference::MessagesToL1 { expected, actual } => {
+                write!(f, "messages to L1 differ: expected {}, got {}", format_felts(expected), format_felts(actual))
+            }
+            OsOutputDifference::MessagesToL2 { expected, actual } => {
+                write!(f, "messages to L2 differ: expected {}, got {}", format_felts(expected), format_felts(actual))
+            }
+            OsOutputDifference::Contract { contract_address, in_expected } => {
+                let (present, missing) = if *in_expected { ("expected", "actual") } else { ("actual", "expected") };
+                write!(f, "contract 0x{contract_address:x} is in the {present} output but not in the {missing} one")
+            }
+            OsOutputDifference::Nonce { contract_address, expected, actual } => {
+                write!(f, "contract 0x{contract_address:x} nonce differs: expected 0x{expected:x}, got 0x{actual:x}")
+            }
+            OsOutputDifference::ClassHash { contract_address, expected, actual } => write!(
+                f,
+                "contract 0x{contract_address:x} class hash differs: expected {}, got {}",
+                format_optional_felt(expected),
+                format_optional_felt(actual)
+            ),
+            OsOutputDifference::Storage { contract_address, key, expected, actual } => write!(
+                f,
+                "contract 0x{contract_address:x} storage key 0x{key:x} differs: expected {}, got {}",
+                format_optional_felt(expected),
+                format_optional_felt(actual)
+            ),
+            OsOutputDifference::DeclaredClass { class_hash, expected, actual } => write!(
+                f,
+                "compiled class hash of class 0x{class_hash:x} differs: expected {}, got {}",
+                format_optional_felt(expected),
+                format_optional_felt(actual)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for PieDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PieDifference::Value { path, expected, actual } => {
+                write!(f, "{path} differs: expected {}, got {}", OptionalDisplay(expected), OptionalDisplay(actual))
+            }
+            PieDifference::SegmentSize { segment_index, expected, actual } => {
+                write!(f, "size of segment {segment_index} differs: expected {expected}, got {actual}")
+            }
+            PieDifference::Memory { segment_index, offset, expected, actual } => write!(
+                f,
+                "memory at {segment_index}:{offset} differs: expected {}, got {}",
+                OptionalDisplay(expected),
+                OptionalDisplay(actual)
+            ),
+            PieDifference::Output(difference) => write!(f, "OS output: {difference}"),
+        }
+    }
+}
+
+/// Compares every part of two PIEs: metadata, memory (segment by segment), execution resources,
+/// additional data of the builtins and version.
+///
+/// If both output segments can be decoded as a `StarknetOsOutput` and the decoded outputs differ,
+/// the differences of the output are reported as `PieDifference::Output` instead of the memory
+/// cells read by the decoder. The size of the segment and the cells the decoder skips, such as the
+/// KZG commitments, are still compared.
+pub fn diff_pies(expected: &CairoPie, actual: &CairoPie) -> Result<Vec<PieDifference>, serde_json::Error> {
+    let mut differences = vec![];
+
+    diff_json(
+        "metadata",
+        &serde_json::to_value(&expected.metadata)?,
+        &serde_json::to_value(&actual.metadata)?,
+        &mut differences,
+    );
+    diff_json(
+        "execution_resources",
+        &serde_json::to_value(&expected.execution_resources)?,
+        &serde_json::to_value(&actual.execution_resources)?,
+        &mut differences,
+    );
+    diff_json(
+        "additional_data",
+        &serde_json::to_value(&expected.additional_data)?,
+        &serde_json::to_value(&actual.additional_data)?,
+        &mut differences,
+    );
+    diff_json(
+        "version",
+        &serde_json::to_value(&expected.version)?,
+        &serde_json::to_value(&actual.version)?,
+        &mut differences,
+    );
+
+    let expected_segments = get_memory_segments(expected);
+    let actual_segments = get_memory_segments(actual);
+    let empty_segment = BTreeMap::new();
+    let segment_indexes: BTreeSet<usize> = expected_segments.keys().chain(actual_segments.keys()).copied().collect();
+
+    for segment_index in segment_indexes {
+        let expected_segment = expected_segments.get(&segment_index).unwrap_or(&empty_segment);
+        let actual_segment = actual_segments.get(&segment_index).unwrap_or(&empty_segment);
+
+        let mut decoded_offsets = vec![];
+        if segment_index == OUTPUT_SEGMENT_INDEX {
+            if let (Some((expected_output, expected_offsets)), Some((actual_output, actual_offsets))) =
+                (decode_os_output(expected_segment), decode_os_output(actual_segment))
+            {
+                let output_differences = diff_os_outputs(&expected_output, &actual_output);
+                // If the decoded outputs are identical, the memory is compared cell by cell: the cells
+                // can still differ, e.g. in the order of the contracts
+                if !output_differences.is_empty() {
+                    differences.extend(output_differences.into_iter().map(PieDifference::Output));
+                    // Cells read by only one of the decoders are compared as memory
+                    decoded_offsets = expected_offsets
+                        .iter()
+                        .flat_map(|expected_range| {
+                            actual_offsets.iter().map(move |actual_range| {
+                                expected_range.start.max(actual_range.start)..expected_range.end.min(actual_range.end)
+                            })
+                        })
+                        .filter(|range| !range.is_empty())
+                        .collect();
+                }
+            }
+        }
+
+        diff_memory_segment(segment_index, expected_segment, actual_segment, &decoded_offsets, &mut differences);
+    }
+
+    Ok(differences)
+}
+
+/// Compares the changes to the state, messages and header of two OS outputs.
+pub fn diff_os_outputs(expected: &StarknetOsOutput, actual: &StarknetOsOutput) -> Vec<OsOutputDifference> {
+    let mut differences = vec![];
+
+    let headers = [
+        ("initial_root", expected.initial_root, actual.initial_root),
+        ("final_root", expected.final_root, actual.final_root),
+        ("prev_block_number", expected.prev_block_number, actual.prev_block_number),
+        ("new_block_number", expected.new_block_number, actual.new_block_number),
+        ("prev_block_hash", expected.prev_block_hash, actual.prev_block_hash),
+        ("new_block_hash", expected.new_block_hash, actual.new_block_hash),
+        ("os_program_hash", expected.os_program_hash, actual.os_program_hash),
+        ("starknet_os_config_hash", expected.starknet_os_config_hash, actual.starknet_os_config_hash),
+        ("use_kzg_da", expected.use_kzg_da, actual.use_kzg_da),
+        ("full_output", expected.full_output, actual.full_output),
+    ];
+    for (field, expected, actual) in headers {
+        if expected != actual {
+            differences.push(OsOutputDifference::Header { field, expected, actual });
+        }
+    }
+
+    if expected.messages_to_l1 != actual.messages_to_l1 {
+        differences.push(OsOutputDifference::MessagesToL1 {
+            expected: expected.messages_to_l1.clone(),
+            actual: actual.messages_to_l1.clone(),
+        });
+    }
+    if expected.messages_to_l2 != actual.messages_to_l2 {
+        differences.push(OsOutputDifference::MessagesToL2 {
+            expected: expected.messages_to_l2.clone(),
+            actual: actual.messages_to_l2.clone(),
+        });
+    }
+
+    let expected_contracts: BTreeMap<Felt252, &ContractChanges> =
+        expected.contracts.iter().map(|contract| (contract.addr, contract)).collect();
+    let actual_contracts: BTreeMap<Felt252, &ContractChanges> =
+        actual.contracts.iter().map(|contract| (contract.addr, contract)).collect();
+    let contract_addresses: BTreeSet<Felt252> =
+        expected_contracts.keys().chain(actual_contracts.keys()).copied().collect();
+
+    for contract_address in contract_addresses {
+        let (expected_contract, actual_contract) =
+            match (expected_contracts.get(&contract_address), actual_contracts.get(&contract_address)) {
+                (Some(expected_contract), Some(actual_contract)) => (expected_contract, actual_contract),
+                (expected_contract, _) => {
+                    differences.push(OsOutputDifference::Contract {
+                        contract_address,
+                        in_expected: expected_contract.is_some(),
+                    });
+                    continue;
+                }
+            };
+
+        if expected_contract.nonce != actual_contract.nonce {
+            differences.push(OsOutputDifference::Nonce {
+                contract_address,
+                expected: expected_contract.nonce,
+                actual: actual_contract.nonce,
+            });
+        }
+        if expected_contract.class_hash != actual_contract.class_hash {
+            differences.push(OsOutputDifference::ClassHash {
+                contract_address,
+                expected: expected_contract.class_hash,
+                actual: actual_contract.class_hash,
+            });
+        }
+        differences
+            .extend(diff_felt_maps(&expected_contract.storage_changes, &actual_contract.storage_changes).map(
+                |(key, expected, actual)| OsOutputDifference::Storage { contract_address, key, expected, actual },
+            ));
+    }
+
+    differences.extend(
+        diff_felt_maps(&expected.classes, &actual.classes)
+            .map(|(class_hash, expected, actual)| OsOutputDifference::DeclaredClass { class_hash, expected, actual }),
+    );
+
+    differences
+}
+
+/// Returns the keys with different values in the two maps, in order.
+fn diff_felt_maps<'a>(
+    expected: &'a HashMap<Felt252, Felt252>,
+    actual: &'a HashMap<Felt252, Felt252>,
+) -> impl Iterator<Item = (Felt252, Option<Felt252>, Option<Felt252>)> + 'a {
+    let keys: BTreeSet<Felt252> = expected.keys().chain(actual.keys()).copied().collect();
+    keys.into_iter().filter_map(|key| {
+        let (expected, actual) = (expected.get(&key).copied(), actual.get(&key).copied());
+        (expected != actual).then_some((key, expected, actual))
+    })
+}
+
+/// Records the differences between two JSON values, `path` being the location of the values
+/// in the PIE.
+fn diff_json(path: &str, expected: &Value, actual: &Value, differences: &mut Vec<PieDifference>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let keys: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+            for key in keys {
+                let path = format!("{path}.{key}");
+                match (expected.get(key), actual.get(key)) {
+                    (Some(expected), Some(actual)) => diff_json(&path, expected, actual, differences),
+                    (expected, actual) => differences.push(PieDifference::Value {
+                        path,
+                        expected: expected.cloned(),
+                        actual: actual.cloned(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for index in 0..expected.len().max(actual.len()) {
+                let path = format!("{path}[{index}]");
+                match (expected.get(index), actual.get(index)) {
+                    (Some(expected), Some(actual)) => diff_json(&path, expected, actual, differences),
+                    (expected, actual) => differences.push(PieDifference::Value {
+                        path,
+                        expected: expected.cloned(),
+                        actual: actual.cloned(),
+                    }),
+                }
+            }
+        }
+        (expected, actual) if expected != actual => differences.push(PieDifference::Value {
+            path: path.to_string(),
+            expected: Some(expected.clone()),
+            actual: Some(actual.clone()),
+        }),
+        _ => {}
+    }
+}
+
+type MemorySegment<'a> = BTreeMap<usize, &'a MaybeRelocatable>;
+
+fn get_memory_segments(pie: &CairoPie) -> BTreeMap<usize, MemorySegment<'_>> {
+    let mut segments: BTreeMap<usize, MemorySegment> = BTreeMap::new();
+    for ((segment_index, offset), value) in &pie.memory.0 {
+        segments.entry(*segment_index).or_default().insert(*offset, value);
+    }
+    segments
+}
+
+fn segment_size(segment: &MemorySegment) -> usize {
+    segment.keys().next_back().map(|offset| offset + 1).unwrap_or(0)
+}
+
+/// Records the differences between two memory segments, except for the cells at `skipped_offsets`.
+fn diff_memory_segment(
+    segment_index: usize,
+    expected: &MemorySegment,
+    actual: &MemorySegment,
+    skipped_offsets: &[Range<usize>],
+    differences: &mut Vec<PieDifference>,
+) {
+    let (expected_size, actual_size) = (segment_size(expected), segment_size(actual));
+    if expected_size != actual_size {
+        differences.push(PieDifference::SegmentSize { segment_index, expected: expected_size, actual: actual_size });
+    }
+
+    let offsets: BTreeSet<usize> = expected.keys().chain(actual.keys()).copied().collect();
+    for offset in offsets.into_iter().filter(|offset| !skipped_offsets.iter().any(|range| range.contains(offset))) {
+        let (expected, actual) = (expected.get(&offset).copied(), actual.get(&offset).copied());
+        if expected != actual {
+            differences.push(PieDifference::Memory {
+                segment_index,
+                offset,
+                expected: expected.cloned(),
+                actual: actual.cloned(),
+            });
+        }
+    }
+}
+
+/// Decodes the output segment of a PIE of the OS, if it is a valid OS output.
+/// Also returns the offsets of the cells read by the decoder, i.e. all the cells of the output
+/// except the KZG data and the cells after the end of the output.
+fn decode_os_output(segment: &MemorySegment) -> Option<(StarknetOsOutput, Vec<Range<usize>>)> {
+    let output = (0..segment_size(segment))
+        .map(|offset| match segment.get(&offset) {
+            Some(MaybeRelocatable::Int(value)) => Some(*value),
+            _ => None,
+        })
+        .collect::<Option<Vec<Felt252>>>()?;
+
+    let mut output_iter = output.iter().copied();
+    let os_output = deserialize_os_output(&mut output_iter).ok()?;
+    let output_end = output.len() - output_iter.len();
+    let decoded_offsets = match kzg_data_offsets(&output) {
+        Some(kzg_offsets) => vec![0..kzg_offsets.start, kzg_offsets.end..output_end],
+        None => vec![0..output_end],
+    };
+
+    Some((os_output, decoded_offsets))
+}
+
+#[cfg(test)]
+mod tests {
+    use cairo_vm::types::builtin_name::BuiltinName;
+    use cairo_vm::types::program::StrippedProgram;
+    use cairo_vm::vm::runners::cairo_pie::{
+        CairoPieAdditionalData, CairoPieMemory, CairoPieMetadata, CairoPieVersion, SegmentInfo,
+    };
+    use cairo_vm::vm::runners::cairo_runner::ExecutionResources;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Builds a PIE with the specified memory, the other parts of the PIE being empty.
+    fn cairo_pie(memory: Vec<((usize, usize), MaybeRelocatable)>) -> CairoPie {
+        CairoPie {
+            metadata: CairoPieMetadata {
+                program: StrippedProgram { data: vec![], builtins: vec![BuiltinName::output], main: 0, prime: () },
+                program_segment: SegmentInfo::from((0, 0)),
+                execution_segment: SegmentInfo::from((1, 1)),
+                ret_fp_segment: SegmentInfo::from((3, 0)),
+                ret_pc_segment: SegmentInfo::from((4, 0)),
+                builtin_segments: HashMap::from([(BuiltinName::output, SegmentInfo::from((2, 18)))]),
+                extra_segments: vec![],
+            },
+            memory: CairoPieMemory(memory),
+            execution_resources: ExecutionResources {
+                n_steps: 0,
+                n_memory_holes: 0,
+                builtin_instance_counter: HashMap::new(),
+            },
+            additional_data: CairoPieAdditionalData(HashMap::new()),
+            version: CairoPieVersion { cairo_pie: () },
+        }
+    }
+
+    /// Output of a block using KZG data availability with a single blob: header, KZG data and
+    /// (empty) messages.
+    fn kzg_os_output(final_root: u64, kzg_commitment: u64) -> Vec<Felt252> {
+        let header = [1, final_root, 99, 100, 3, 4, 0, 5, 1, 0];
+        let kzg_data = [0x10, 1, kzg_commitment, 0x11, 0x12, 0x13];
+        let messages = [0, 0];
+        header.into_iter().chain(kzg_data).chain(messages).map(Felt252::from).collect()
+    }
+
+    fn pie_memory(output: Vec<Felt252>, execution: u64) -> Vec<((usize, usize), MaybeRelocatable)> {
+        let mut memory = vec![((1, 0), MaybeRelocatable::from(Felt252::from(execution)))];
+        memory.extend(
+            output
+                .into_iter()
+                .enumerate()
+                .map(|(offset, value)| ((OUTPUT_SEGMENT_INDEX, offset), MaybeRelocatable::from(value))),
+        );
+        memory
+    }
+
+    fn os_output(storage_changes: &[(u64, u64)]) -> StarknetOsOutput {
+        StarknetOsOutput {
+            initial_root: Felt252::from(1),
+            final_root: Felt252::from(2),
+            prev_block_number: Felt252::from(99),
+            new_block_number: Felt252::from(100),
+            prev_block_hash: Felt252::from(3),
+            new_block_hash: Felt252::from(4),
+            os_program_hash: Felt252::ZERO,
+            starknet_os_config_hash: Felt252::from(5),
+            use_kzg_da: Felt252::ZERO,
+            full_output: Felt252::ONE,
+            messages_to_l1: vec![],
+            messages_to_l2: vec![],
+            contracts: vec![ContractChanges {
+                addr: Felt252::from(0x100),
+                nonce: Felt252::ONE,
+                class_hash: None,
+                storage_changes: storage_changes
+                    .iter()
+                    .map(|(key, value)| (Felt252::from(*key), Felt252::from(*value)))
+                    .collect(),
+            }],
+            classes: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_diff_os_outputs() {
+        let expected = os_output(&[(1, 10), (2, 20)]);
+        let mut actual = os_output(&[(1, 10), (2, 21), (3, 30)]);
+        actual.final_root = Felt252::from(6);
+
+        let differences = diff_os_outputs(&expected, &actual);
+        let contract_address = Felt252::from(0x100);
+        assert_eq!(
+            differences,
+            vec![
+                OsOutputDifference::Header {
+                    field: "final_root",
+                    expected: Felt252::from(2),
+                    actual: Felt252::from(6)
+                },
+                OsOutputDifference::Storage {
+                    contract_address,
+                    key: Felt252::from(2),
+                    expected: Some(Felt252::from(20)),
+                    actual: Some(Felt252::from(21)),
+                },
+                OsOutputDifference::Storage {
+                    contract_address,
+                    key: Felt252::from(3),
+                    expected: None,
+                    actual: Some(Felt252::from(30)),
+                },
+            ]
+        );
+        assert_eq!(differences[1].to_string(), "contract 0x100 storage key 0x2 differs: expected 0x14, got 0x15");
+        assert!(diff_os_outputs(&expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let expected = json!({"builtin_segments": {"output": {"index": 2, "size": 10}}, "extra_segments": [1, 2]});
+        let actual = json!({"builtin_segments": {"output": {"index": 2, "size": 12}}, "extra_segments": [1]});
+
+        let mut differences = vec![];
+        diff_json("metadata", &expected, &actual, &mut differences);
+        assert_eq!(
+            differences,
+            vec![
+                PieDifference::Value {
+                    path: "metadata.builtin_segments.output.size".to_string(),
+                    expected: Some(json!(10)),
+                    actual: Some(json!(12)),
+                },
+                PieDifference::Value {
+                    path: "metadata.extra_segments[1]".to_string(),
+                    expected: Some(json!(2)),
+                    actual: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_pies() {
+        let expected = cairo_pie(pie_memory(kzg_os_output(2, 0x20), 7));
+        let mut actual_output = kzg_os_output(6, 0x21);
+        actual_output.push(Felt252::from(0x30));
+        let actual = cairo_pie(pie_memory(actual_output, 8));
+
+        let differences = diff_pies(&expected, &actual).unwrap();
+        assert_eq!(
+            differences,
+            vec![
+                PieDifference::Memory {
+                    segment_index: 1,
+                    offset: 0,
+                    expected: Some(MaybeRelocatable::from(Felt252::from(7))),
+                    actual: Some(MaybeRelocatable::from(Felt252::from(8))),
+                },
+                PieDifference::Output(OsOutputDifference::Header {
+                    field: "final_root",
+                    expected: Felt252::from(2),
+                    actual: Felt252::from(6),
+                }),
+                PieDifference::SegmentSize { segment_index: OUTPUT_SEGMENT_INDEX, expected: 18, actual: 19 },
+                // KZG commitment, skipped by the decoder
+                PieDifference::Memory {
+                    segment_index: OUTPUT_SEGMENT_INDEX,
+                    offset: 12,
+                    expected: Some(MaybeRelocatable::from(Felt252::from(0x20))),
+                    actual: Some(MaybeRelocatable::from(Felt252::from(0x21))),
+                },
+                // After the end of the output
+                PieDifference::Memory {
+                    segment_index: OUTPUT_SEGMENT_INDEX,
+                    offset: 18,
+                    expected: None,
+                    actual: Some(MaybeRelocatable::from(Felt252::from(0x30))),
+                },
+            ]
+        );
+        assert_eq!(differences.iter().filter(|difference| difference.is_in_output()).count(), 4);
+        assert!(diff_pies(&expected, &expected).unwrap().is_empty());
+    }
+}
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::error::Error;
 
 use blockifier::blockifier::block::{pre_process_block, BlockNumberHashPair};
 use blockifier::context::BlockContext;
@@ -10,20 +9,21 @@ use blockifier::transaction::objects::TransactionExecutionInfo;
 use blockifier::transaction::transaction_execution::Transaction;
 use blockifier::transaction::transactions::ExecutableTransaction;
 use cairo_vm::Felt252;
-use rpc_client::pathfinder::proofs::{PathfinderProof, TrieNode};
+use rpc_client::pathfinder::client::ClientError;
+use rpc_client::pathfinder::proofs::{MultiProof, PathfinderProof};
 use rpc_client::RpcClient;
+use serde::{Deserialize, Serialize};
 use starknet::core::types::{BlockId, StarknetError};
 use starknet::providers::{Provider as _, ProviderError};
 use starknet_api::transaction::TransactionHash;
 use starknet_os::config::{DEFAULT_STORAGE_TREE_HEIGHT, STORED_BLOCK_HASH_BUFFER};
 use starknet_os::crypto::pedersen::PedersenHash;
+use starknet_os::execution::helper::ContractStorageMap;
 use starknet_os::starknet::starknet_storage::{CommitmentInfo, CommitmentInfoError, PerContractStorage};
-use starknet_os::starkware_utils::commitment_tree::base_types::{Length, NodePath, TreeIndex};
+use starknet_os::starkware_utils::commitment_tree::base_types::TreeIndex;
 use starknet_os::starkware_utils::commitment_tree::errors::TreeError;
-use starknet_os::starkware_utils::commitment_tree::inner_node_fact::InnerNodeFact;
-use starknet_os::starkware_utils::commitment_tree::patricia_tree::nodes::{BinaryNodeFact, EdgeNodeFact};
-use starknet_os::storage::dict_storage::DictStorage;
-use starknet_os::storage::storage::{Fact, HashFunctionType};
+
+use crate::ProveBlockError;
 
 /// Retrieves the transaction hash from a Blockifier `Transaction` object.
 fn get_tx_hash(tx: &Transaction) -> TransactionHash {
@@ -43,7 +43,7 @@ pub fn reexecute_transactions_with_blockifier<S: StateReader>(
     block_context: &BlockContext,
     buffer_block_hash: Felt252,
     txs: Vec<Transaction>,
-) -> Result<Vec<TransactionExecutionInfo>, Box<dyn Error>> {
+) -> Result<Vec<TransactionExecutionInfo>, ProveBlockError> {
     let current_block_number = block_context.block_info().block_number;
     let buffer_block_number_and_hash = if current_block_number.0 >= STORED_BLOCK_HASH_BUFFER {
         Some(BlockNumberHashPair {
@@ -57,7 +57,8 @@ pub fn reexecute_transactions_with_blockifier<S: StateReader>(
     // Writes the hash of the (current_block_number - N) block under its block number in the dedicated
     // contract state, where N=STORED_BLOCK_HASH_BUFFER.
     // https://github.com/starkware-libs/sequencer/blob/ee6513d338011067e46c55db4aa6926c8e57650e/crates/blockifier/src/blockifier/block.rs#L110
-    pre_process_block(state, buffer_block_number_and_hash, current_block_number)?;
+    pre_process_block(state, buffer_block_number_and_hash, current_block_number)
+        .map_err(|e| ProveBlockError::ReExecutionError(Box::new(e)))?;
 
     let n_txs = txs.len();
     let tx_execution_infos = txs
@@ -65,39 +66,47 @@ pub fn reexecute_transactions_with_blockifier<S: StateReader>(
         .enumerate()
         .map(|(index, tx)| {
             let tx_hash = get_tx_hash(&tx);
-            let tx_result = tx.execute(state, block_context, true, true);
-            match tx_result {
-                Err(e) => {
-                    panic!("Transaction {:x} ({}/{}) failed in blockifier: {}", tx_hash.0, index + 1, n_txs, e);
-                }
-                Ok(info) => {
-                    if info.is_reverted() {
-                        tracing::warn!(
-                            "Transaction {:x} ({}/{}) reverted: {:?}",
-                            tx_hash.0,
-                            index + 1,
-                            n_txs,
-                            info.revert_error
-                        );
-                        tracing::warn!("TransactionExecutionInfo: {:?}", info);
-                    }
-                    info
+            let info = tx.execute(state, block_context, true, true).map_err(|e| {
+                ProveBlockError::TransactionExecutionError {
+                    block_number: current_block_number.0,
+                    tx_index: index,
+                    tx_hash: tx_hash.0,
+                    source: e,
                 }
+            })?;
+            if info.is_reverted() {
+                tracing::warn!(
+                    "Transaction {:x} ({}/{}) reverted: {:?}",
+                    tx_hash.0,
+                    index + 1,
+                    n_txs,
+                    info.revert_error
+                );
+                tracing::warn!("TransactionExecutionInfo: {:?}", info);
             }
+            Ok(info)
         })
-        .collect();
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(tx_execution_infos)
 }
 
-pub(crate) struct ProverPerContractStorage {
-    rpc_client: RpcClient,
-    block_id: BlockId,
+/// Storage of a contract as seen by the OS: the storage proofs of the contract before and after
+/// the block, the values read from the node and the writes of the block.
+///
+/// The storage can be saved in an `OsRunBundle`. The node is not saved with it, so the values
+/// read by the OS must be fetched beforehand with `prefetch_storage_values`.
+#[derive(Serialize, Deserialize)]
+pub struct ProverPerContractStorage {
+    /// Node and block to read the storage values from.
+    #[serde(skip)]
+    node: Option<(RpcClient, BlockId)>,
     contract_address: Felt252,
     previous_tree_root: Felt252,
     storage_proof: PathfinderProof,
     previous_storage_proof: PathfinderProof,
-    ongoing_storage_changes: HashMap<TreeIndex, Felt252>,
+    /// Storage values indexed by key, initially the values read from the node.
+    ongoing_storage_changes: HashMap<Felt252, Felt252>,
 }
 
 impl ProverPerContractStorage {
@@ -110,8 +119,7 @@ impl ProverPerContractStorage {
         previous_storage_proof: PathfinderProof,
     ) -> Result<Self, TreeError> {
         Ok(Self {
-            rpc_client,
-            block_id,
+            node: Some((rpc_client, block_id)),
             contract_address,
             previous_tree_root,
             storage_proof,
@@ -121,43 +129,47 @@ impl ProverPerContractStorage {
     }
 }
 
-pub(crate) fn format_commitment_facts<H: HashFunctionType>(
-    trie_nodes: &[Vec<TrieNode>],
-) -> HashMap<Felt252, Vec<Felt252>> {
-    let mut facts = HashMap::new();
-
-    for nodes in trie_nodes {
-        for node in nodes {
-            let (key, fact_as_tuple) = match node {
-                TrieNode::Binary { left, right } => {
-                    let fact = BinaryNodeFact::new((*left).into(), (*right).into())
-                        .expect("storage proof endpoint gave us an invalid binary node");
-
-                    // TODO: the hash function should probably be split from the Fact trait.
-                    //       we use a placeholder for the Storage trait in the meantime.
-                    let node_hash = Felt252::from(<BinaryNodeFact as Fact<DictStorage, H>>::hash(&fact));
-                    let fact_as_tuple = <BinaryNodeFact as InnerNodeFact<DictStorage, H>>::to_tuple(&fact);
-
-                    (node_hash, fact_as_tuple)
-                }
-                TrieNode::Edge { child, path } => {
-                    let fact = EdgeNodeFact::new((*child).into(), NodePath(path.value.to_biguint()), Length(path.len))
-                        .expect("storage proof endpoint gave us an invalid edge node");
-                    // TODO: the hash function should probably be split from the Fact trait.
-                    //       we use a placeholder for the Storage trait in the meantime.
-                    let node_hash = Felt252::from(<EdgeNodeFact as Fact<DictStorage, H>>::hash(&fact));
-                    let fact_as_tuple = <EdgeNodeFact as InnerNodeFact<DictStorage, H>>::to_tuple(&fact);
-
-                    (node_hash, fact_as_tuple)
-                }
-            };
+/// Reads the values of the specified `(contract address, key)` pairs from the node with batched
+/// requests and stores them in the storage of each contract, so that the OS can read them without
+/// a node. Keys of contracts without a storage and keys already known are skipped.
+pub(crate) async fn prefetch_storage_values(
+    rpc_client: &RpcClient,
+    block_id: BlockId,
+    contract_storages: &mut ContractStorageMap<ProverPerContractStorage>,
+    keys: impl IntoIterator<Item = (Felt252, Felt252)>,
+) -> Result<(), ClientError> {
+    let mut missing_keys: Vec<(Felt252, Felt252)> = keys
+        .into_iter()
+        .filter(|(contract_address, key)| {
+            contract_storages
+                .get(contract_address)
+                .is_some_and(|contract_storage| !contract_storage.ongoing_storage_changes.contains_key(key))
+        })
+        .collect();
+    missing_keys.sort();
+    missing_keys.dedup();
 
-            let fact_as_tuple_of_felts: Vec<_> = fact_as_tuple.into_iter().map(Felt252::from).collect();
-            facts.insert(key, fact_as_tuple_of_felts);
+    let values = rpc_client.get_storage_values(block_id, &missing_keys).await?;
+    for ((contract_address, key), value) in missing_keys.into_iter().zip(values) {
+        if let Some(contract_storage) = contract_storages.get_mut(&contract_address) {
+            contract_storage.ongoing_storage_changes.insert(key, value);
         }
     }
 
-    facts
+    Ok(())
+}
+
+/// Reads a storage value from the node. The storage of a contract that does not exist yet is empty.
+async fn get_storage_value(
+    rpc_client: &RpcClient,
+    block_id: BlockId,
+    contract_address: Felt252,
+    key: Felt252,
+) -> Result<Felt252, ProviderError> {
+    match rpc_client.starknet_rpc().get_storage_at(contract_address, key, block_id).await {
+        Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(Felt252::ZERO),
+        result => result,
+    }
 }
 
 impl PerContractStorage for ProverPerContractStorage {
@@ -167,16 +179,12 @@ impl PerContractStorage for ProverPerContractStorage {
             self.storage_proof.contract_data.as_ref().expect("storage proof should have a contract_data field");
         let updated_root = contract_data.root;
 
-        let commitment_facts = format_commitment_facts::<PedersenHash>(&contract_data.storage_proofs);
-
-        let previous_commitment_facts = match &self.previous_storage_proof.contract_data {
-            None => HashMap::default(),
-            Some(previous_contract_data) => {
-                format_commitment_facts::<PedersenHash>(&previous_contract_data.storage_proofs)
-            }
-        };
-
-        let commitment_facts = commitment_facts.into_iter().chain(previous_commitment_facts.into_iter()).collect();
+        let previous_storage_proofs =
+            self.previous_storage_proof.contract_data.iter().flat_map(|contract_data| &contract_data.storage_proofs);
+        let commitment_facts = MultiProof::<PedersenHash>::from_proofs(
+            contract_data.storage_proofs.iter().chain(previous_storage_proofs).map(Vec::as_slice),
+        )
+        .commitment_facts();
 
         Ok(CommitmentInfo {
             previous_root: self.previous_tree_root,
@@ -187,28 +195,20 @@ impl PerContractStorage for ProverPerContractStorage {
     }
 
     async fn read(&mut self, key: TreeIndex) -> Option<Felt252> {
+        let key = Felt252::from(key);
         if let Some(value) = self.ongoing_storage_changes.get(&key) {
-            Some(*value)
-        } else {
-            let key_felt = Felt252::from(key.clone());
-            // TODO: this should be fallible
-            let value = match self
-                .rpc_client
-                .starknet_rpc()
-                .get_storage_at(self.contract_address, key_felt, self.block_id)
-                .await
-            {
-                Ok(value) => Ok(value),
-                Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(Felt252::ZERO),
-                Err(e) => Err(e),
-            }
-            .unwrap();
-            self.ongoing_storage_changes.insert(key, value);
-            Some(value)
+            return Some(*value);
         }
+
+        // A storage loaded from a bundle only knows the prefetched values
+        let (rpc_client, block_id) = self.node.as_ref()?;
+        // TODO: this should be fallible
+        let value = get_storage_value(rpc_client, *block_id, self.contract_address, key).await.unwrap();
+        self.ongoing_storage_changes.insert(key, value);
+        Some(value)
     }
 
     fn write(&mut self, key: TreeIndex, value: Felt252) {
-        self.ongoing_storage_changes.insert(key, value);
+        self.ongoing_storage_changes.insert(Felt252::from(key), value);
     }
 }
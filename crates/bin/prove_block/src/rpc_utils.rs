@@ -2,121 +2,314 @@ use std::collections::HashMap;
 
 use blockifier::transaction::objects::TransactionExecutionInfo;
 use cairo_vm::Felt252;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use num_bigint::BigInt;
-use rpc_client::pathfinder::client::ClientError;
 use rpc_client::pathfinder::proofs::{
-    ContractData, EdgePath, PathfinderClassProof, PathfinderProof, ProofVerificationError, TrieNode,
+    ContractData, EdgePath, MultiProof, PathfinderClassProof, PathfinderProof, ProofOutcome,
 };
 use rpc_client::RpcClient;
-use starknet::core::types::BlockWithTxs;
+use starknet::core::types::{BlockId, StarknetError};
+use starknet::providers::{Provider, ProviderError};
 use starknet_api::core::{ContractAddress, PatriciaKey};
-use starknet_api::state::StorageKey;
 use starknet_api::{contract_address, felt, patricia_key};
 use starknet_os::config::DEFAULT_STORAGE_TREE_HEIGHT;
+use starknet_os::crypto::pedersen::PedersenHash;
+use starknet_os::crypto::poseidon::PoseidonHash;
 use starknet_os::starkware_utils::commitment_tree::base_types::Height;
+use starknet_os::storage::storage::HashFunctionType;
 use starknet_types_core::felt::Felt;
 
+use crate::cache::ProveBlockCache;
 use crate::utils::get_all_accessed_keys;
+use crate::ProveBlockError;
 
-/// Fetches the state + storage proof for a single contract for all the specified keys.
-/// This function handles the chunking of requests imposed by the RPC API and merges
-/// the proofs returned from multiple calls into one.
-async fn fetch_storage_proof_for_contract(
-    rpc_client: &RpcClient,
-    contract_address: Felt,
-    keys: &[Felt],
-    block_number: u64,
-) -> Result<PathfinderProof, ClientError> {
-    let storage_proof = if keys.is_empty() {
-        rpc_client.pathfinder_rpc().get_proof(block_number, contract_address, &[]).await?
-    } else {
-        // The endpoint is limited to 100 keys at most per call
-        const MAX_KEYS: usize = 100;
-        let mut chunked_storage_proofs = Vec::new();
-        for keys_chunk in keys.chunks(MAX_KEYS) {
-            chunked_storage_proofs
-                .push(rpc_client.pathfinder_rpc().get_proof(block_number, contract_address, keys_chunk).await?);
-        }
-        merge_storage_proofs(chunked_storage_proofs)
-    };
-
-    Ok(storage_proof)
-}
+/// Maximum number of storage proof requests sent in a single JSON-RPC batch.
+const MAX_PROOFS_PER_BATCH: usize = 10;
 
-/// Fetches the storage proof for the specified contract and storage keys.
-/// This function can fetch additional keys if required to fill gaps in the storage trie
-/// that must be filled to get the OS to function. See `get_key_following_edge` for more details.
-async fn get_storage_proof_for_contract<KeyIter: Iterator<Item = StorageKey>>(
+/// Fetches the state + storage proofs for all the specified contracts and keys and stores them
+/// in the cache. Only the keys missing from the cache are fetched.
+/// This function handles the chunking of requests imposed by the RPC API. The chunks are grouped
+/// in batches of `MAX_PROOFS_PER_BATCH` requests, fetched concurrently with at most
+/// `max_concurrent_requests` batches in flight.
+async fn fetch_storage_proofs(
     rpc_client: &RpcClient,
-    contract_address: ContractAddress,
-    storage_keys: KeyIter,
+    cache: &ProveBlockCache,
     block_number: u64,
-) -> Result<PathfinderProof, ClientError> {
-    let contract_address_felt = *contract_address.key();
-    let keys: Vec<_> = storage_keys.map(|storage_key| *storage_key.key()).collect();
-
-    let mut storage_proof =
-        fetch_storage_proof_for_contract(rpc_client, contract_address_felt, &keys, block_number).await?;
+    keys_by_contract: &[(Felt, Vec<Felt>)],
+    max_concurrent_requests: usize,
+) -> Result<(), ProveBlockError> {
+    // The endpoint is limited to 100 keys at most per call
+    const MAX_KEYS: usize = 100;
 
-    let contract_data = match &storage_proof.contract_data {
-        None => {
-            return Ok(storage_proof);
+    let mut requests: Vec<(Felt, Vec<Felt>)> = vec![];
+    for (contract_address, keys) in keys_by_contract {
+        let missing_keys = cache.missing_storage_keys(block_number, *contract_address, keys);
+        if missing_keys.is_empty() && !cache.contains_contract_proof(block_number, *contract_address) {
+            requests.push((*contract_address, vec![]));
         }
-        Some(contract_data) => contract_data,
-    };
-    let additional_keys = verify_storage_proof(contract_data, &keys);
+        requests.extend(missing_keys.chunks(MAX_KEYS).map(|keys_chunk| (*contract_address, keys_chunk.to_vec())));
+    }
 
-    // Fetch additional proofs required to fill gaps in the storage trie that could make
-    // the OS crash otherwise.
-    if !additional_keys.is_empty() {
-        let additional_proof =
-            fetch_storage_proof_for_contract(rpc_client, contract_address_felt, &additional_keys, block_number).await?;
+    // Proofs are fetched in small batches: the responses are large
+    let storage_proofs: Vec<Vec<PathfinderProof>> = stream::iter(requests.chunks(MAX_PROOFS_PER_BATCH))
+        .map(|batch| async move {
+            let results = rpc_client.proof_rpc().get_proofs(block_number, batch).await.map_err(|source| {
+                let contract_addresses = batch.iter().map(|(contract_address, _)| *contract_address).collect();
+                ProveBlockError::StorageProofBatchFetchError { block_number, contract_addresses, source }
+            })?;
+            batch
+                .iter()
+                .zip(results)
+                .map(|((contract_address, _), result)| {
+                    result.map_err(|source| ProveBlockError::StorageProofFetchError {
+                        block_number,
+                        contract_address: *contract_address,
+                        source,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .buffered(max_concurrent_requests)
+        .try_collect()
+        .await?;
 
-        storage_proof = merge_storage_proofs(vec![storage_proof, additional_proof]);
+    for ((contract_address, keys), storage_proof) in requests.iter().zip(storage_proofs.into_iter().flatten()) {
+        cache.insert_storage_proof(block_number, *contract_address, keys, storage_proof);
     }
 
-    Ok(storage_proof)
+    Ok(())
 }
 
+/// Maximum number of extra fetch rounds done to fill the gaps of a trie.
+/// Each round goes at least one level deeper in the trie, this cap is only there to bail out
+/// if the node keeps returning proofs that do not cover the gaps.
+const MAX_GAP_FILLING_ITERATIONS: usize = 16;
+
 /// Verify the storage proofs and handle errors.
 /// Returns a list of additional keys to fetch to fill gaps in the tree that will make the OS
 /// crash otherwise.
-/// This function will panic if the proof contains an invalid node hash (i.e. the hash of a child
-/// node does not match the one specified in the parent).
-fn verify_storage_proof(contract_data: &ContractData, keys: &[Felt]) -> Vec<Felt> {
+/// This function will return an error if the proof contains an invalid node hash (i.e. the hash
+/// of a child node does not match the one specified in the parent).
+fn verify_storage_proof(contract_data: &ContractData, keys: &[Felt]) -> Result<Vec<Felt>, String> {
+    // All the nodes of the proofs, i.e. the preimage that will be given to the OS
+    let preimage = MultiProof::<PedersenHash>::from_proofs(contract_data.storage_proofs.iter().map(Vec::as_slice));
     let mut additional_keys = vec![];
-    if let Err(errors) = contract_data.verify(keys) {
-        for error in errors {
-            match error {
-                ProofVerificationError::NonExistenceProof { key, height, proof } => {
-                    if let Some(TrieNode::Edge { child: _, path }) = proof.last() {
-                        if height.0 < DEFAULT_STORAGE_TREE_HEIGHT {
-                            let modified_key = get_key_following_edge(key, height, path);
-                            tracing::trace!(
-                                "Fetching modified key {} for key {}",
-                                modified_key.to_hex_string(),
-                                key.to_hex_string()
-                            );
-                            additional_keys.push(modified_key);
-                        }
-                    }
-                }
-                _ => {
-                    panic!("Proof verification failed: {}", error);
-                }
+    for (key, outcome) in keys.iter().zip(contract_data.verify(keys)) {
+        additional_keys.extend(get_gap_filling_key(*key, outcome, &preimage)?);
+    }
+
+    Ok(additional_keys)
+}
+
+/// Handles the outcome of the verification of the proof of `key`.
+/// Returns the key to fetch to fill the gap left in the tree by a proof of non-membership,
+/// if the bottom node of the edge that diverges from `key` is not already part of the preimage.
+/// See `get_key_following_edge` for more details.
+/// This function will return an error if the proof is invalid.
+fn get_gap_filling_key<H: HashFunctionType>(
+    key: Felt,
+    outcome: ProofOutcome,
+    preimage: &MultiProof<H>,
+) -> Result<Option<Felt>, String> {
+    match outcome {
+        ProofOutcome::Member { .. } | ProofOutcome::NonMember { witness: None } => Ok(None),
+        ProofOutcome::NonMember { witness: Some(witness) } => {
+            if witness.height.0 < DEFAULT_STORAGE_TREE_HEIGHT && !preimage.contains(&witness.child) {
+                let modified_key = get_key_following_edge(key, witness.height, &witness.path);
+                tracing::trace!(
+                    "Fetching modified key {} for key {}",
+                    modified_key.to_hex_string(),
+                    key.to_hex_string()
+                );
+                return Ok(Some(modified_key));
+            }
+            Ok(None)
+        }
+        ProofOutcome::Invalid(error) => Err(error.to_string()),
+    }
+}
+
+/// Tries whose proofs can leave gaps that make the OS crash, see `fill_trie_gaps`.
+trait GapFilling {
+    /// Identifies a trie, ex: the address of the contract for storage tries.
+    type TrieId: Copy;
+
+    /// Fetches the proofs of the specified keys that are not cached yet.
+    async fn fetch_proofs(&self, keys_by_trie: &[(Self::TrieId, Vec<Felt>)]) -> Result<(), ProveBlockError>;
+
+    /// Verifies the proofs of `keys` and returns the keys to fetch to fill the gaps they leave.
+    fn gap_filling_keys(&self, trie_id: Self::TrieId, keys: &[Felt]) -> Result<Vec<Felt>, ProveBlockError>;
+
+    /// Error returned if the gaps are not filled after `MAX_GAP_FILLING_ITERATIONS` rounds.
+    fn gap_filling_error(&self, trie_id: Self::TrieId) -> ProveBlockError;
+
+    /// Name of the trie in the logs.
+    fn trie_name(&self, trie_id: Self::TrieId) -> String;
+}
+
+/// Fetches the proofs of `keys_by_trie`, then the proofs of the keys required to fill the gaps
+/// of the tries. The proofs of these additional keys can reveal new gaps, so the keys are fetched
+/// until the preimage covers all the paths, up to `MAX_GAP_FILLING_ITERATIONS` rounds.
+/// The additional keys are added to `keys_by_trie`.
+async fn fill_trie_gaps<T: GapFilling>(
+    tries: &T,
+    keys_by_trie: &mut [(T::TrieId, Vec<Felt>)],
+) -> Result<(), ProveBlockError> {
+    tries.fetch_proofs(keys_by_trie).await?;
+
+    for iteration in 0.. {
+        let mut needs_additional_keys = false;
+        for (trie_id, keys) in keys_by_trie.iter_mut() {
+            let mut additional_keys = tries.gap_filling_keys(*trie_id, keys)?;
+            additional_keys.sort();
+            additional_keys.dedup();
+            additional_keys.retain(|key| !keys.contains(key));
+            if additional_keys.is_empty() {
+                continue;
+            }
+
+            if iteration == MAX_GAP_FILLING_ITERATIONS {
+                return Err(tries.gap_filling_error(*trie_id));
             }
+
+            tracing::info!(
+                "Fetching {} extra keys to fill the gaps of the {} (round {}): {}",
+                additional_keys.len(),
+                tries.trie_name(*trie_id),
+                iteration + 1,
+                additional_keys.iter().map(|key| key.to_hex_string()).collect::<Vec<_>>().join(", ")
+            );
+            needs_additional_keys = true;
+            keys.extend(additional_keys);
+        }
+
+        if !needs_additional_keys {
+            break;
+        }
+        tries.fetch_proofs(keys_by_trie).await?;
+    }
+
+    Ok(())
+}
+
+/// The storage tries of the contracts at a given block.
+struct StorageTries<'a> {
+    rpc_client: &'a RpcClient,
+    cache: &'a ProveBlockCache,
+    block_number: u64,
+    max_concurrent_requests: usize,
+}
+
+impl GapFilling for StorageTries<'_> {
+    type TrieId = Felt;
+
+    async fn fetch_proofs(&self, keys_by_contract: &[(Felt, Vec<Felt>)]) -> Result<(), ProveBlockError> {
+        fetch_storage_proofs(
+            self.rpc_client,
+            self.cache,
+            self.block_number,
+            keys_by_contract,
+            self.max_concurrent_requests,
+        )
+        .await
+    }
+
+    fn gap_filling_keys(&self, contract_address: Felt, keys: &[Felt]) -> Result<Vec<Felt>, ProveBlockError> {
+        let storage_proof = get_cached_storage_proof(self.cache, self.block_number, contract_address, keys)?;
+        let Some(contract_data) = &storage_proof.contract_data else {
+            return Ok(vec![]);
+        };
+
+        verify_storage_proof(contract_data, keys).map_err(|reason| ProveBlockError::StorageProofVerificationError {
+            block_number: self.block_number,
+            contract_address,
+            reason,
+        })
+    }
+
+    fn gap_filling_error(&self, contract_address: Felt) -> ProveBlockError {
+        ProveBlockError::StorageTrieGapFillingError {
+            block_number: self.block_number,
+            contract_address,
+            iterations: MAX_GAP_FILLING_ITERATIONS,
         }
     }
 
-    additional_keys
+    fn trie_name(&self, contract_address: Felt) -> String {
+        format!("storage trie of contract 0x{:x} at block {}", contract_address, self.block_number)
+    }
+}
+
+/// The class trie at a given block.
+struct ClassTrie<'a> {
+    rpc_client: &'a RpcClient,
+    cache: &'a ProveBlockCache,
+    block_number: u64,
+    max_concurrent_requests: usize,
 }
 
+impl GapFilling for ClassTrie<'_> {
+    type TrieId = ();
+
+    async fn fetch_proofs(&self, keys_by_trie: &[((), Vec<Felt>)]) -> Result<(), ProveBlockError> {
+        for (_, class_hashes) in keys_by_trie {
+            fetch_class_proofs(
+                self.rpc_client,
+                self.cache,
+                self.block_number,
+                class_hashes,
+                self.max_concurrent_requests,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    fn gap_filling_keys(&self, _: (), class_hashes: &[Felt]) -> Result<Vec<Felt>, ProveBlockError> {
+        let proofs = class_hashes
+            .iter()
+            .map(|class_hash| get_cached_class_proof(self.cache, self.block_number, *class_hash))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let preimage = MultiProof::<PoseidonHash>::from_proofs(proofs.iter().map(|proof| proof.class_proof.as_slice()));
+        let mut additional_class_hashes = vec![];
+        for (class_hash, proof) in class_hashes.iter().zip(&proofs) {
+            let additional_class_hash = get_gap_filling_key(*class_hash, proof.verify(*class_hash), &preimage)
+                .map_err(|reason| ProveBlockError::ClassProofVerificationError {
+                    block_number: self.block_number,
+                    class_hash: *class_hash,
+                    reason,
+                })?;
+            additional_class_hashes.extend(additional_class_hash);
+        }
+
+        Ok(additional_class_hashes)
+    }
+
+    fn gap_filling_error(&self, _: ()) -> ProveBlockError {
+        ProveBlockError::ClassTrieGapFillingError {
+            block_number: self.block_number,
+            iterations: MAX_GAP_FILLING_ITERATIONS,
+        }
+    }
+
+    fn trie_name(&self, _: ()) -> String {
+        format!("class trie at block {}", self.block_number)
+    }
+}
+
+/// Fetches the storage proofs of all the contracts and keys accessed by the transactions.
+/// This function can fetch additional keys if required to fill gaps in the storage trie
+/// that must be filled to get the OS to function. See `fill_trie_gaps` and
+/// `get_key_following_edge` for more details.
 pub(crate) async fn get_storage_proofs(
     client: &RpcClient,
+    cache: &ProveBlockCache,
     block_number: u64,
     tx_execution_infos: &[TransactionExecutionInfo],
     old_block_number: Felt,
-) -> Result<HashMap<Felt, PathfinderProof>, ClientError> {
+    max_concurrent_requests: usize,
+) -> Result<HashMap<Felt, PathfinderProof>, ProveBlockError> {
     let accessed_keys_by_address = {
         let mut keys = get_all_accessed_keys(tx_execution_infos);
         // We need to fetch the storage proof for the block hash contract
@@ -124,20 +317,47 @@ pub(crate) async fn get_storage_proofs(
         keys
     };
 
-    let mut storage_proofs = HashMap::new();
+    // Sort contracts and keys to issue the same requests from one run to another
+    let mut keys_by_contract: Vec<(Felt, Vec<Felt>)> = accessed_keys_by_address
+        .into_iter()
+        .map(|(contract_address, storage_keys)| {
+            let mut keys: Vec<_> = storage_keys.into_iter().map(|storage_key| *storage_key.key()).collect();
+            keys.sort();
+            (*contract_address.key(), keys)
+        })
+        .collect();
+    keys_by_contract.sort_by_key(|(contract_address, _)| *contract_address);
 
     tracing::info!("Contracts we're fetching proofs for:");
-    for (contract_address, storage_keys) in accessed_keys_by_address {
-        tracing::info!("    Fetching proof for {}", contract_address.to_string());
-        let contract_address_felt = *contract_address.key();
-        let storage_proof =
-            get_storage_proof_for_contract(client, contract_address, storage_keys.into_iter(), block_number).await?;
-        storage_proofs.insert(contract_address_felt, storage_proof);
+    for (contract_address, _) in &keys_by_contract {
+        tracing::info!("    Fetching proof for 0x{:x}", contract_address);
+    }
+
+    // Also fetch the additional proofs required to fill gaps in the storage trie that could make
+    // the OS crash otherwise, until no gap is left.
+    let storage_tries = StorageTries { rpc_client: client, cache, block_number, max_concurrent_requests };
+    fill_trie_gaps(&storage_tries, &mut keys_by_contract).await?;
+
+    let mut storage_proofs = HashMap::with_capacity(keys_by_contract.len());
+    for (contract_address, keys) in &keys_by_contract {
+        let storage_proof = get_cached_storage_proof(cache, block_number, *contract_address, keys)?;
+        storage_proofs.insert(*contract_address, storage_proof);
     }
 
     Ok(storage_proofs)
 }
 
+fn get_cached_storage_proof(
+    cache: &ProveBlockCache,
+    block_number: u64,
+    contract_address: Felt,
+    keys: &[Felt],
+) -> Result<PathfinderProof, ProveBlockError> {
+    cache
+        .get_storage_proof(block_number, contract_address, keys)
+        .ok_or(ProveBlockError::MissingStorageProof { block_number, contract_address })
+}
+
 /// Returns a modified key that follows the specified edge path.
 /// This function is used to work around an issue where the OS fails if it encounters a
 /// write to 0 and the last node in the storage proof is an edge node of length 1.
@@ -168,61 +388,133 @@ fn get_key_following_edge(key: Felt, height: Height, edge_path: &EdgePath) -> Fe
     Felt::from(new_key)
 }
 
-fn merge_storage_proofs(proofs: Vec<PathfinderProof>) -> PathfinderProof {
-    let class_commitment = proofs[0].class_commitment;
-    let state_commitment = proofs[0].state_commitment;
-    let contract_proof = proofs[0].contract_proof.clone();
-
-    let contract_data = {
-        let mut contract_data: Option<ContractData> = None;
-
-        for proof in proofs {
-            if let Some(data) = proof.contract_data {
-                if let Some(contract_data) = contract_data.as_mut() {
-                    contract_data.storage_proofs.extend(data.storage_proofs);
-                } else {
-                    contract_data = Some(data);
-                }
-            }
-        }
+/// Fetches the class proofs of the specified classes and stores them in the cache.
+/// Only the proofs missing from the cache are fetched, in batches of `MAX_PROOFS_PER_BATCH`
+/// classes with at most `max_concurrent_requests` batches in flight.
+async fn fetch_class_proofs(
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    block_number: u64,
+    class_hashes: &[Felt],
+    max_concurrent_requests: usize,
+) -> Result<(), ProveBlockError> {
+    let missing_class_hashes: Vec<Felt> = class_hashes
+        .iter()
+        .filter(|class_hash| cache.get_class_proof(block_number, class_hash).is_none())
+        .copied()
+        .collect();
 
-        contract_data
-    };
+    let fetched_proofs: Vec<Vec<PathfinderClassProof>> =
+        stream::iter(missing_class_hashes.chunks(MAX_PROOFS_PER_BATCH))
+            .map(|batch| async move {
+                let results = rpc_client.proof_rpc().get_class_proofs(block_number, batch).await.map_err(|source| {
+                    ProveBlockError::ClassProofBatchFetchError { block_number, class_hashes: batch.to_vec(), source }
+                })?;
+                batch
+                    .iter()
+                    .zip(results)
+                    .map(|(class_hash, result)| {
+                        result.map_err(|source| ProveBlockError::ClassProofFetchError {
+                            block_number,
+                            class_hash: *class_hash,
+                            source,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .buffered(max_concurrent_requests)
+            .try_collect()
+            .await?;
+
+    for (class_hash, proof) in missing_class_hashes.into_iter().zip(fetched_proofs.into_iter().flatten()) {
+        cache.insert_class_proof(block_number, class_hash, proof);
+    }
 
-    PathfinderProof { class_commitment, state_commitment, contract_proof, contract_data }
+    Ok(())
 }
 
+fn get_cached_class_proof(
+    cache: &ProveBlockCache,
+    block_number: u64,
+    class_hash: Felt,
+) -> Result<PathfinderClassProof, ProveBlockError> {
+    cache
+        .get_class_proof(block_number, &class_hash)
+        .ok_or(ProveBlockError::MissingClassProof { block_number, class_hash })
+}
+
+/// Fetches the class proofs of the specified classes.
+///
+/// Classes declared in the block are not part of the class trie of the previous block, their
+/// proofs at that block are proofs of non-membership. As for storage proofs, this function
+/// fetches additional proofs if required to fill gaps in the class trie that must be filled
+/// to get the OS to function. See `fill_trie_gaps` and `get_key_following_edge` for more details.
+/// The returned map contains the proofs of the requested classes and of these additional keys.
 pub(crate) async fn get_class_proofs(
     rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
     block_number: u64,
-    class_hashes: &[&Felt],
-) -> Result<HashMap<Felt252, PathfinderClassProof>, ClientError> {
+    class_hashes: &[Felt],
+    max_concurrent_requests: usize,
+) -> Result<HashMap<Felt252, PathfinderClassProof>, ProveBlockError> {
+    let class_trie = ClassTrie { rpc_client, cache, block_number, max_concurrent_requests };
+    let mut keys_by_trie = [((), class_hashes.to_vec())];
+    fill_trie_gaps(&class_trie, &mut keys_by_trie).await?;
+
+    let [(_, class_hashes)] = keys_by_trie;
     let mut proofs: HashMap<Felt252, PathfinderClassProof> = HashMap::with_capacity(class_hashes.len());
     for class_hash in class_hashes {
-        let proof = rpc_client.pathfinder_rpc().get_class_proof(block_number, class_hash).await?;
-        // TODO: need to combine these, similar to merge_chunked_storage_proofs above?
-        proofs.insert(**class_hash, proof);
+        proofs.insert(class_hash, get_cached_class_proof(cache, block_number, class_hash)?);
     }
 
     Ok(proofs)
 }
 
-pub(crate) fn get_starknet_version(block_with_txs: &BlockWithTxs) -> blockifier::versioned_constants::StarknetVersion {
-    let starknet_version_str = &block_with_txs.starknet_version;
-    match starknet_version_str.as_ref() {
-        "0.13.0" => blockifier::versioned_constants::StarknetVersion::V0_13_0,
-        "0.13.1" => blockifier::versioned_constants::StarknetVersion::V0_13_1,
-        "0.13.1.1" => blockifier::versioned_constants::StarknetVersion::V0_13_1_1,
-        "0.13.2" => blockifier::versioned_constants::StarknetVersion::V0_13_2,
-        "0.13.2.1" => blockifier::versioned_constants::StarknetVersion::Latest,
-        other => {
-            unimplemented!("Unsupported Starknet version: {}", other)
-        }
-    }
+/// Class hash and nonce of a contract before and after the block.
+pub(crate) struct ContractClassAndNonce {
+    pub previous_class_hash: Felt,
+    pub previous_nonce: Felt,
+    pub class_hash: Felt,
+}
+
+/// Fetches the class hash and nonce of the specified contracts at the previous block and the
+/// class hash at the current block, with at most `max_concurrent_requests` contracts
+/// processed at a time. Contracts that do not exist at the previous block get zero values.
+/// Results are returned in the same order as `contract_addresses`.
+pub(crate) async fn get_contract_classes_and_nonces(
+    rpc_client: &RpcClient,
+    previous_block_id: BlockId,
+    block_id: BlockId,
+    contract_addresses: &[Felt],
+    max_concurrent_requests: usize,
+) -> Result<Vec<ContractClassAndNonce>, ProveBlockError> {
+    let or_zero_if_not_found = |result: Result<Felt, ProviderError>| match result {
+        Ok(value) => Ok(value),
+        Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(Felt::ZERO),
+        Err(e) => Err(e),
+    };
+
+    stream::iter(contract_addresses)
+        .map(|contract_address| async move {
+            let previous_class_hash = or_zero_if_not_found(
+                rpc_client.starknet_rpc().get_class_hash_at(previous_block_id, *contract_address).await,
+            )?;
+            let previous_nonce =
+                or_zero_if_not_found(rpc_client.starknet_rpc().get_nonce(previous_block_id, *contract_address).await)?;
+            let class_hash = rpc_client.starknet_rpc().get_class_hash_at(block_id, *contract_address).await?;
+
+            Ok::<_, ProveBlockError>(ContractClassAndNonce { previous_class_hash, previous_nonce, class_hash })
+        })
+        .buffered(max_concurrent_requests)
+        .try_collect()
+        .await
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
+    use rpc_client::pathfinder::proofs::{EdgeWitness, ProofVerificationError, TrieNode};
     use rstest::rstest;
 
     use super::*;
@@ -255,4 +547,92 @@ mod tests {
         let modified_key = get_key_following_edge(key, height, &edge_path);
         assert_eq!(modified_key, expected_key);
     }
+
+    #[test]
+    fn test_gap_filling_key() {
+        let key = Felt::from_hex_unchecked("0x00A0");
+        let bottom_node = TrieNode::Binary { left: Felt::ONE, right: Felt::TWO };
+        let non_member = |height| ProofOutcome::NonMember {
+            witness: Some(EdgeWitness {
+                child: bottom_node.hash::<PedersenHash>(),
+                path: EdgePath { len: 4, value: Felt::from(0xB) },
+                height,
+            }),
+        };
+        let empty_preimage = MultiProof::<PedersenHash>::new();
+
+        assert_eq!(
+            get_gap_filling_key(key, non_member(Height(4)), &empty_preimage),
+            Ok(Some(Felt::from_hex_unchecked("0x00B0")))
+        );
+
+        // No gap to fill if the bottom node of the edge is already part of the preimage
+        let preimage = MultiProof::<PedersenHash>::from_proofs([[bottom_node.clone()].as_slice()]);
+        assert_eq!(get_gap_filling_key(key, non_member(Height(4)), &preimage), Ok(None));
+
+        // No gap to fill if the edge goes all the way down from the root
+        assert_eq!(
+            get_gap_filling_key(key, non_member(Height(DEFAULT_STORAGE_TREE_HEIGHT)), &empty_preimage),
+            Ok(None)
+        );
+
+        // Nor if the key is in the trie
+        assert_eq!(get_gap_filling_key(key, ProofOutcome::Member { leaf_value: Felt::ONE }, &empty_preimage), Ok(None));
+
+        let invalid_proof = ProofOutcome::Invalid(ProofVerificationError::InvalidChildNodeHash {
+            node_hash: Felt::ONE,
+            parent_hash: Felt::TWO,
+        });
+        assert!(get_gap_filling_key(key, invalid_proof, &empty_preimage).is_err());
+    }
+
+    /// Trie in which the proof of a key leaves a gap that is filled by the proof of another key.
+    struct ChainedGaps {
+        gaps: HashMap<Felt, Felt>,
+        fetched_keys: Mutex<Vec<Vec<Felt>>>,
+    }
+
+    impl GapFilling for ChainedGaps {
+        type TrieId = ();
+
+        async fn fetch_proofs(&self, keys_by_trie: &[((), Vec<Felt>)]) -> Result<(), ProveBlockError> {
+            self.fetched_keys.lock().unwrap().push(keys_by_trie[0].1.clone());
+            Ok(())
+        }
+
+        fn gap_filling_keys(&self, _: (), keys: &[Felt]) -> Result<Vec<Felt>, ProveBlockError> {
+            Ok(keys.iter().filter_map(|key| self.gaps.get(key).copied()).collect())
+        }
+
+        fn gap_filling_error(&self, _: ()) -> ProveBlockError {
+            ProveBlockError::ClassTrieGapFillingError { block_number: 1, iterations: MAX_GAP_FILLING_ITERATIONS }
+        }
+
+        fn trie_name(&self, _: ()) -> String {
+            "test trie".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fill_trie_gaps() {
+        // The gap-filling key of 0x1 leaves a gap of its own, filled by 0x3
+        let tries = ChainedGaps {
+            gaps: HashMap::from([(Felt::ONE, Felt::TWO), (Felt::TWO, Felt::THREE)]),
+            fetched_keys: Mutex::default(),
+        };
+        let mut keys_by_trie = [((), vec![Felt::ONE])];
+        fill_trie_gaps(&tries, &mut keys_by_trie).await.unwrap();
+        assert_eq!(keys_by_trie[0].1, vec![Felt::ONE, Felt::TWO, Felt::THREE]);
+        assert_eq!(
+            tries.fetched_keys.into_inner().unwrap(),
+            vec![vec![Felt::ONE], vec![Felt::ONE, Felt::TWO], vec![Felt::ONE, Felt::TWO, Felt::THREE]]
+        );
+
+        // Gaps that keep appearing are reported after `MAX_GAP_FILLING_ITERATIONS` rounds
+        let gaps = (0..=MAX_GAP_FILLING_ITERATIONS as u64).map(|i| (Felt::from(i), Felt::from(i + 1))).collect();
+        let tries = ChainedGaps { gaps, fetched_keys: Mutex::default() };
+        let mut keys_by_trie = [((), vec![Felt::ZERO])];
+        let result = fill_trie_gaps(&tries, &mut keys_by_trie).await;
+        assert!(matches!(result, Err(ProveBlockError::ClassTrieGapFillingError { .. })), "{result:?}");
+    }
 }
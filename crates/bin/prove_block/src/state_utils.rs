@@ -2,16 +2,19 @@ use std::collections::{HashMap, HashSet};
 
 use cairo_vm::Felt252;
 use rpc_client::RpcClient;
-use starknet::core::types::{BlockId, MaybePendingStateUpdate, StarknetError, StateDiff, TransactionTraceWithHash};
+use rpc_replay::class_cache::{CachedClass, ClassCacheError};
+use rpc_replay::utils::get_subcalled_contracts_from_tx_traces;
+use starknet::core::types::{
+    BlockId, MaybePendingStateUpdate, StarknetError, StateDiff, StateUpdate, TransactionTraceWithHash,
+};
 use starknet::providers::{Provider, ProviderError};
 use starknet_os_types::casm_contract_class::GenericCasmContractClass;
 use starknet_os_types::class_hash_utils::ContractClassComponentHashes;
 use starknet_os_types::compiled_class::GenericCompiledClass;
 use starknet_os_types::deprecated_compiled_class::GenericDeprecatedCompiledClass;
-use starknet_os_types::sierra_contract_class::GenericSierraContractClass;
 use starknet_types_core::felt::Felt;
 
-use crate::utils::get_subcalled_contracts_from_tx_traces;
+use crate::cache::ProveBlockCache;
 use crate::ProveBlockError;
 
 #[derive(Clone)]
@@ -21,6 +24,12 @@ pub struct FormattedStateUpdate {
     pub compiled_classes: HashMap<Felt252, GenericCasmContractClass>,
     pub deprecated_compiled_classes: HashMap<Felt252, GenericDeprecatedCompiledClass>,
     pub declared_class_hash_component_hashes: HashMap<Felt252, ContractClassComponentHashes>,
+    /// State diff of the block, as returned by the node.
+    pub state_diff: StateDiff,
+    /// Global state root before the block.
+    pub old_root: Felt252,
+    /// Global state root after the block.
+    pub new_root: Felt252,
 }
 
 /// Given the `block_id` of the target block to prove, it:
@@ -30,79 +39,66 @@ pub struct FormattedStateUpdate {
 /// - Consolidates that information into a `FormattedStateUpdate`.
 pub(crate) async fn get_formatted_state_update(
     rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
     previous_block_id: BlockId,
     block_id: BlockId,
 ) -> Result<(FormattedStateUpdate, Vec<TransactionTraceWithHash>), ProveBlockError> {
-    let state_update =
-        match rpc_client.starknet_rpc().get_state_update(block_id).await.expect("Failed to get state update") {
-            MaybePendingStateUpdate::Update(update) => update,
-            MaybePendingStateUpdate::PendingUpdate(_) => {
-                panic!("Block is still pending!")
-            }
-        };
-    let state_diff = state_update.state_diff;
-
+    let state_update = match rpc_client.starknet_rpc().get_state_update(block_id).await? {
+        MaybePendingStateUpdate::Update(update) => update,
+        MaybePendingStateUpdate::PendingUpdate(_) => {
+            return Err(ProveBlockError::PendingBlock { block_id });
+        }
+    };
     // Extract other contracts used in our block from the block trace
     // We need this to get all the class hashes used and correctly feed address_to_class_hash
-    let traces =
-        rpc_client.starknet_rpc().trace_block_transactions(block_id).await.expect("Failed to get block tx traces");
+    let traces = rpc_client.starknet_rpc().trace_block_transactions(block_id).await?;
     let (accessed_addresses, accessed_classes) = get_subcalled_contracts_from_tx_traces(&traces);
 
-    let declared_classes: HashSet<_> =
-        state_diff.declared_classes.iter().map(|declared_item| declared_item.class_hash).collect();
-
     // TODO: Handle deprecated classes
-    let mut class_hash_to_compiled_class_hash: HashMap<Felt252, Felt252> = HashMap::new();
-    let (compiled_contract_classes, deprecated_compiled_contract_classes, declared_class_hash_component_hashes) =
-        build_compiled_class_and_maybe_update_class_hash_to_compiled_class_hash(
-            rpc_client,
-            previous_block_id,
-            block_id,
-            &accessed_addresses,
-            &declared_classes,
-            &accessed_classes,
-            &mut class_hash_to_compiled_class_hash,
-        )
-        .await?;
-
-    // OS will expect a Zero in compiled_class_hash for new classes. Overwrite the needed entries.
-    format_declared_classes(&state_diff, &mut class_hash_to_compiled_class_hash);
+    let formatted_state_update = build_compiled_class_and_maybe_update_class_hash_to_compiled_class_hash(
+        rpc_client,
+        cache,
+        previous_block_id,
+        block_id,
+        &accessed_addresses,
+        &accessed_classes,
+        state_update,
+    )
+    .await?;
 
-    Ok((
-        FormattedStateUpdate {
-            class_hash_to_compiled_class_hash,
-            compiled_classes: compiled_contract_classes,
-            deprecated_compiled_classes: deprecated_compiled_contract_classes,
-            declared_class_hash_component_hashes,
-        },
-        traces,
-    ))
+    Ok((formatted_state_update, traces))
 }
 
-/// Retrieves the compiled class for the given class hash at a specific block
-/// by getting the class from the RPC and compiling it to CASM if necessary (Cairo 1).
-fn compile_contract_class(
-    contract_class: starknet::core::types::ContractClass,
+/// Returns the compiled class for the specified class hash, from the cache if possible.
+/// Classes are immutable, so a class compiled for a previous block can be reused as is.
+async fn get_compiled_class(
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    block_id: BlockId,
+    class_hash: Felt,
 ) -> Result<GenericCompiledClass, ProveBlockError> {
-    let compiled_class = match contract_class {
-        starknet::core::types::ContractClass::Sierra(flattened_sierra_cc) => {
-            let sierra_class = GenericSierraContractClass::from(flattened_sierra_cc);
-            let compiled_class = sierra_class.compile()?;
-            GenericCompiledClass::Cairo1(compiled_class)
-        }
-        starknet::core::types::ContractClass::Legacy(legacy_cc) => {
-            let compiled_class = GenericDeprecatedCompiledClass::try_from(legacy_cc)?;
-            GenericCompiledClass::Cairo0(compiled_class)
-        }
-    };
+    Ok(get_class(rpc_client, cache, block_id, class_hash).await?.compiled_class)
+}
 
-    Ok(compiled_class)
+/// Same as `get_compiled_class`, also returns the data of the Sierra class kept by the cache.
+async fn get_class(
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    block_id: BlockId,
+    class_hash: Felt,
+) -> Result<CachedClass, ProveBlockError> {
+    cache.class_cache().get_class(rpc_client, block_id, class_hash).await.map_err(|e| match e {
+        ClassCacheError::RpcError(e) => ProveBlockError::RpcError(e),
+        ClassCacheError::ContractClassError(e) => ProveBlockError::ContractClassError(e),
+        ClassCacheError::LegacyContractDecompressionError(e) => ProveBlockError::LegacyContractDecompressionError(e),
+    })
 }
 
 /// Fetches (+ compile) the contract class for the specified contract at the specified block
 /// and adds it to the hashmaps that will then be added to the OS input.
 async fn add_compiled_class_from_contract_to_os_input(
     rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
     contract_address: Felt,
     block_id: BlockId,
     class_hash_to_compiled_class_hash: &mut HashMap<Felt252, Felt252>,
@@ -110,22 +106,26 @@ async fn add_compiled_class_from_contract_to_os_input(
     deprecated_compiled_contract_classes: &mut HashMap<Felt, GenericDeprecatedCompiledClass>,
 ) -> Result<(), ProveBlockError> {
     let class_hash = rpc_client.starknet_rpc().get_class_hash_at(block_id, contract_address).await?;
-    let contract_class = rpc_client.starknet_rpc().get_class(block_id, class_hash).await?;
 
     add_compiled_class_to_os_input(
+        rpc_client,
+        cache,
+        block_id,
         class_hash,
-        contract_class,
         class_hash_to_compiled_class_hash,
         compiled_contract_classes,
         deprecated_compiled_contract_classes,
     )
+    .await
 }
 
 /// Fetches (+ compile) the contract class for the specified class at the specified block
 /// and adds it to the hashmaps that will then be added to the OS input.
-fn add_compiled_class_to_os_input(
+async fn add_compiled_class_to_os_input(
+    rpc_client: &RpcClient,
+    cache: &ProveBlockCache,
+    block_id: BlockId,
     class_hash: Felt,
-    contract_class: starknet::core::types::ContractClass,
     class_hash_to_compiled_class_hash: &mut HashMap<Felt252, Felt252>,
     compiled_contract_classes: &mut HashMap<Felt, GenericCasmContractClass>,
     deprecated_compiled_contract_classes: &mut HashMap<Felt, GenericDeprecatedCompiledClass>,
@@ -135,7 +135,7 @@ fn add_compiled_class_to_os_input(
         return Ok(());
     }
 
-    let compiled_class = compile_contract_class(contract_class)?;
+    let compiled_class = get_compiled_class(rpc_client, cache, block_id, class_hash).await?;
     let compiled_class_hash = compiled_class.class_hash()?;
 
     // Remove deprecated classes from HashMap
@@ -162,24 +162,20 @@ fn add_compiled_class_to_os_input(
 /// If the class is already present in `address_to_class_hash`, it is used directly;
 /// otherwise, it is fetched from the provided `JsonRpcClient`.
 ///
-/// The resulting compiled classes and any associated mappings are returned, while
-/// the `class_hash_to_compiled_class_hash` map is updated with new entries.
+/// The resulting compiled classes and any associated mappings are returned, along with
+/// the `class_hash_to_compiled_class_hash` map of all the classes and the classes declared
+/// in the state diff of `state_update`.
 async fn build_compiled_class_and_maybe_update_class_hash_to_compiled_class_hash(
     provider: &RpcClient,
+    cache: &ProveBlockCache,
     previous_block_id: BlockId,
     block_id: BlockId,
     accessed_addresses: &HashSet<Felt252>,
-    declared_classes: &HashSet<Felt252>,
     accessed_classes: &HashSet<Felt252>,
-    class_hash_to_compiled_class_hash: &mut HashMap<Felt252, Felt252>,
-) -> Result<
-    (
-        HashMap<Felt252, GenericCasmContractClass>,
-        HashMap<Felt252, GenericDeprecatedCompiledClass>,
-        HashMap<Felt252, ContractClassComponentHashes>,
-    ),
-    ProveBlockError,
-> {
+    state_update: StateUpdate,
+) -> Result<FormattedStateUpdate, ProveBlockError> {
+    let state_diff = state_update.state_diff;
+    let mut class_hash_to_compiled_class_hash: HashMap<Felt252, Felt252> = HashMap::new();
     let mut compiled_contract_classes: HashMap<Felt252, GenericCasmContractClass> = HashMap::new();
     let mut deprecated_compiled_contract_classes: HashMap<Felt252, GenericDeprecatedCompiledClass> = HashMap::new();
 
@@ -190,9 +186,10 @@ async fn build_compiled_class_and_maybe_update_class_hash_to_compiled_class_hash
         // block, so we can ignore "ContractNotFound" failures.
         if let Err(e) = add_compiled_class_from_contract_to_os_input(
             provider,
+            cache,
             *contract_address,
             previous_block_id,
-            class_hash_to_compiled_class_hash,
+            &mut class_hash_to_compiled_class_hash,
             &mut compiled_contract_classes,
             &mut deprecated_compiled_contract_classes,
         )
@@ -208,9 +205,10 @@ async fn build_compiled_class_and_maybe_update_class_hash_to_compiled_class_hash
 
         add_compiled_class_from_contract_to_os_input(
             provider,
+            cache,
             *contract_address,
             block_id,
-            class_hash_to_compiled_class_hash,
+            &mut class_hash_to_compiled_class_hash,
             &mut compiled_contract_classes,
             &mut deprecated_compiled_contract_classes,
         )
@@ -218,26 +216,38 @@ async fn build_compiled_class_and_maybe_update_class_hash_to_compiled_class_hash
     }
 
     for class_hash in accessed_classes {
-        let contract_class = provider.starknet_rpc().get_class(block_id, class_hash).await?;
         add_compiled_class_to_os_input(
+            provider,
+            cache,
+            block_id,
             *class_hash,
-            contract_class,
-            class_hash_to_compiled_class_hash,
+            &mut class_hash_to_compiled_class_hash,
             &mut compiled_contract_classes,
             &mut deprecated_compiled_contract_classes,
-        )?;
+        )
+        .await?;
     }
 
     let mut declared_class_hash_to_component_hashes = HashMap::new();
-    for class_hash in declared_classes {
-        let contract_class = provider.starknet_rpc().get_class(block_id, class_hash).await?;
-        if let starknet::core::types::ContractClass::Sierra(flattened_sierra_class) = &contract_class {
-            let component_hashes = ContractClassComponentHashes::from(flattened_sierra_class.clone());
-            declared_class_hash_to_component_hashes.insert(*class_hash, component_hashes);
+    for declared_item in &state_diff.declared_classes {
+        let class_hash = declared_item.class_hash;
+        if let Some(component_hashes) = get_class(provider, cache, block_id, class_hash).await?.component_hashes {
+            declared_class_hash_to_component_hashes.insert(class_hash, component_hashes);
         }
     }
 
-    Ok((compiled_contract_classes, deprecated_compiled_contract_classes, declared_class_hash_to_component_hashes))
+    // OS will expect a Zero in compiled_class_hash for new classes. Overwrite the needed entries.
+    format_declared_classes(&state_diff, &mut class_hash_to_compiled_class_hash);
+
+    Ok(FormattedStateUpdate {
+        class_hash_to_compiled_class_hash,
+        compiled_classes: compiled_contract_classes,
+        deprecated_compiled_classes: deprecated_compiled_contract_classes,
+        declared_class_hash_component_hashes: declared_class_hash_to_component_hashes,
+        state_diff,
+        old_root: state_update.old_root,
+        new_root: state_update.new_root,
+    })
 }
 
 fn format_declared_classes(state_diff: &StateDiff, class_hash_to_compiled_class_hash: &mut HashMap<Felt252, Felt252>) {
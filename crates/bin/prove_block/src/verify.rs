@@ -0,0 +1,494 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use blockifier::execution::call_info::CallInfo;
+use blockifier::state::cached_state::{CachedState, CommitmentStateDiff};
+use blockifier::state::state_api::StateReader;
+use blockifier::transaction::objects::TransactionExecutionInfo;
+use starknet::core::types::{
+    Event, ExecutionResult, FeePayment, MsgToL1, StateDiff, TransactionReceipt, TransactionTraceWithHash,
+};
+use starknet_types_core::felt::Felt;
+
+use crate::ProveBlockError;
+
+/// Difference between the re-execution of a block with blockifier and the data returned by the node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// Value of a storage cell after the block, `None` if the cell is not part of the state diff.
+    Storage {
+        contract_address: Felt,
+        key: Felt,
+        expected: Option<Felt>,
+        actual: Option<Felt>,
+    },
+    Nonce {
+        contract_address: Felt,
+        expected: Option<Felt>,
+        actual: Option<Felt>,
+    },
+    ClassHash {
+        contract_address: Felt,
+        expected: Option<Felt>,
+        actual: Option<Felt>,
+    },
+    /// Compiled class hash of a class declared in the block.
+    DeclaredClass {
+        class_hash: Felt,
+        expected: Option<Felt>,
+        actual: Option<Felt>,
+    },
+    /// The node returned a number of traces or receipts different from the number of transactions
+    /// executed by blockifier. Only the first transactions are compared.
+    TransactionCount {
+        n_traces: usize,
+        n_receipts: usize,
+        n_executed: usize,
+    },
+    Fee {
+        expected: Felt,
+        actual: Felt,
+    },
+    RevertStatus {
+        expected: bool,
+        actual: bool,
+    },
+    Events {
+        n_expected: usize,
+        n_actual: usize,
+    },
+    Messages {
+        n_expected: usize,
+        n_actual: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the transaction that caused the divergence in the block, if it could be identified.
+    /// State diff divergences are attributed to the last transaction that accessed the value.
+    pub tx_index: Option<usize>,
+    pub kind: DivergenceKind,
+}
+
+struct OptionalFelt<'a>(&'a Option<Felt>);
+
+impl fmt::Display for OptionalFelt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "0x{value:x}"),
+            None => write!(f, "unchanged"),
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.tx_index {
+            Some(tx_index) => write!(f, "tx {tx_index}: ")?,
+            None => write!(f, "unknown tx: ")?,
+        }
+
+        match &self.kind {
+            DivergenceKind::Storage { contract_address, key, expected, actual } => write!(
+                f,
+                "storage 0x{contract_address:x}[0x{key:x}]: node {}, blockifier {}",
+                OptionalFelt(expected),
+                OptionalFelt(actual)
+            ),
+            DivergenceKind::Nonce { contract_address, expected, actual } => write!(
+                f,
+                "nonce of 0x{contract_address:x}: node {}, blockifier {}",
+                OptionalFelt(expected),
+                OptionalFelt(actual)
+            ),
+            DivergenceKind::ClassHash { contract_address, expected, actual } => write!(
+                f,
+                "class hash of 0x{contract_address:x}: node {}, blockifier {}",
+                OptionalFelt(expected),
+                OptionalFelt(actual)
+            ),
+            DivergenceKind::DeclaredClass { class_hash, expected, actual } => write!(
+                f,
+                "compiled class hash of declared class 0x{class_hash:x}: node {}, blockifier {}",
+                OptionalFelt(expected),
+                OptionalFelt(actual)
+            ),
+            DivergenceKind::TransactionCount { n_traces, n_receipts, n_executed } => write!(
+                f,
+                "number of transactions: node returned {n_traces} traces and {n_receipts} receipts, blockifier \
+                 executed {n_executed} transactions"
+            ),
+            DivergenceKind::Fee { expected, actual } => {
+                write!(f, "fee: node 0x{expected:x}, blockifier 0x{actual:x}")
+            }
+            DivergenceKind::RevertStatus { expected, actual } => {
+                write!(f, "reverted: node {expected}, blockifier {actual}")
+            }
+            DivergenceKind::Events { n_expected, n_actual } => {
+                write!(f, "events differ: node emitted {n_expected}, blockifier emitted {n_actual}")
+            }
+            DivergenceKind::Messages { n_expected, n_actual } => {
+                write!(f, "L2 to L1 messages differ: node sent {n_expected}, blockifier sent {n_actual}")
+            }
+        }
+    }
+}
+
+/// State diff of a block, in a format that can be built from both the node and blockifier diffs.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct NormalizedStateDiff {
+    storage: BTreeMap<(Felt, Felt), Felt>,
+    nonces: BTreeMap<Felt, Felt>,
+    class_hashes: BTreeMap<Felt, Felt>,
+    compiled_class_hashes: BTreeMap<Felt, Felt>,
+}
+
+impl From<&StateDiff> for NormalizedStateDiff {
+    fn from(state_diff: &StateDiff) -> Self {
+        let storage = state_diff
+            .storage_diffs
+            .iter()
+            .flat_map(|diff| diff.storage_entries.iter().map(|entry| ((diff.address, entry.key), entry.value)))
+            .collect();
+        let nonces = state_diff.nonces.iter().map(|update| (update.contract_address, update.nonce)).collect();
+        let class_hashes = state_diff
+            .deployed_contracts
+            .iter()
+            .map(|item| (item.address, item.class_hash))
+            .chain(state_diff.replaced_classes.iter().map(|item| (item.contract_address, item.class_hash)))
+            .collect();
+        // Cairo 0 classes (`deprecated_declared_classes`) have no compiled class hash and are not
+        // part of the blockifier state diff.
+        let compiled_class_hashes =
+            state_diff.declared_classes.iter().map(|item| (item.class_hash, item.compiled_class_hash)).collect();
+
+        Self { storage, nonces, class_hashes, compiled_class_hashes }
+    }
+}
+
+impl From<CommitmentStateDiff> for NormalizedStateDiff {
+    fn from(state_diff: CommitmentStateDiff) -> Self {
+        let storage = state_diff
+            .storage_updates
+            .into_iter()
+            .flat_map(|(address, updates)| {
+                updates.into_iter().map(move |(key, value)| ((*address.0.key(), *key.0.key()), value))
+            })
+            .collect();
+        let nonces =
+            state_diff.address_to_nonce.into_iter().map(|(address, nonce)| (*address.0.key(), nonce.0)).collect();
+        let class_hashes = state_diff
+            .address_to_class_hash
+            .into_iter()
+            .map(|(address, class_hash)| (*address.0.key(), class_hash.0))
+            .collect();
+        let compiled_class_hashes = state_diff
+            .class_hash_to_compiled_class_hash
+            .into_iter()
+            .map(|(class_hash, compiled_class_hash)| (class_hash.0, compiled_class_hash.0))
+            .collect();
+
+        Self { storage, nonces, class_hashes, compiled_class_hashes }
+    }
+}
+
+/// Returns the keys for which the two maps differ, with the expected and actual values.
+fn diff_maps<K: Ord + Copy>(
+    expected: &BTreeMap<K, Felt>,
+    actual: &BTreeMap<K, Felt>,
+) -> Vec<(K, Option<Felt>, Option<Felt>)> {
+    let keys: BTreeSet<K> = expected.keys().chain(actual.keys()).copied().collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let expected_value = expected.get(&key).copied();
+            let actual_value = actual.get(&key).copied();
+            (expected_value != actual_value).then_some((key, expected_value, actual_value))
+        })
+        .collect()
+}
+
+fn collect_call_infos<'a>(call_info: &'a CallInfo, call_infos: &mut Vec<&'a CallInfo>) {
+    call_infos.push(call_info);
+    for inner_call in &call_info.inner_calls {
+        collect_call_infos(inner_call, call_infos);
+    }
+}
+
+/// Returns all the calls (including inner calls) of a transaction.
+fn get_call_infos(tx_execution_info: &TransactionExecutionInfo) -> Vec<&CallInfo> {
+    let mut call_infos = vec![];
+    for call_info in [
+        &tx_execution_info.validate_call_info,
+        &tx_execution_info.execute_call_info,
+        &tx_execution_info.fee_transfer_call_info,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        collect_call_infos(call_info, &mut call_infos);
+    }
+    call_infos
+}
+
+/// Returns the index of the last transaction with a call matching `predicate`.
+fn find_last_tx<F>(tx_execution_infos: &[TransactionExecutionInfo], predicate: F) -> Option<usize>
+where
+    F: Fn(&CallInfo) -> bool,
+{
+    tx_execution_infos
+        .iter()
+        .rposition(|tx_execution_info| get_call_infos(tx_execution_info).into_iter().any(&predicate))
+}
+
+fn calls_contract(call_info: &CallInfo, contract_address: Felt) -> bool {
+    *call_info.call.storage_address.0.key() == contract_address
+}
+
+fn compare_state_diffs(
+    expected: &NormalizedStateDiff,
+    actual: &NormalizedStateDiff,
+    tx_execution_infos: &[TransactionExecutionInfo],
+) -> Vec<Divergence> {
+    let mut divergences = vec![];
+
+    for ((contract_address, key), expected, actual) in diff_maps(&expected.storage, &actual.storage) {
+        let tx_index = find_last_tx(tx_execution_infos, |call_info| {
+            calls_contract(call_info, contract_address)
+                && call_info.accessed_storage_keys.iter().any(|accessed_key| *accessed_key.0.key() == key)
+        });
+        divergences
+            .push(Divergence { tx_index, kind: DivergenceKind::Storage { contract_address, key, expected, actual } });
+    }
+
+    for (contract_address, expected, actual) in diff_maps(&expected.nonces, &actual.nonces) {
+        let tx_index = find_last_tx(tx_execution_infos, |call_info| calls_contract(call_info, contract_address));
+        divergences.push(Divergence { tx_index, kind: DivergenceKind::Nonce { contract_address, expected, actual } });
+    }
+
+    for (contract_address, expected, actual) in diff_maps(&expected.class_hashes, &actual.class_hashes) {
+        let tx_index = find_last_tx(tx_execution_infos, |call_info| calls_contract(call_info, contract_address));
+        divergences
+            .push(Divergence { tx_index, kind: DivergenceKind::ClassHash { contract_address, expected, actual } });
+    }
+
+    for (class_hash, expected, actual) in diff_maps(&expected.compiled_class_hashes, &actual.compiled_class_hashes) {
+        divergences
+            .push(Divergence { tx_index: None, kind: DivergenceKind::DeclaredClass { class_hash, expected, actual } });
+    }
+
+    divergences
+}
+
+fn get_receipt_data(receipt: &TransactionReceipt) -> (&FeePayment, &[Event], &[MsgToL1]) {
+    match receipt {
+        TransactionReceipt::Invoke(receipt) => (&receipt.actual_fee, &receipt.events, &receipt.messages_sent),
+        TransactionReceipt::L1Handler(receipt) => (&receipt.actual_fee, &receipt.events, &receipt.messages_sent),
+        TransactionReceipt::Declare(receipt) => (&receipt.actual_fee, &receipt.events, &receipt.messages_sent),
+        TransactionReceipt::Deploy(receipt) => (&receipt.actual_fee, &receipt.events, &receipt.messages_sent),
+        TransactionReceipt::DeployAccount(receipt) => (&receipt.actual_fee, &receipt.events, &receipt.messages_sent),
+    }
+}
+
+/// Events as (from address, keys, data), sorted as blockifier and the node may order them differently.
+type EventTuple = (Felt, Vec<Felt>, Vec<Felt>);
+/// Messages as (from address, to address, payload), sorted.
+type MessageTuple = (Felt, Felt, Vec<Felt>);
+
+fn get_blockifier_events_and_messages(
+    tx_execution_info: &TransactionExecutionInfo,
+) -> (Vec<EventTuple>, Vec<MessageTuple>) {
+    let mut events = vec![];
+    let mut messages = vec![];
+    for call_info in get_call_infos(tx_execution_info) {
+        let from_address = *call_info.call.storage_address.0.key();
+        events.extend(call_info.execution.events.iter().map(|ordered_event| {
+            let keys = ordered_event.event.keys.iter().map(|key| key.0).collect();
+            (from_address, keys, ordered_event.event.data.0.clone())
+        }));
+        messages.extend(call_info.execution.l2_to_l1_messages.iter().map(|ordered_message| {
+            let to_address = Felt::from_bytes_be_slice(ordered_message.message.to_address.0.as_bytes());
+            (from_address, to_address, ordered_message.message.payload.0.clone())
+        }));
+    }
+    events.sort();
+    messages.sort();
+    (events, messages)
+}
+
+fn compare_transactions(
+    tx_execution_infos: &[TransactionExecutionInfo],
+    traces: &[TransactionTraceWithHash],
+    receipts: &[TransactionReceipt],
+) -> Vec<Divergence> {
+    let mut divergences = vec![];
+
+    if traces.len() != tx_execution_infos.len() || receipts.len() != tx_execution_infos.len() {
+        divergences.push(Divergence {
+            tx_index: None,
+            kind: DivergenceKind::TransactionCount {
+                n_traces: traces.len(),
+                n_receipts: receipts.len(),
+                n_executed: tx_execution_infos.len(),
+            },
+        });
+    }
+
+    for (tx_index, (tx_execution_info, receipt)) in tx_execution_infos.iter().zip(receipts).enumerate() {
+        let mut push = |kind| divergences.push(Divergence { tx_index: Some(tx_index), kind });
+
+        // The receipt holds the execution result of every type of transaction
+        let expected_reverted = matches!(receipt.execution_result(), ExecutionResult::Reverted { .. });
+        let actual_reverted = tx_execution_info.is_reverted();
+        if expected_reverted != actual_reverted {
+            push(DivergenceKind::RevertStatus { expected: expected_reverted, actual: actual_reverted });
+        }
+
+        let (fee, events, messages) = get_receipt_data(receipt);
+        let actual_fee = Felt::from(tx_execution_info.transaction_receipt.fee.0);
+        if fee.amount != actual_fee {
+            push(DivergenceKind::Fee { expected: fee.amount, actual: actual_fee });
+        }
+
+        let mut expected_events: Vec<EventTuple> =
+            events.iter().map(|event| (event.from_address, event.keys.clone(), event.data.clone())).collect();
+        expected_events.sort();
+        let mut expected_messages: Vec<MessageTuple> = messages
+            .iter()
+            .map(|message| (message.from_address, message.to_address, message.payload.clone()))
+            .collect();
+        expected_messages.sort();
+
+        let (actual_events, actual_messages) = get_blockifier_events_and_messages(tx_execution_info);
+        if expected_events != actual_events {
+            push(DivergenceKind::Events { n_expected: expected_events.len(), n_actual: actual_events.len() });
+        }
+        if expected_messages != actual_messages {
+            push(DivergenceKind::Messages { n_expected: expected_messages.len(), n_actual: actual_messages.len() });
+        }
+    }
+
+    divergences
+}
+
+/// Checks that the re-execution of a block with blockifier matches the data of the node.
+///
+/// Compares the state diff of `state` after re-execution with the state diff of the block
+/// (storage, nonces, class hashes and declared classes), and the fee, revert status, events and
+/// L2 to L1 messages of each transaction with its trace and receipt.
+/// Mismatches otherwise only surface later on as hard to debug failures of the OS.
+pub(crate) fn verify_reexecution<S: StateReader>(
+    block_number: u64,
+    state: &mut CachedState<S>,
+    state_diff: &StateDiff,
+    tx_execution_infos: &[TransactionExecutionInfo],
+    traces: &[TransactionTraceWithHash],
+    receipts: &[TransactionReceipt],
+) -> Result<(), ProveBlockError> {
+    let actual_state_diff: CommitmentStateDiff =
+        state.to_state_diff().map_err(|e| ProveBlockError::ReExecutionError(Box::new(e)))?.into();
+
+    let mut divergences = compare_transactions(tx_execution_infos, traces, receipts);
+    divergences.extend(compare_state_diffs(
+        &NormalizedStateDiff::from(state_diff),
+        &NormalizedStateDiff::from(actual_state_diff),
+        tx_execution_infos,
+    ));
+
+    if divergences.is_empty() {
+        return Ok(());
+    }
+
+    for divergence in &divergences {
+        tracing::error!("Block {}: re-execution diverges from the node, {}", block_number, divergence);
+    }
+    Err(ProveBlockError::ReExecutionMismatch { block_number, divergences })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use starknet::core::types::{ContractStorageDiffItem, NonceUpdate, StorageEntry};
+
+    use super::*;
+
+    #[test]
+    fn test_compare_transactions() {
+        let reverted_l1_handler: TransactionReceipt = serde_json::from_value(json!({
+            "type": "L1_HANDLER",
+            "transaction_hash": "0x1",
+            "message_hash": format!("0x{}", "00".repeat(32)),
+            "actual_fee": { "amount": "0x0", "unit": "WEI" },
+            "finality_status": "ACCEPTED_ON_L2",
+            "messages_sent": [],
+            "events": [],
+            "execution_resources": { "steps": 0, "data_availability": { "l1_gas": 0, "l1_data_gas": 0 } },
+            "execution_status": "REVERTED",
+            "revert_reason": "Out of gas"
+        }))
+        .unwrap();
+        let tx_execution_infos = [TransactionExecutionInfo::default(), TransactionExecutionInfo::default()];
+
+        let divergences = compare_transactions(&tx_execution_infos, &[], &[reverted_l1_handler]);
+        assert_eq!(
+            divergences,
+            vec![
+                Divergence {
+                    tx_index: None,
+                    kind: DivergenceKind::TransactionCount { n_traces: 0, n_receipts: 1, n_executed: 2 },
+                },
+                Divergence { tx_index: Some(0), kind: DivergenceKind::RevertStatus { expected: true, actual: false } },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_state_diffs() {
+        let state_diff = StateDiff {
+            storage_diffs: vec![ContractStorageDiffItem {
+                address: Felt::ONE,
+                storage_entries: vec![
+                    StorageEntry { key: Felt::from(10), value: Felt::from(100) },
+                    StorageEntry { key: Felt::from(11), value: Felt::from(110) },
+                ],
+            }],
+            deprecated_declared_classes: vec![],
+            declared_classes: vec![],
+            deployed_contracts: vec![],
+            replaced_classes: vec![],
+            nonces: vec![NonceUpdate { contract_address: Felt::TWO, nonce: Felt::ONE }],
+        };
+        let expected = NormalizedStateDiff::from(&state_diff);
+
+        let mut actual = NormalizedStateDiff::default();
+        actual.storage.insert((Felt::ONE, Felt::from(10)), Felt::from(100));
+        actual.storage.insert((Felt::ONE, Felt::from(11)), Felt::from(111));
+        actual.nonces.insert(Felt::TWO, Felt::ONE);
+        actual.class_hashes.insert(Felt::THREE, Felt::from(30));
+
+        let divergences = compare_state_diffs(&expected, &actual, &[]);
+        assert_eq!(
+            divergences,
+            vec![
+                Divergence {
+                    tx_index: None,
+                    kind: DivergenceKind::Storage {
+                        contract_address: Felt::ONE,
+                        key: Felt::from(11),
+                        expected: Some(Felt::from(110)),
+                        actual: Some(Felt::from(111)),
+                    },
+                },
+                Divergence {
+                    tx_index: None,
+                    kind: DivergenceKind::ClassHash {
+                        contract_address: Felt::THREE,
+                        expected: None,
+                        actual: Some(Felt::from(30)),
+                    },
+                },
+            ]
+        );
+        assert!(compare_state_diffs(&expected, &expected, &[]).is_empty());
+    }
+}
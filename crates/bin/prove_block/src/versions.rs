@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use blockifier::versioned_constants::{StarknetVersion, VersionedConstants};
+
+use crate::ProveBlockError;
+
+/// Set of hints an OS program relies on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintSet {
+    /// Hints of the Starknet OS v0.13.2, implemented by `SnosHintProcessor`.
+    V0_13_2,
+}
+
+/// Everything required to prove a block of a given Starknet version.
+#[derive(Clone)]
+pub struct VersionConfig {
+    /// Constants used by blockifier to re-execute the transactions of the block.
+    pub versioned_constants: VersionedConstants,
+    /// Compiled OS program to run.
+    pub compiled_os: Arc<[u8]>,
+    /// Hints required to run `compiled_os`.
+    pub hint_set: HintSet,
+}
+
+/// Maps the `starknet_version` field of a block to the configuration used to prove it.
+///
+/// Blocks with a version that is not registered are rejected with
+/// `ProveBlockError::UnsupportedStarknetVersion`, additional versions can be supported by
+/// registering their versioned constants with `VersionRegistry::register_versioned_constants_file`.
+#[derive(Clone)]
+pub struct VersionRegistry {
+    compiled_os: Arc<[u8]>,
+    versions: BTreeMap<String, VersionConfig>,
+}
+
+/// Versions supported out of the box, with their blockifier counterpart.
+const BUILTIN_VERSIONS: [(&str, StarknetVersion); 5] = [
+    ("0.13.0", StarknetVersion::V0_13_0),
+    ("0.13.1", StarknetVersion::V0_13_1),
+    ("0.13.1.1", StarknetVersion::V0_13_1_1),
+    ("0.13.2", StarknetVersion::V0_13_2),
+    ("0.13.2.1", StarknetVersion::Latest),
+];
+
+impl VersionRegistry {
+    /// Builds a registry with all the versions supported by blockifier, proved with `compiled_os`.
+    pub fn new(compiled_os: &[u8]) -> Self {
+        let compiled_os: Arc<[u8]> = Arc::from(compiled_os);
+        let versions = BUILTIN_VERSIONS
+            .into_iter()
+            .map(|(version, starknet_version)| {
+                let config = VersionConfig {
+                    versioned_constants: VersionedConstants::get(starknet_version).clone(),
+                    compiled_os: compiled_os.clone(),
+                    hint_set: HintSet::V0_13_2,
+                };
+                (version.to_string(), config)
+            })
+            .collect();
+
+        Self { compiled_os, versions }
+    }
+
+    /// Registers the configuration of a version, replacing the existing one if any.
+    pub fn register(&mut self, version: &str, config: VersionConfig) {
+        self.versions.insert(version.to_string(), config);
+    }
+
+    /// Registers versioned constants for the specified version, e.g. to prove blocks of a version
+    /// not yet supported by blockifier. Blocks of this version are proved with the default OS
+    /// program of the registry.
+    pub fn register_versioned_constants(&mut self, version: &str, versioned_constants: VersionedConstants) {
+        let config =
+            VersionConfig { versioned_constants, compiled_os: self.compiled_os.clone(), hint_set: HintSet::V0_13_2 };
+        self.register(version, config);
+    }
+
+    /// Same as `register_versioned_constants`, reading the versioned constants from a JSON file
+    /// in the blockifier format.
+    pub fn register_versioned_constants_file(&mut self, version: &str, path: &Path) -> Result<(), ProveBlockError> {
+        let read_versioned_constants = || -> Result<VersionedConstants, std::io::Error> {
+            let file = File::open(path)?;
+            Ok(serde_json::from_reader(BufReader::new(file))?)
+        };
+        let versioned_constants = read_versioned_constants()
+            .map_err(|source| ProveBlockError::VersionedConstantsFileError { path: path.to_path_buf(), source })?;
+
+        self.register_versioned_constants(version, versioned_constants);
+        Ok(())
+    }
+
+    /// Returns the configuration to use for a block of the specified version.
+    pub fn get(&self, block_number: u64, version: &str) -> Result<&VersionConfig, ProveBlockError> {
+        self.versions
+            .get(version)
+            .ok_or_else(|| ProveBlockError::UnsupportedStarknetVersion { block_number, version: version.to_string() })
+    }
+
+    /// Returns all the supported versions, in lexicographic order.
+    pub fn versions(&self) -> impl Iterator<Item = &str> {
+        self.versions.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::v0_13_0("0.13.0")]
+    #[case::v0_13_1_1("0.13.1.1")]
+    #[case::v0_13_2_1("0.13.2.1")]
+    fn test_builtin_versions(#[case] version: &str) {
+        let registry = VersionRegistry::new(b"os");
+        let config = registry.get(1, version).unwrap();
+        assert_eq!(config.compiled_os.as_ref(), b"os");
+        assert_eq!(config.hint_set, HintSet::V0_13_2);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut registry = VersionRegistry::new(b"os");
+        let error = registry.get(1, "0.12.3").err().unwrap();
+        assert!(matches!(
+            error,
+            ProveBlockError::UnsupportedStarknetVersion { block_number: 1, ref version } if version == "0.12.3"
+        ));
+
+        registry.register_versioned_constants("0.12.3", VersionedConstants::latest_constants().clone());
+        assert!(registry.get(1, "0.12.3").is_ok());
+    }
+}
@@ -1,4 +1,4 @@
-use rpc_client::pathfinder::proofs::ProofVerificationError;
+use rpc_client::pathfinder::proofs::{ProofOutcome, TrieNode};
 use rpc_client::RpcClient;
 use rstest::rstest;
 use starknet::core::types::BlockId;
@@ -51,15 +51,16 @@ async fn test_class_proof_verification_non_inclusion(#[case] class_hash_str: Str
     let rpc_client = RpcClient::new(&endpoint);
 
     let class_proof = rpc_client.pathfinder_rpc().get_class_proof(block_number, &class_hash).await.unwrap();
-    let result = class_proof.verify(class_hash);
-    assert!(result.is_err());
+    let outcome = class_proof.verify(class_hash);
 
-    if let ProofVerificationError::NonExistenceProof { key, proof, .. } = result.unwrap_err() {
-        assert_eq!(class_hash, key);
-        assert_eq!(class_proof.class_proof, proof);
-        // In order to get the height we need to follow the same logic from verify function
+    if let ProofOutcome::NonMember { witness: Some(witness) } = outcome {
+        // The witness is the last node of the proof
+        let Some(TrieNode::Edge { child, path }) = class_proof.class_proof.last() else {
+            panic!("Non-membership proofs end with an edge node");
+        };
+        assert_eq!((*child, path), (witness.child, &witness.path));
     } else {
-        panic!("This tests is only meant for NonInclusionProof errors");
+        panic!("This tests is only meant for non-membership proofs, got {outcome:?}");
     }
 }
 
@@ -75,5 +76,5 @@ async fn test_class_proof_verification_ok(#[case] class_hash_str: String, #[case
     let rpc_client = RpcClient::new(&endpoint);
 
     let class_proof = rpc_client.pathfinder_rpc().get_class_proof(block_number, &class_hash).await.unwrap();
-    assert!(class_proof.verify(class_hash).is_ok());
+    assert!(matches!(class_proof.verify(class_hash), ProofOutcome::Member { .. }));
 }
@@ -1,6 +1,9 @@
 use cairo_vm::types::layout_name::LayoutName;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
-use prove_block::{debug_prove_error, get_memory_segment, prove_block};
+use prove_block::{debug_prove_error, get_memory_segment, prove_block_with_client};
+use rpc_client::proof_provider::ProofApi;
+use rpc_client::recording::RpcRecording;
+use rpc_client::RpcClient;
 use rstest::rstest;
 const DEFAULT_COMPILED_OS: &[u8] = include_bytes!("../../../../build/os_latest.json");
 
@@ -72,12 +75,12 @@ const DEFAULT_COMPILED_OS: &[u8] = include_bytes!("../../../../build/os_latest.j
 #[case::memory_invalid_signature(216914)]
 #[case::diff_assert_values(218624)]
 #[case::could_nt_compute_operand_op1(204337)]
-#[ignore = "Requires a running Pathfinder node"]
+#[ignore = "Requires a running Pathfinder node or RPC recordings"]
 #[tokio::test(flavor = "multi_thread")]
 async fn test_prove_selected_blocks(#[case] block_number: u64) {
-    let endpoint = std::env::var("PATHFINDER_RPC_URL").expect("Missing PATHFINDER_RPC_URL in env");
+    let rpc_client = get_rpc_client(block_number);
     let (snos_pie, _snos_output) =
-        prove_block(DEFAULT_COMPILED_OS, block_number, &endpoint, LayoutName::all_cairo, true)
+        prove_block_with_client(DEFAULT_COMPILED_OS, block_number, &rpc_client, LayoutName::all_cairo, true)
             .await
             .map_err(debug_prove_error)
             .expect("OS generate Cairo PIE");
@@ -96,6 +99,22 @@ async fn test_prove_selected_blocks(#[case] block_number: u64) {
     }
 }
 
+/// Returns an RPC client for the block to prove.
+/// If `SNOS_RPC_RECORDINGS_DIR` is set, the block is proven offline from the
+/// `<SNOS_RPC_RECORDINGS_DIR>/<block_number>.json.gz` recording (see the `--record` option of
+/// the `prove_block` binary). Otherwise, the Pathfinder node at `PATHFINDER_RPC_URL` is used.
+fn get_rpc_client(block_number: u64) -> RpcClient {
+    if let Ok(recordings_dir) = std::env::var("SNOS_RPC_RECORDINGS_DIR") {
+        let recording_path = std::path::Path::new(&recordings_dir).join(format!("{block_number}.json.gz"));
+        let recording = RpcRecording::load(&recording_path)
+            .unwrap_or_else(|e| panic!("Failed to load RPC recording {}: {e}", recording_path.display()));
+        return RpcClient::from_recording(recording, ProofApi::Pathfinder);
+    }
+
+    let endpoint = std::env::var("PATHFINDER_RPC_URL").expect("Missing PATHFINDER_RPC_URL in env");
+    RpcClient::new(&endpoint)
+}
+
 fn get_reference_pie_bytes(block_number: u64) -> Option<Vec<u8>> {
     match block_number {
         173404 => Some(include_bytes!("../reference-pies/173404.zip").to_vec()),
@@ -1,29 +1,141 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use futures::future::try_join_all;
 use reqwest::Url;
-use starknet::providers::jsonrpc::HttpTransport;
-use starknet::providers::JsonRpcClient;
+use serde_json::json;
+use starknet::core::types::BlockId;
+use starknet::providers::jsonrpc::JsonRpcClientError;
+use starknet::providers::{JsonRpcClient, ProviderError};
+use starknet_types_core::felt::Felt;
+use tokio::sync::Semaphore;
 
-use crate::pathfinder::client::PathfinderRpcClient;
+use crate::config::RpcClientConfig;
+use crate::disk_cache::DiskCache;
+use crate::pathfinder::client::{ClientError, PathfinderRpcClient};
+use crate::proof_provider::{ProofApi, ProofProvider};
+use crate::recording::RpcRecording;
+use crate::stats::{RpcStats, RpcStatsSnapshot};
+use crate::storage_proof::StorageProofRpcClient;
+use crate::transport::RpcTransport;
+
+/// Code of the `ContractNotFound` JSON-RPC error.
+const CONTRACT_NOT_FOUND: i64 = 20;
+
+/// Whether a request that failed with this error could succeed if sent again, see
+/// `ClientError::is_retryable`.
+pub fn is_retryable_provider_error(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::RateLimited => true,
+        // starknet-rs wraps the errors of the transport, as well as the responses it fails to parse
+        ProviderError::Other(e) => matches!(
+            e.as_any().downcast_ref::<JsonRpcClientError<ClientError>>(),
+            Some(JsonRpcClientError::TransportError(client_error)) if client_error.is_retryable()
+        ),
+        _ => false,
+    }
+}
 
 struct RpcClientInner {
     /// starknet-rs client, used to access data from endpoints defined in the Starknet RPC spec.
-    starknet_client: JsonRpcClient<HttpTransport>,
+    starknet_client: JsonRpcClient<RpcTransport>,
+    /// Transport of the Starknet RPC endpoint, for the batch requests not supported by starknet-rs.
+    starknet_transport: RpcTransport,
+    /// Maximum number of requests per batch.
+    max_batch_size: usize,
     /// A Pathfinder-specific client to access endpoints not covered by starknet-rs.
     pathfinder_client: PathfinderRpcClient,
+    /// Client used to fetch storage and class proofs, see `ProofApi`.
+    proof_provider: Box<dyn ProofProvider>,
+    /// Responses received by both clients, if the client was built in recording mode.
+    recording: Option<Arc<Mutex<RpcRecording>>>,
+    /// Requests sent by all the clients.
+    stats: Arc<RpcStats>,
+}
+
+fn parse_url(url: &str) -> Url {
+    Url::parse(url).unwrap_or_else(|e| panic!("Could not parse provider URL ({}): {}", url, e))
 }
 
 impl RpcClientInner {
-    fn new(base_url: &str) -> Self {
-        let starknet_rpc_url = format!("{}/rpc/v0_7", base_url);
-        tracing::info!("Starknet RPC URL: {}", starknet_rpc_url);
-        let provider = JsonRpcClient::new(HttpTransport::new(
-            Url::parse(starknet_rpc_url.as_str())
-                .unwrap_or_else(|e| panic!("Could not parse provider URL ({}): {}", starknet_rpc_url, e)),
-        ));
-        let pathfinder_client = PathfinderRpcClient::new(base_url);
+    fn new(
+        base_url: &str,
+        proof_api: ProofApi,
+        config: RpcClientConfig,
+        recording: Option<Arc<Mutex<RpcRecording>>>,
+    ) -> Self {
+        let starknet_rpc_url =
+            config.starknet_rpc_url.clone().unwrap_or_else(|| format!("{}/{}", base_url, config.spec_version.path()));
+        tracing::info!("Starknet RPC URL: {} (spec v{})", starknet_rpc_url, config.spec_version);
+        let pathfinder_rpc_url =
+            config.pathfinder_rpc_url.clone().unwrap_or_else(|| format!("{}/rpc/pathfinder/v0.1", base_url));
+        let storage_proof_rpc_url =
+            config.storage_proof_rpc_url.clone().unwrap_or_else(|| format!("{}/rpc/v0_8", base_url));
+        let stats = Arc::new(RpcStats::default());
+        let concurrency_limit = config.max_concurrent_requests.map(|limit| Arc::new(Semaphore::new(limit.max(1))));
+        let disk_cache = config.disk_cache.as_ref().map(|disk_cache_config| {
+            let disk_cache = DiskCache::open(&disk_cache_config.dir, disk_cache_config.max_size_bytes)
+                .unwrap_or_else(|e| {
+                    panic!("Could not open RPC cache directory ({}): {}", disk_cache_config.dir.display(), e)
+                })
+                .with_max_cacheable_block(disk_cache_config.max_cacheable_block);
+            tracing::info!("RPC cache: {} ({} bytes)", disk_cache.dir().display(), disk_cache.size_bytes());
+            Arc::new(disk_cache)
+        });
 
-        Self { starknet_client: provider, pathfinder_client }
+        let http_transport = |url: &str| {
+            let mut transport =
+                RpcTransport::new_http_with_config(parse_url(url), config.clone()).with_stats(stats.clone());
+            if let Some(semaphore) = &concurrency_limit {
+                transport = transport.with_concurrency_limit(semaphore.clone());
+            }
+            if let Some(disk_cache) = &disk_cache {
+                transport = transport.with_disk_cache(disk_cache.clone());
+            }
+            match &recording {
+                Some(recording) => transport.with_recorder(recording.clone()),
+                None => transport,
+            }
+        };
+
+        let proof_provider: Box<dyn ProofProvider> = match proof_api {
+            ProofApi::Pathfinder => Box::new(PathfinderRpcClient::with_transport(http_transport(&pathfinder_rpc_url))),
+            ProofApi::Starknet => {
+                Box::new(StorageProofRpcClient::with_transport(http_transport(&storage_proof_rpc_url)))
+            }
+        };
+
+        Self {
+            starknet_client: JsonRpcClient::new(
+                http_transport(&starknet_rpc_url).with_spec_version(config.spec_version),
+            ),
+            starknet_transport: http_transport(&starknet_rpc_url).with_spec_version(config.spec_version),
+            max_batch_size: config.max_batch_size.max(1),
+            pathfinder_client: PathfinderRpcClient::with_transport(http_transport(&pathfinder_rpc_url)),
+            proof_provider,
+            recording,
+            stats,
+        }
+    }
+
+    fn from_recording(recording: RpcRecording, proof_api: ProofApi) -> Self {
+        let recording = Arc::new(recording);
+        let stats = Arc::new(RpcStats::default());
+        let replay_transport = || RpcTransport::new_replay(recording.clone()).with_stats(stats.clone());
+
+        let proof_provider: Box<dyn ProofProvider> = match proof_api {
+            ProofApi::Pathfinder => Box::new(PathfinderRpcClient::with_transport(replay_transport())),
+            ProofApi::Starknet => Box::new(StorageProofRpcClient::with_transport(replay_transport())),
+        };
+
+        Self {
+            starknet_client: JsonRpcClient::new(replay_transport()),
+            starknet_transport: replay_transport(),
+            max_batch_size: RpcClientConfig::default().max_batch_size,
+            pathfinder_client: PathfinderRpcClient::with_transport(replay_transport()),
+            proof_provider,
+            recording: None,
+            stats,
+        }
     }
 }
 
@@ -34,14 +146,135 @@ pub struct RpcClient {
 
 impl RpcClient {
     pub fn new(base_url: &str) -> Self {
-        Self { inner: Arc::new(RpcClientInner::new(base_url)) }
+        Self::new_with_proof_api(base_url, ProofApi::default())
+    }
+
+    /// Builds a client that fetches proofs with the specified API.
+    pub fn new_with_proof_api(base_url: &str, proof_api: ProofApi) -> Self {
+        Self::new_with_config(base_url, proof_api, RpcClientConfig::default())
+    }
+
+    /// Builds a client with the specified endpoints, timeouts, retries, concurrency limit and disk cache.
+    pub fn new_with_config(base_url: &str, proof_api: ProofApi, config: RpcClientConfig) -> Self {
+        Self { inner: Arc::new(RpcClientInner::new(base_url, proof_api, config, None)) }
+    }
+
+    /// Builds a client that stores every response it receives from the node.
+    /// The responses can be retrieved with `RpcClient::recording` and replayed later on
+    /// with `RpcClient::from_recording`.
+    pub fn new_recording(base_url: &str, proof_api: ProofApi) -> Self {
+        Self::new_recording_with_config(base_url, proof_api, RpcClientConfig::default())
+    }
+
+    /// Same as `new_recording`, with the specified endpoints, timeouts, retries, concurrency limit and disk cache.
+    pub fn new_recording_with_config(base_url: &str, proof_api: ProofApi, config: RpcClientConfig) -> Self {
+        let recording = Arc::new(Mutex::new(RpcRecording::new()));
+        Self { inner: Arc::new(RpcClientInner::new(base_url, proof_api, config, Some(recording))) }
+    }
+
+    /// Builds a client that answers requests from a recording, without any network access.
+    /// Requests that are not part of the recording fail with `ClientError::MissingRecordedResponse`.
+    /// `proof_api` must match the API used to make the recording.
+    pub fn from_recording(recording: RpcRecording, proof_api: ProofApi) -> Self {
+        Self { inner: Arc::new(RpcClientInner::from_recording(recording, proof_api)) }
     }
 
-    pub fn starknet_rpc(&self) -> &JsonRpcClient<HttpTransport> {
+    pub fn starknet_rpc(&self) -> &JsonRpcClient<RpcTransport> {
         &self.inner.starknet_client
     }
 
     pub fn pathfinder_rpc(&self) -> &PathfinderRpcClient {
         &self.inner.pathfinder_client
     }
+
+    pub fn proof_rpc(&self) -> &dyn ProofProvider {
+        self.inner.proof_provider.as_ref()
+    }
+
+    /// Reads the storage values of `(contract address, key)` pairs at the specified block, in the
+    /// order of `keys`. The keys are read with `starknet_getStorageAt` requests grouped in JSON-RPC
+    /// batches of `RpcClientConfig::max_batch_size` requests, sent concurrently.
+    /// The storage of a contract that does not exist is empty.
+    pub async fn get_storage_values(&self, block_id: BlockId, keys: &[(Felt, Felt)]) -> Result<Vec<Felt>, ClientError> {
+        // Same parameters as starknet-rs, to share the recorded and cached responses
+        let requests: Vec<_> = keys
+            .iter()
+            .map(|(contract_address, key)| {
+                (
+                    "starknet_getStorageAt",
+                    json!({ "contract_address": contract_address, "key": key, "block_id": block_id }),
+                )
+            })
+            .collect();
+
+        let batches = try_join_all(
+            requests.chunks(self.inner.max_batch_size).map(|batch| self.inner.starknet_transport.request_batch(batch)),
+        )
+        .await?;
+
+        batches
+            .into_iter()
+            .flatten()
+            .map(|value| match value {
+                Err(ClientError::JsonRpcError { code: CONTRACT_NOT_FOUND, .. }) => Ok(Felt::ZERO),
+                value => value,
+            })
+            .collect()
+    }
+
+    /// Returns the number of requests sent so far and the traffic they generated.
+    pub fn stats(&self) -> RpcStatsSnapshot {
+        self.inner.stats.snapshot()
+    }
+
+    /// Returns a snapshot of the responses recorded so far, if the client is in recording mode.
+    pub fn recording(&self) -> Option<RpcRecording> {
+        self.inner.recording.as_ref().map(|recording| recording.lock().expect("recorder lock is poisoned").clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use starknet::core::types::StarknetError;
+    use starknet::providers::Provider;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_is_retryable_provider_error() {
+        let server_error = ClientError::HttpError { status: StatusCode::SERVICE_UNAVAILABLE, body: String::new() };
+        let transport_error = ProviderError::Other(Box::new(JsonRpcClientError::TransportError(server_error)));
+        assert!(is_retryable_provider_error(&transport_error));
+        assert!(is_retryable_provider_error(&ProviderError::RateLimited));
+        assert!(!is_retryable_provider_error(&ProviderError::StarknetError(StarknetError::BlockNotFound)));
+
+        // Replaying a recording that misses a response must not be retried
+        let rpc_client = RpcClient::from_recording(RpcRecording::new(), ProofApi::default());
+        let missing_response = rpc_client.starknet_rpc().chain_id().await.unwrap_err();
+        assert!(!is_retryable_provider_error(&missing_response));
+    }
+
+    #[tokio::test]
+    async fn test_get_storage_values() {
+        let block_id = BlockId::Number(1);
+        let storage_at_params = |contract_address: u64, key: u64| json!({ "contract_address": Felt::from(contract_address), "key": Felt::from(key), "block_id": block_id });
+
+        let mut recording = RpcRecording::new();
+        recording.insert(
+            "starknet_getStorageAt",
+            storage_at_params(1, 2),
+            json!({ "jsonrpc": "2.0", "id": 0, "result": "0x5" }),
+        );
+        recording.insert(
+            "starknet_getStorageAt",
+            storage_at_params(3, 2),
+            json!({ "jsonrpc": "2.0", "id": 0, "error": { "code": 20, "message": "Contract not found" } }),
+        );
+        let rpc_client = RpcClient::from_recording(recording, ProofApi::default());
+
+        let keys = [(Felt::from(1), Felt::from(2)), (Felt::from(3), Felt::from(2))];
+        let values = rpc_client.get_storage_values(block_id, &keys).await.unwrap();
+        assert_eq!(values, vec![Felt::from(5), Felt::ZERO]);
+    }
 }
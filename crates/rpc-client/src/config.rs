@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::spec_version::RpcSpecVersion;
+
+/// Endpoints and HTTP settings of an `RpcClient`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcClientConfig {
+    /// Version of the Starknet RPC spec implemented by the node.
+    pub spec_version: RpcSpecVersion,
+    /// URL of the Starknet RPC endpoint. Defaults to `<base URL>/<spec version path>`,
+    /// ex: `http://localhost:9545/rpc/v0_7`.
+    pub starknet_rpc_url: Option<String>,
+    /// URL of the Pathfinder-specific endpoint. Defaults to `<base URL>/rpc/pathfinder/v0.1`.
+    pub pathfinder_rpc_url: Option<String>,
+    /// URL of the endpoint serving `starknet_getStorageProof`. Defaults to `<base URL>/rpc/v0_8`.
+    pub storage_proof_rpc_url: Option<String>,
+    /// Timeout of a request, from the connection to the end of the response body.
+    /// Large storage proofs can take a while to be generated.
+    pub request_timeout: Option<Duration>,
+    /// Timeout of the connection to the node.
+    pub connect_timeout: Option<Duration>,
+    /// Number of times a request is sent again after a transient failure: timeouts, connection
+    /// failures, HTTP 429 and 5xx responses. See `ClientError::is_retryable`.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each subsequent retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two retries.
+    pub max_backoff: Duration,
+    /// Maximum number of requests in flight, shared by all the endpoints of the client.
+    /// Unlimited if not set.
+    pub max_concurrent_requests: Option<usize>,
+    /// Maximum number of requests sent in a single JSON-RPC batch, see `RpcClient::get_storage_values`.
+    /// Batching is disabled if set to 1.
+    pub max_batch_size: usize,
+    /// Persistent cache of the responses to requests for immutable data. Disabled if not set.
+    pub disk_cache: Option<DiskCacheConfig>,
+}
+
+/// Location, size limit and finality bound of the RPC response cache, see `DiskCache`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskCacheConfig {
+    /// Directory of the cache. Entries are keyed by endpoint URL, the directory can be shared
+    /// by nodes of different chains.
+    pub dir: PathBuf,
+    /// The oldest entries are removed when the cache grows larger than this.
+    pub max_size_bytes: u64,
+    /// Highest block number whose responses can be cached, more recent blocks could still be
+    /// reverted. If not set, only requests targeting a block hash are cached.
+    pub max_cacheable_block: Option<u64>,
+}
+
+impl Default for RpcClientConfig {
+    fn default() -> Self {
+        Self {
+            spec_version: RpcSpecVersion::default(),
+            starknet_rpc_url: None,
+            pathfinder_rpc_url: None,
+            storage_proof_rpc_url: None,
+            request_timeout: Some(Duration::from_secs(120)),
+            connect_timeout: Some(Duration::from_secs(10)),
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_concurrent_requests: None,
+            max_batch_size: 100,
+            disk_cache: None,
+        }
+    }
+}
+
+impl RpcClientConfig {
+    /// Returns the delay before the specified retry, starting at 0.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff.saturating_mul(2u32.saturating_pow(retry)).min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let config = RpcClientConfig {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..Default::default()
+        };
+        assert_eq!(config.backoff(0), Duration::from_secs(1));
+        assert_eq!(config.backoff(1), Duration::from_secs(2));
+        assert_eq!(config.backoff(3), Duration::from_secs(8));
+        assert_eq!(config.backoff(4), Duration::from_secs(10));
+        assert_eq!(config.backoff(100), Duration::from_secs(10));
+    }
+}
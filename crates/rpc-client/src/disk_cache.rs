@@ -0,0 +1,280 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::recording::RecordedCall;
+
+/// Version of the cache entry format, part of the key of every entry.
+/// Bump it whenever the layout of the entries changes, older entries will then be ignored.
+const DISK_CACHE_VERSION: u32 = 1;
+
+/// Counter making the names of the temporary files of a process unique, see `DiskCache::insert`.
+static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Methods that return the same response forever when called on a given block number or hash.
+const IMMUTABLE_METHODS: &[&str] = &[
+    "starknet_getBlockWithTxHashes",
+    "starknet_getBlockWithTxs",
+    "starknet_getBlockWithReceipts",
+    "starknet_getStateUpdate",
+    "starknet_getStorageAt",
+    "starknet_getClass",
+    "starknet_getClassHashAt",
+    "starknet_getClassAt",
+    "starknet_getNonce",
+    "starknet_traceBlockTransactions",
+    "starknet_getStorageProof",
+    "pathfinder_getProof",
+    "pathfinder_getClassProof",
+];
+
+/// Whether the response to a request can be cached: the method must return immutable data and
+/// the request must target a final block, i.e. a block hash or a block number up to
+/// `max_cacheable_block`. Requests for `latest`, `pending` or more recent block numbers are not
+/// cached, a reorg could change their responses.
+pub fn is_cacheable(method: &str, params: &serde_json::Value, max_cacheable_block: Option<u64>) -> bool {
+    if !IMMUTABLE_METHODS.contains(&method) {
+        return false;
+    }
+
+    // All the requests are sent with named parameters, by starknet-rs and by our own clients
+    let block_id = match params {
+        serde_json::Value::Object(params) => params.get("block_id"),
+        _ => None,
+    };
+    match block_id {
+        Some(serde_json::Value::Object(block_id)) => {
+            if block_id.contains_key("block_hash") {
+                return true;
+            }
+            match (block_id.get("block_number").and_then(|block_number| block_number.as_u64()), max_cacheable_block) {
+                (Some(block_number), Some(max_cacheable_block)) => block_number <= max_cacheable_block,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Builds the name of the file storing the response to a request sent to `endpoint`.
+fn entry_file_name(endpoint: &str, method: &str, params: &serde_json::Value) -> String {
+    let digest = Sha256::digest(format!("{DISK_CACHE_VERSION}:{endpoint}:{method}:{params}").as_bytes());
+    format!("{}.json.gz", hex::encode(digest))
+}
+
+/// Opt-in cache of the responses to requests for immutable data (see `is_cacheable`), stored
+/// in a directory as one gzipped JSON file per request.
+///
+/// Only successful responses are cached. Entries are keyed by endpoint URL as well, so the cache
+/// can be shared by nodes of different chains.
+/// When the size of the cache exceeds its limit, the oldest entries are removed.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size_bytes: u64,
+    /// Highest block number considered final, see `is_cacheable`.
+    max_cacheable_block: Option<u64>,
+    /// Total size of the entries.
+    size_bytes: Mutex<u64>,
+}
+
+impl DiskCache {
+    /// Opens the cache stored in `dir`, creating the directory if needed.
+    /// Only requests targeting a block hash are cached until `with_max_cacheable_block` is called.
+    pub fn open(dir: &Path, max_size_bytes: u64) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let cache =
+            Self { dir: dir.to_path_buf(), max_size_bytes, max_cacheable_block: None, size_bytes: Mutex::new(0) };
+        let size_bytes = cache.entries()?.iter().map(|(_, size, _)| size).sum();
+        *cache.size_bytes.lock().expect("cache lock is poisoned") = size_bytes;
+
+        Ok(cache)
+    }
+
+    /// Caches the responses to requests targeting block numbers up to `max_cacheable_block`.
+    /// More recent blocks could still be reverted.
+    pub fn with_max_cacheable_block(mut self, max_cacheable_block: Option<u64>) -> Self {
+        self.max_cacheable_block = max_cacheable_block;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether the response to a request can be cached, see `is_cacheable`.
+    pub fn is_cacheable(&self, method: &str, params: &serde_json::Value) -> bool {
+        is_cacheable(method, params, self.max_cacheable_block)
+    }
+
+    /// Total size of the entries, in bytes.
+    pub fn size_bytes(&self) -> u64 {
+        *self.size_bytes.lock().expect("cache lock is poisoned")
+    }
+
+    /// Returns the cached response to a request sent to `endpoint`, if any.
+    pub fn get(&self, endpoint: &str, method: &str, params: &serde_json::Value) -> Option<serde_json::Value> {
+        let path = self.dir.join(entry_file_name(endpoint, method, params));
+        let file = File::open(&path).ok()?;
+        let call: RecordedCall = match serde_json::from_reader(GzDecoder::new(BufReader::new(file))) {
+            Ok(call) => call,
+            Err(e) => {
+                tracing::warn!("Ignoring corrupted RPC cache entry {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        // Guard against hash collisions
+        (call.method == method && &call.params == params).then_some(call.response)
+    }
+
+    /// Stores the response to a request sent to `endpoint`. Failures are only logged, the cache is
+    /// an optimization.
+    pub fn insert(&self, endpoint: &str, method: &str, params: &serde_json::Value, response: &serde_json::Value) {
+        let path = self.dir.join(entry_file_name(endpoint, method, params));
+        let call = RecordedCall { method: method.to_string(), params: params.clone(), response: response.clone() };
+
+        // Write to a temporary file first so that concurrent readers never see partial entries.
+        // The name is unique so that concurrent writers of the same entry, in this process or in
+        // another one sharing the directory, do not write to the same file.
+        let tmp_path =
+            self.dir.join(format!("{}.{}.tmp", std::process::id(), TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+        // Returns the size of the new entry and the size of the entry it replaces, if any
+        let write_entry = || -> std::io::Result<(u64, u64)> {
+            let mut encoder = GzEncoder::new(BufWriter::new(File::create(&tmp_path)?), Compression::default());
+            serde_json::to_writer(&mut encoder, &call)?;
+            encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+            let entry_size = fs::metadata(&tmp_path)?.len();
+            // The entry can already exist, ex: after concurrent misses or when rewriting a corrupted entry
+            let replaced_size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+            fs::rename(&tmp_path, &path)?;
+            Ok((entry_size, replaced_size))
+        };
+
+        match write_entry() {
+            Ok((entry_size, replaced_size)) => {
+                let size_bytes = {
+                    let mut size_bytes = self.size_bytes.lock().expect("cache lock is poisoned");
+                    *size_bytes = (*size_bytes + entry_size).saturating_sub(replaced_size);
+                    *size_bytes
+                };
+                if size_bytes > self.max_size_bytes {
+                    if let Err(e) = self.evict() {
+                        tracing::warn!("Failed to evict RPC cache entries from {}: {}", self.dir.display(), e);
+                    }
+                }
+            }
+            Err(e) => {
+                // Temporary files are not listed as entries, they would never be removed otherwise
+                let _ = fs::remove_file(&tmp_path);
+                tracing::warn!("Failed to write RPC cache entry {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Removes all the entries.
+    pub fn clear(&self) -> std::io::Result<()> {
+        for (path, _, _) in self.entries()? {
+            fs::remove_file(path)?;
+        }
+        *self.size_bytes.lock().expect("cache lock is poisoned") = 0;
+
+        Ok(())
+    }
+
+    /// Removes the oldest entries until the cache fits in its size limit.
+    fn evict(&self) -> std::io::Result<()> {
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut size_bytes: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        for (path, size, _) in entries {
+            if size_bytes <= self.max_size_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            size_bytes -= size;
+        }
+        *self.size_bytes.lock().expect("cache lock is poisoned") = size_bytes;
+
+        Ok(())
+    }
+
+    /// Lists the entries with their size and modification time.
+    fn entries(&self) -> std::io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut entries = vec![];
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if !path.to_string_lossy().ends_with(".json.gz") {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            entries.push((path, metadata.len(), metadata.modified()?));
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use serde_json::json;
+
+    use super::*;
+
+    #[rstest]
+    #[case::block_number("starknet_getStateUpdate", json!({ "block_id": { "block_number": 1 } }), true)]
+    #[case::max_block_number("starknet_getStorageProof", json!({ "block_id": { "block_number": 10 } }), true)]
+    #[case::recent_block_number("starknet_getStateUpdate", json!({ "block_id": { "block_number": 11 } }), false)]
+    #[case::block_hash("starknet_getClass", json!({ "block_id": { "block_hash": "0x1" }, "class_hash": "0x2" }), true)]
+    #[case::latest("starknet_getStorageAt", json!({ "block_id": "latest", "key": "0x1" }), false)]
+    #[case::pending("starknet_getNonce", json!({ "block_id": "pending" }), false)]
+    #[case::positional("starknet_getStorageProof", json!([{ "block_number": 1 }, [], [], []]), false)]
+    #[case::mutable_method("starknet_blockNumber", json!([]), false)]
+    fn test_is_cacheable(#[case] method: &str, #[case] params: serde_json::Value, #[case] expected: bool) {
+        assert_eq!(is_cacheable(method, &params, Some(10)), expected);
+    }
+
+    #[test]
+    fn test_block_numbers_are_not_cacheable_without_bound() {
+        assert!(!is_cacheable("starknet_getStateUpdate", &json!({ "block_id": { "block_number": 1 } }), None));
+        assert!(is_cacheable("starknet_getStateUpdate", &json!({ "block_id": { "block_hash": "0x1" } }), None));
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::open(dir.path(), u64::MAX).unwrap();
+
+        let endpoint = "http://localhost:9545/rpc/v0_7";
+        let params = json!({ "block_id": { "block_number": 1 } });
+        let response = json!({ "jsonrpc": "2.0", "id": 0, "result": "0x1" });
+        assert_eq!(cache.get(endpoint, "starknet_getStateUpdate", &params), None);
+
+        cache.insert(endpoint, "starknet_getStateUpdate", &params, &response);
+        assert_eq!(cache.get(endpoint, "starknet_getStateUpdate", &params), Some(response.clone()));
+        assert_eq!(cache.get(endpoint, "starknet_getBlockWithTxs", &params), None);
+        assert_eq!(cache.get("http://localhost:9546/rpc/v0_7", "starknet_getStateUpdate", &params), None);
+        assert!(cache.size_bytes() > 0);
+        assert_eq!(DiskCache::open(dir.path(), u64::MAX).unwrap().size_bytes(), cache.size_bytes());
+
+        // Rewriting an entry replaces it
+        let size_bytes = cache.size_bytes();
+        cache.insert(endpoint, "starknet_getStateUpdate", &params, &response);
+        assert_eq!(cache.size_bytes(), size_bytes);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        cache.clear().unwrap();
+        assert_eq!(cache.get(endpoint, "starknet_getStateUpdate", &params), None);
+        assert_eq!(cache.size_bytes(), 0);
+    }
+}
@@ -1,4 +1,14 @@
 pub mod client;
+pub mod config;
+pub mod disk_cache;
 pub mod pathfinder;
+pub mod proof_provider;
+pub mod recording;
+pub mod spec_version;
+pub mod stats;
+pub mod storage_proof;
+pub mod transport;
 
 pub use client::RpcClient;
+pub use config::{DiskCacheConfig, RpcClientConfig};
+pub use spec_version::RpcSpecVersion;
@@ -1,72 +1,61 @@
-use reqwest::{Response, StatusCode};
-use serde::de::DeserializeOwned;
-use serde::Deserialize;
+use reqwest::{StatusCode, Url};
 use serde_json::json;
 use starknet_types_core::felt::Felt;
 
 use crate::pathfinder::proofs::{PathfinderClassProof, PathfinderProof};
+use crate::spec_version::LossyConversion;
+use crate::transport::RpcTransport;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
     #[error("Encountered a request error: {0}")]
     ReqwestError(#[from] reqwest::Error),
+    #[error("Encountered a serde error: {0}")]
+    SerdeError(#[from] serde_json::Error),
+    #[error("No recorded response for {method} with params {params}")]
+    MissingRecordedResponse { method: String, params: String },
+    #[error("Received HTTP {status}: {body}")]
+    HttpError { status: StatusCode, body: String },
+    #[error("JSON-RPC error {code}: {message}")]
+    JsonRpcError { code: i64, message: String },
+    #[error(transparent)]
+    LossyConversion(#[from] LossyConversion),
     #[error("Encountered a custom error: {0}")]
     CustomError(String),
 }
 
-fn jsonrpc_request(method: &str, params: serde_json::Value) -> serde_json::Value {
-    json!({
-        "jsonrpc": "2.0",
-        "id": "0",
-        "method": method,
-        "params": params,
-    })
-}
-
-async fn post_jsonrpc_request<T: DeserializeOwned>(
-    client: &reqwest::Client,
-    rpc_provider: &str,
-    method: &str,
-    params: serde_json::Value,
-) -> Result<T, ClientError> {
-    let request = jsonrpc_request(method, params);
-    let response = client.post(format!("{}/rpc/pathfinder/v0.1", rpc_provider)).json(&request).send().await?;
-
-    #[derive(Deserialize)]
-    struct TransactionReceiptResponse<T> {
-        result: T,
-    }
-
-    let response: TransactionReceiptResponse<T> = handle_error(response).await?;
-
-    Ok(response.result)
-}
-
-async fn handle_error<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
-    match response.status() {
-        StatusCode::OK => Ok(response.json().await?),
-        s => {
-            let error = response.text().await?;
-            Err(ClientError::CustomError(format!("Received response: {s:?} Error: {error}")))
+impl ClientError {
+    /// Whether the request could succeed if sent again, ex: on timeouts, connection failures,
+    /// rate limits or server errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ReqwestError(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            Self::HttpError { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            Self::SerdeError(_)
+            | Self::MissingRecordedResponse { .. }
+            | Self::JsonRpcError { .. }
+            | Self::LossyConversion(_)
+            | Self::CustomError(_) => false,
         }
     }
 }
 
 pub struct PathfinderRpcClient {
-    /// A raw client to access endpoints not covered by starknet-rs.
-    http_client: reqwest::Client,
-    /// The base URL of the RPC client
-    rpc_base_url: String,
+    /// Transport used to access endpoints not covered by starknet-rs.
+    transport: RpcTransport,
 }
 
 impl PathfinderRpcClient {
     pub fn new(base_url: &str) -> Self {
-        let starknet_rpc_url = format!("{}/rpc/v0_7", base_url);
-        tracing::info!("Starknet RPC URL: {}", starknet_rpc_url);
-        let http_client =
-            reqwest::ClientBuilder::new().build().unwrap_or_else(|e| panic!("Could not build reqwest client: {e}"));
+        let pathfinder_rpc_url = format!("{}/rpc/pathfinder/v0.1", base_url);
+        let url = Url::parse(&pathfinder_rpc_url)
+            .unwrap_or_else(|e| panic!("Could not parse Pathfinder RPC URL ({}): {}", pathfinder_rpc_url, e));
+
+        Self::with_transport(RpcTransport::new_http(url))
+    }
 
-        Self { http_client, rpc_base_url: base_url.to_string() }
+    pub fn with_transport(transport: RpcTransport) -> Self {
+        Self { transport }
     }
 
     pub async fn get_proof(
@@ -75,13 +64,32 @@ impl PathfinderRpcClient {
         contract_address: Felt,
         keys: &[Felt],
     ) -> Result<PathfinderProof, ClientError> {
-        post_jsonrpc_request(
-            &self.http_client,
-            &self.rpc_base_url,
-            "pathfinder_getProof",
-            json!({ "block_id": { "block_number": block_number }, "contract_address": contract_address, "keys": keys }),
-        )
-        .await
+        self.transport
+            .request(
+                "pathfinder_getProof",
+                json!({ "block_id": { "block_number": block_number }, "contract_address": contract_address, "keys": keys }),
+            )
+            .await
+    }
+
+    /// Returns the proofs of several contracts in a single JSON-RPC batch, in the order of `requests`.
+    /// Each proof can fail independently of the others.
+    pub async fn get_proofs(
+        &self,
+        block_number: u64,
+        requests: &[(Felt, Vec<Felt>)],
+    ) -> Result<Vec<Result<PathfinderProof, ClientError>>, ClientError> {
+        let requests: Vec<_> = requests
+            .iter()
+            .map(|(contract_address, keys)| {
+                (
+                    "pathfinder_getProof",
+                    json!({ "block_id": { "block_number": block_number }, "contract_address": contract_address, "keys": keys }),
+                )
+            })
+            .collect();
+
+        self.transport.request_batch(&requests).await
     }
 
     pub async fn get_class_proof(
@@ -90,12 +98,31 @@ impl PathfinderRpcClient {
         class_hash: &Felt,
     ) -> Result<PathfinderClassProof, ClientError> {
         log::debug!("querying pathfinder_getClassProof for {:x}", class_hash);
-        post_jsonrpc_request(
-            &self.http_client,
-            &self.rpc_base_url,
-            "pathfinder_getClassProof",
-            json!({ "block_id": { "block_number": block_number }, "class_hash": class_hash }),
-        )
-        .await
+        self.transport
+            .request(
+                "pathfinder_getClassProof",
+                json!({ "block_id": { "block_number": block_number }, "class_hash": class_hash }),
+            )
+            .await
+    }
+
+    /// Returns the proofs of several classes in a single JSON-RPC batch, in the order of
+    /// `class_hashes`. Each proof can fail independently of the others.
+    pub async fn get_class_proofs(
+        &self,
+        block_number: u64,
+        class_hashes: &[Felt],
+    ) -> Result<Vec<Result<PathfinderClassProof, ClientError>>, ClientError> {
+        let requests: Vec<_> = class_hashes
+            .iter()
+            .map(|class_hash| {
+                (
+                    "pathfinder_getClassProof",
+                    json!({ "block_id": { "block_number": block_number }, "class_hash": class_hash }),
+                )
+            })
+            .collect();
+
+        self.transport.request_batch(&requests).await
     }
 }
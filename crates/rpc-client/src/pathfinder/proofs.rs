@@ -1,4 +1,7 @@
-use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
 use starknet_os::config::DEFAULT_STORAGE_TREE_HEIGHT;
 use starknet_os::crypto::pedersen::PedersenHash;
 use starknet_os::crypto::poseidon::PoseidonHash;
@@ -8,7 +11,9 @@ use starknet_os::storage::dict_storage::DictStorage;
 use starknet_os::storage::storage::{Fact, HashFunctionType};
 use starknet_types_core::felt::Felt;
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+use crate::storage_proof::compute_state_commitment;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum TrieNode {
     #[serde(rename = "binary")]
     Binary { left: Felt, right: Felt },
@@ -17,6 +22,23 @@ pub enum TrieNode {
 }
 
 impl TrieNode {
+    /// Hashes of the children of the node.
+    fn children(&self) -> Vec<Felt> {
+        match self {
+            TrieNode::Binary { left, right } => vec![*left, *right],
+            TrieNode::Edge { child, .. } => vec![*child],
+        }
+    }
+
+    /// The node as a commitment fact, i.e. the preimage of its hash: `[left, right]` for binary
+    /// nodes and `[length, path, child]` for edge nodes.
+    pub fn to_fact(&self) -> Vec<Felt> {
+        match self {
+            TrieNode::Binary { left, right } => vec![*left, *right],
+            TrieNode::Edge { child, path } => vec![Felt::from(path.len), path.value, *child],
+        }
+    }
+
     pub fn hash<H: HashFunctionType>(&self) -> Felt {
         match self {
             TrieNode::Binary { left, right } => {
@@ -38,7 +60,7 @@ impl TrieNode {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ContractData {
     /// Root of the Contract state tree
     pub root: Felt,
@@ -46,34 +68,77 @@ pub struct ContractData {
     pub storage_proofs: Vec<Vec<TrieNode>>,
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum ProofVerificationError<'a> {
-    #[error("Non-inclusion proof for key {}. Height {}.", key.to_hex_string(), height.0)]
-    NonExistenceProof { key: Felt, height: Height, proof: &'a [TrieNode] },
-
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProofVerificationError {
     #[error("Proof verification failed, node_hash {node_hash:x} != parent_hash {parent_hash:x}")]
     InvalidChildNodeHash { node_hash: Felt, parent_hash: Felt },
 
+    #[error(
+        "Proof for key {} ends at height {} without reaching the leaf nor diverging from the key",
+        key.to_hex_string(),
+        height.0
+    )]
+    IncompleteProof { key: Felt, height: Height },
+
+    #[error("Proof for key {} goes deeper than the height of the tree", key.to_hex_string())]
+    ProofTooLong { key: Felt },
+
     #[error("Conversion error")]
     ConversionError,
 }
 
-impl ContractData {
-    /// Verifies that each contract state proof is valid.
-    pub fn verify(&self, storage_keys: &[Felt]) -> Result<(), Vec<ProofVerificationError>> {
-        let mut errors = vec![];
+/// Edge node of a proof of non-membership, whose path diverges from the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeWitness {
+    pub child: Felt,
+    pub path: EdgePath,
+    /// Height of the bottom node of the edge, i.e. of `child`.
+    pub height: Height,
+}
 
-        for (index, storage_key) in storage_keys.iter().enumerate() {
-            if let Err(e) = verify_proof::<PedersenHash>(*storage_key, self.root, &self.storage_proofs[index]) {
-                errors.push(e);
-            }
+/// Outcome of the verification of a proof, see `verify_proof`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofOutcome {
+    /// The key is in the trie and has the value `leaf_value`.
+    Member { leaf_value: Felt },
+    /// The key is not in the trie. `witness` is the edge node that diverges from the key, it is
+    /// only `None` if the trie is empty.
+    NonMember { witness: Option<EdgeWitness> },
+    /// The proof does not prove anything about the key.
+    Invalid(ProofVerificationError),
+}
+
+impl ProofOutcome {
+    /// Whether the proof is a valid proof of membership or non-membership.
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, Self::Invalid(_))
+    }
+
+    /// Value of the key, zero if the key is not in the trie.
+    pub fn value(&self) -> Result<Felt, ProofVerificationError> {
+        match self {
+            Self::Member { leaf_value } => Ok(*leaf_value),
+            Self::NonMember { .. } => Ok(Felt::ZERO),
+            Self::Invalid(error) => Err(error.clone()),
         }
+    }
+}
 
-        if errors.is_empty() { Ok(()) } else { Err(errors) }
+impl ContractData {
+    /// Verifies the storage proof of each key, returns one outcome per key.
+    pub fn verify(&self, storage_keys: &[Felt]) -> Vec<ProofOutcome> {
+        storage_keys
+            .iter()
+            .enumerate()
+            .map(|(index, storage_key)| {
+                let proof = self.storage_proofs.get(index).map(Vec::as_slice).unwrap_or_default();
+                verify_proof::<PedersenHash>(*storage_key, self.root, proof)
+            })
+            .collect()
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PathfinderProof {
     pub state_commitment: Felt,
     pub class_commitment: Option<Felt>,
@@ -81,6 +146,112 @@ pub struct PathfinderProof {
     pub contract_data: Option<ContractData>,
 }
 
+/// Inconsistency between a contract proof and the state it is supposed to prove.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ContractProofError {
+    #[error("State commitment 0x{state_commitment:x} of the proof differs from the state root 0x{state_root:x}")]
+    StateRootMismatch { state_commitment: Felt, state_root: Felt },
+
+    #[error(
+        "State commitment 0x{state_commitment:x} does not match the contract root 0x{contract_root:x} and class root \
+         0x{class_root:x}"
+    )]
+    StateCommitmentMismatch { state_commitment: Felt, contract_root: Felt, class_root: Felt },
+
+    #[error("Invalid contract proof: {0}")]
+    InvalidContractProof(String),
+
+    #[error(
+        "Contract leaf 0x{leaf:x} does not match class hash 0x{class_hash:x}, storage root 0x{storage_root:x} and \
+         nonce 0x{nonce:x}"
+    )]
+    ContractLeafMismatch { leaf: Felt, class_hash: Felt, storage_root: Felt, nonce: Felt },
+
+    #[error(
+        "Contract is not in the contract trie but has class hash 0x{class_hash:x}, storage root 0x{storage_root:x} \
+         and nonce 0x{nonce:x}"
+    )]
+    MissingContract { class_hash: Felt, storage_root: Felt, nonce: Felt },
+
+    #[error("Invalid storage proof: {0}")]
+    InvalidStorageProof(String),
+}
+
+/// Computes the hash of the leaf of a contract in the contract trie:
+/// `H(H(H(class_hash, storage_root), nonce), 0)`, with the Pedersen hash.
+pub fn contract_leaf_hash(class_hash: Felt, storage_root: Felt, nonce: Felt) -> Felt {
+    // The last element is the version of the contract state hash
+    let hash = PedersenHash::hash_felts(class_hash, storage_root);
+    let hash = PedersenHash::hash_felts(hash, nonce);
+    PedersenHash::hash_felts(hash, Felt::ZERO)
+}
+
+impl PathfinderProof {
+    /// Root of the contract trie, i.e. the hash of the first node of the contract proof.
+    pub fn contract_root(&self) -> Felt {
+        self.contract_proof.first().map(|node| node.hash::<PedersenHash>()).unwrap_or(Felt::ZERO)
+    }
+
+    /// Verifies the proof of a contract from the state root of the block down to the storage values:
+    /// * the state commitment of the proof is the state root of the block,
+    /// * the state commitment is the commitment of the contract and class trie roots,
+    /// * the contract proof is a valid path of the contract trie,
+    /// * the leaf of the contract matches its class hash, nonce and storage root, or the contract
+    ///   is not in the trie and all these values are zero,
+    /// * the storage proofs of `storage_keys` are valid paths of the storage trie.
+    ///
+    /// Proofs of non-membership are valid storage proofs, the value of these keys is zero.
+    pub fn verify_contract(
+        &self,
+        state_root: Felt,
+        contract_address: Felt,
+        class_hash: Felt,
+        nonce: Felt,
+        storage_keys: &[Felt],
+    ) -> Result<(), ContractProofError> {
+        if self.state_commitment != state_root {
+            return Err(ContractProofError::StateRootMismatch { state_commitment: self.state_commitment, state_root });
+        }
+
+        let contract_root = self.contract_root();
+        let class_root = self.class_commitment.unwrap_or(Felt::ZERO);
+        if compute_state_commitment(contract_root, class_root) != self.state_commitment {
+            return Err(ContractProofError::StateCommitmentMismatch {
+                state_commitment: self.state_commitment,
+                contract_root,
+                class_root,
+            });
+        }
+
+        let leaf = match verify_proof::<PedersenHash>(contract_address, contract_root, &self.contract_proof) {
+            ProofOutcome::Member { leaf_value } => Some(leaf_value),
+            ProofOutcome::NonMember { .. } => None,
+            ProofOutcome::Invalid(e) => return Err(ContractProofError::InvalidContractProof(e.to_string())),
+        };
+
+        let storage_root = self.contract_data.as_ref().map(|contract_data| contract_data.root).unwrap_or(Felt::ZERO);
+        match leaf {
+            Some(leaf) if leaf != contract_leaf_hash(class_hash, storage_root, nonce) => {
+                return Err(ContractProofError::ContractLeafMismatch { leaf, class_hash, storage_root, nonce });
+            }
+            None if class_hash != Felt::ZERO || storage_root != Felt::ZERO || nonce != Felt::ZERO => {
+                return Err(ContractProofError::MissingContract { class_hash, storage_root, nonce });
+            }
+            _ => {}
+        }
+
+        if let Some(contract_data) = &self.contract_data {
+            for outcome in contract_data.verify(storage_keys) {
+                if let ProofOutcome::Invalid(e) = outcome {
+                    return Err(ContractProofError::InvalidStorageProof(e.to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Deserialize)]
 pub struct PathfinderClassProof {
@@ -89,14 +260,14 @@ pub struct PathfinderClassProof {
 }
 
 impl PathfinderClassProof {
-    /// Verifies that the class proof is valid.
-    pub fn verify(&self, class_hash: Felt) -> Result<(), ProofVerificationError> {
+    /// Verifies the class proof, the leaf value of a declared class is its compiled class hash.
+    pub fn verify(&self, class_hash: Felt) -> ProofOutcome {
         verify_proof::<PoseidonHash>(class_hash, self.class_commitment, &self.class_proof)
     }
 }
 
 // Types defined for Deserialize functionality
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct EdgePath {
     pub len: u64,
     pub value: Felt,
@@ -104,23 +275,26 @@ pub struct EdgePath {
 
 /// This function goes through the tree from top to bottom and verifies that
 /// the hash of each node is equal to the corresponding hash in the parent node.
-pub fn verify_proof<H: HashFunctionType>(
-    key: Felt,
-    commitment: Felt,
-    proof: &[TrieNode],
-) -> Result<(), ProofVerificationError> {
+/// The proof must either go down to the leaf of `key` or end with an edge node that diverges from
+/// `key`. The hash function is Pedersen for the contract and storage tries, Poseidon for the class trie.
+pub fn verify_proof<H: HashFunctionType>(key: Felt, commitment: Felt, proof: &[TrieNode]) -> ProofOutcome {
     let bits = key.to_bits_be();
 
     let mut parent_hash = commitment;
 
     // The tree height is 251, so the first 5 bits are ignored.
     let start = 5;
+    let end = start + DEFAULT_STORAGE_TREE_HEIGHT;
     let mut index = start;
 
     for node in proof.iter() {
+        if index >= end {
+            return ProofOutcome::Invalid(ProofVerificationError::ProofTooLong { key });
+        }
+
         let node_hash = node.hash::<H>();
         if node_hash != parent_hash {
-            return Err(ProofVerificationError::InvalidChildNodeHash { node_hash, parent_hash });
+            return ProofOutcome::Invalid(ProofVerificationError::InvalidChildNodeHash { node_hash, parent_hash });
         }
 
         match node {
@@ -129,8 +303,15 @@ pub fn verify_proof<H: HashFunctionType>(
                 index += 1;
             }
             TrieNode::Edge { child, path } => {
-                let path_len_usize: usize = path.len.try_into().map_err(|_| ProofVerificationError::ConversionError)?;
-                let index_usize: usize = index.try_into().map_err(|_| ProofVerificationError::ConversionError)?;
+                if index + path.len > end {
+                    return ProofOutcome::Invalid(ProofVerificationError::ProofTooLong { key });
+                }
+                let Ok(path_len_usize) = usize::try_from(path.len) else {
+                    return ProofOutcome::Invalid(ProofVerificationError::ConversionError);
+                };
+                let Ok(index_usize) = usize::try_from(index) else {
+                    return ProofOutcome::Invalid(ProofVerificationError::ConversionError);
+                };
 
                 let path_bits = path.value.to_bits_be();
                 let relevant_path_bits = &path_bits[path_bits.len() - path_len_usize..];
@@ -144,15 +325,234 @@ pub fn verify_proof<H: HashFunctionType>(
                     // 1. We correctly moved towards the target as far as possible, and
                     // 2. Hashing all the nodes along the path results in the root hash, which means
                     // 3. The target definitely does not exist in this tree
-                    return Err(ProofVerificationError::NonExistenceProof {
-                        key,
-                        height: Height(DEFAULT_STORAGE_TREE_HEIGHT - (index - start)),
-                        proof,
-                    });
+                    let height = Height(end - index);
+                    return ProofOutcome::NonMember {
+                        witness: Some(EdgeWitness { child: *child, path: path.clone(), height }),
+                    };
                 }
             }
         }
     }
 
-    Ok(())
+    if index == end {
+        ProofOutcome::Member { leaf_value: parent_hash }
+    } else if proof.is_empty() && commitment == Felt::ZERO {
+        ProofOutcome::NonMember { witness: None }
+    } else {
+        ProofOutcome::Invalid(ProofVerificationError::IncompleteProof { key, height: Height(end - index) })
+    }
+}
+
+/// Proofs of several keys of a trie, stored as a map of node hash to node.
+///
+/// The nodes shared by several proofs, such as the top of the trie, are only stored once.
+/// Proofs usually come as paths from the root to the keys: the hash of a node is then known from
+/// its parent, and only the nodes not in the multiproof yet are hashed.
+#[derive(Debug, Clone)]
+pub struct MultiProof<H> {
+    nodes: HashMap<Felt, TrieNode>,
+    /// Hashes of the first node of the proofs, i.e. the roots of the trie.
+    roots: HashSet<Felt>,
+    hash_function: PhantomData<H>,
+}
+
+impl<H> Default for MultiProof<H> {
+    fn default() -> Self {
+        Self { nodes: HashMap::new(), roots: HashSet::new(), hash_function: PhantomData }
+    }
+}
+
+impl<H: HashFunctionType> MultiProof<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a multiproof out of proofs of the same trie, or of different versions of the trie.
+    pub fn from_proofs<'a>(proofs: impl IntoIterator<Item = &'a [TrieNode]>) -> Self {
+        let mut multiproof = Self::new();
+        for proof in proofs {
+            multiproof.add_proof(proof);
+        }
+        multiproof
+    }
+
+    /// Adds the nodes of a proof going from the root of the trie to a key.
+    pub fn add_proof(&mut self, proof: &[TrieNode]) {
+        let mut known_hashes: Vec<Felt> = self.roots.iter().copied().collect();
+        for (index, node) in proof.iter().enumerate() {
+            let node_hash = known_hashes
+                .iter()
+                .find(|hash| self.nodes.get(*hash) == Some(node))
+                .copied()
+                .unwrap_or_else(|| node.hash::<H>());
+            self.nodes.entry(node_hash).or_insert_with(|| node.clone());
+            if index == 0 {
+                self.roots.insert(node_hash);
+            }
+            known_hashes = node.children();
+        }
+    }
+
+    /// Adds the nodes of another multiproof, without hashing them again.
+    pub fn merge(&mut self, other: Self) {
+        self.nodes.extend(other.nodes);
+        self.roots.extend(other.roots);
+    }
+
+    pub fn get(&self, node_hash: &Felt) -> Option<&TrieNode> {
+        self.nodes.get(node_hash)
+    }
+
+    pub fn contains(&self, node_hash: &Felt) -> bool {
+        self.nodes.contains_key(node_hash)
+    }
+
+    /// Number of distinct nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Converts the nodes into the commitment facts given to the OS, see `TrieNode::to_fact`.
+    pub fn commitment_facts(&self) -> HashMap<Felt, Vec<Felt>> {
+        self.nodes.iter().map(|(node_hash, node)| (*node_hash, node.to_fact())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTRACT_ADDRESS: Felt = Felt::from_hex_unchecked("0x1234");
+    const CLASS_HASH: Felt = Felt::from_hex_unchecked("0xc1a55");
+    const NONCE: Felt = Felt::from_hex_unchecked("0x3");
+    const STORAGE_ROOT: Felt = Felt::from_hex_unchecked("0x5706a6e");
+    const CLASS_ROOT: Felt = Felt::from_hex_unchecked("0xc1a55007");
+
+    /// Builds the proof of a contract trie holding a single contract, i.e. a single edge node
+    /// going from the root to the leaf of the contract.
+    fn single_contract_proof() -> PathfinderProof {
+        let leaf = contract_leaf_hash(CLASS_HASH, STORAGE_ROOT, NONCE);
+        let edge = TrieNode::Edge {
+            child: leaf,
+            path: EdgePath { len: DEFAULT_STORAGE_TREE_HEIGHT, value: CONTRACT_ADDRESS },
+        };
+        let contract_root = edge.hash::<PedersenHash>();
+
+        PathfinderProof {
+            state_commitment: compute_state_commitment(contract_root, CLASS_ROOT),
+            class_commitment: Some(CLASS_ROOT),
+            contract_proof: vec![edge],
+            contract_data: Some(ContractData { root: STORAGE_ROOT, storage_proofs: vec![] }),
+        }
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        let proof = single_contract_proof().contract_proof;
+        let root = proof[0].hash::<PedersenHash>();
+        let leaf_value = contract_leaf_hash(CLASS_HASH, STORAGE_ROOT, NONCE);
+
+        assert_eq!(verify_proof::<PedersenHash>(CONTRACT_ADDRESS, root, &proof), ProofOutcome::Member { leaf_value });
+        assert_eq!(
+            verify_proof::<PedersenHash>(CONTRACT_ADDRESS + Felt::ONE, root, &proof),
+            ProofOutcome::NonMember {
+                witness: Some(EdgeWitness {
+                    child: leaf_value,
+                    path: EdgePath { len: DEFAULT_STORAGE_TREE_HEIGHT, value: CONTRACT_ADDRESS },
+                    height: Height(0),
+                })
+            }
+        );
+        assert_eq!(
+            verify_proof::<PedersenHash>(CONTRACT_ADDRESS, Felt::ZERO, &[]),
+            ProofOutcome::NonMember { witness: None }
+        );
+        assert!(matches!(
+            verify_proof::<PedersenHash>(CONTRACT_ADDRESS, root + Felt::ONE, &proof),
+            ProofOutcome::Invalid(ProofVerificationError::InvalidChildNodeHash { .. })
+        ));
+        assert_eq!(
+            verify_proof::<PedersenHash>(CONTRACT_ADDRESS, root, &[]),
+            ProofOutcome::Invalid(ProofVerificationError::IncompleteProof {
+                key: CONTRACT_ADDRESS,
+                height: Height(DEFAULT_STORAGE_TREE_HEIGHT)
+            })
+        );
+        // Nodes past the leaf
+        let too_long_proof = [proof[0].clone(), proof[0].clone()];
+        assert_eq!(
+            verify_proof::<PedersenHash>(CONTRACT_ADDRESS, root, &too_long_proof),
+            ProofOutcome::Invalid(ProofVerificationError::ProofTooLong { key: CONTRACT_ADDRESS })
+        );
+    }
+
+    #[test]
+    fn test_multiproof() {
+        let leaf =
+            |value: u64| TrieNode::Edge { child: Felt::from(value), path: EdgePath { len: 250, value: Felt::ZERO } };
+        let (left, right) = (leaf(1), leaf(2));
+        let root = TrieNode::Binary { left: left.hash::<PedersenHash>(), right: right.hash::<PedersenHash>() };
+        let root_hash = root.hash::<PedersenHash>();
+
+        let mut multiproof = MultiProof::<PedersenHash>::from_proofs([
+            [root.clone(), left.clone()].as_slice(),
+            &[root.clone(), right.clone()],
+        ]);
+        // The root is shared by both proofs
+        assert_eq!(multiproof.len(), 3);
+        assert_eq!(multiproof.get(&root_hash), Some(&root));
+
+        let facts = multiproof.commitment_facts();
+        assert_eq!(facts[&root_hash], vec![left.hash::<PedersenHash>(), right.hash::<PedersenHash>()]);
+        assert_eq!(facts[&left.hash::<PedersenHash>()], vec![Felt::from(250), Felt::ZERO, Felt::ONE]);
+
+        // Nodes of another version of the trie
+        let other_root = TrieNode::Edge { child: Felt::THREE, path: EdgePath { len: 251, value: Felt::ZERO } };
+        multiproof.merge(MultiProof::from_proofs([[other_root.clone()].as_slice()]));
+        assert_eq!(multiproof.len(), 4);
+        assert!(multiproof.contains(&other_root.hash::<PedersenHash>()));
+    }
+
+    #[test]
+    fn test_verify_contract() {
+        let proof = single_contract_proof();
+        let state_root = proof.state_commitment;
+
+        assert_eq!(proof.verify_contract(state_root, CONTRACT_ADDRESS, CLASS_HASH, NONCE, &[]), Ok(()));
+        assert!(matches!(
+            proof.verify_contract(state_root, CONTRACT_ADDRESS, CLASS_HASH, NONCE + Felt::ONE, &[]),
+            Err(ContractProofError::ContractLeafMismatch { .. })
+        ));
+        assert!(matches!(
+            proof.verify_contract(state_root + Felt::ONE, CONTRACT_ADDRESS, CLASS_HASH, NONCE, &[]),
+            Err(ContractProofError::StateRootMismatch { .. })
+        ));
+
+        // Another contract is not in the trie
+        let other_address = CONTRACT_ADDRESS + Felt::ONE;
+        let missing_contract_proof = PathfinderProof { contract_data: None, ..proof.clone() };
+        assert_eq!(
+            missing_contract_proof.verify_contract(state_root, other_address, Felt::ZERO, Felt::ZERO, &[]),
+            Ok(())
+        );
+        assert!(matches!(
+            missing_contract_proof.verify_contract(state_root, other_address, CLASS_HASH, Felt::ZERO, &[]),
+            Err(ContractProofError::MissingContract { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_contract_inconsistent_state_commitment() {
+        let proof = PathfinderProof { class_commitment: Some(CLASS_ROOT + Felt::ONE), ..single_contract_proof() };
+        let state_root = proof.state_commitment;
+
+        assert!(matches!(
+            proof.verify_contract(state_root, CONTRACT_ADDRESS, CLASS_HASH, NONCE, &[]),
+            Err(ContractProofError::StateCommitmentMismatch { .. })
+        ));
+    }
 }
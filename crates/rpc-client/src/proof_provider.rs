@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use starknet_types_core::felt::Felt;
+
+use crate::pathfinder::client::{ClientError, PathfinderRpcClient};
+use crate::pathfinder::proofs::{PathfinderClassProof, PathfinderProof};
+
+/// RPC API used to fetch storage and class proofs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProofApi {
+    /// `pathfinder_getProof` and `pathfinder_getClassProof`, only available on Pathfinder nodes.
+    #[default]
+    Pathfinder,
+    /// `starknet_getStorageProof`, part of the Starknet RPC spec since v0.8.
+    Starknet,
+}
+
+/// Source of the storage and class proofs required to build the OS input.
+///
+/// Proofs are returned in the Pathfinder format whatever the underlying API, so callers do not
+/// need to know which node they talk to.
+#[async_trait]
+pub trait ProofProvider: Send + Sync {
+    /// Returns the proof of the contract state and of the specified storage keys of the contract.
+    /// Storage proofs are returned in the same order as `keys`.
+    async fn get_proof(
+        &self,
+        block_number: u64,
+        contract_address: Felt,
+        keys: &[Felt],
+    ) -> Result<PathfinderProof, ClientError>;
+
+    /// Returns the proofs of several contracts, in the order of `requests`.
+    /// Providers that support it fetch all the proofs at once, the default implementation fetches
+    /// them one by one.
+    /// The outer error is returned when the whole batch fails, the inner ones when the proof of
+    /// a single request could not be fetched.
+    async fn get_proofs(
+        &self,
+        block_number: u64,
+        requests: &[(Felt, Vec<Felt>)],
+    ) -> Result<Vec<Result<PathfinderProof, ClientError>>, ClientError> {
+        let mut proofs = Vec::with_capacity(requests.len());
+        for (contract_address, keys) in requests {
+            proofs.push(self.get_proof(block_number, *contract_address, keys).await);
+        }
+        Ok(proofs)
+    }
+
+    /// Returns the proof of the specified class in the class trie.
+    async fn get_class_proof(&self, block_number: u64, class_hash: &Felt) -> Result<PathfinderClassProof, ClientError>;
+
+    /// Returns the proofs of several classes, in the order of `class_hashes`, with the same
+    /// batching and errors as `get_proofs`.
+    async fn get_class_proofs(
+        &self,
+        block_number: u64,
+        class_hashes: &[Felt],
+    ) -> Result<Vec<Result<PathfinderClassProof, ClientError>>, ClientError> {
+        let mut proofs = Vec::with_capacity(class_hashes.len());
+        for class_hash in class_hashes {
+            proofs.push(self.get_class_proof(block_number, class_hash).await);
+        }
+        Ok(proofs)
+    }
+}
+
+#[async_trait]
+impl ProofProvider for PathfinderRpcClient {
+    async fn get_proof(
+        &self,
+        block_number: u64,
+        contract_address: Felt,
+        keys: &[Felt],
+    ) -> Result<PathfinderProof, ClientError> {
+        PathfinderRpcClient::get_proof(self, block_number, contract_address, keys).await
+    }
+
+    async fn get_proofs(
+        &self,
+        block_number: u64,
+        requests: &[(Felt, Vec<Felt>)],
+    ) -> Result<Vec<Result<PathfinderProof, ClientError>>, ClientError> {
+        PathfinderRpcClient::get_proofs(self, block_number, requests).await
+    }
+
+    async fn get_class_proof(&self, block_number: u64, class_hash: &Felt) -> Result<PathfinderClassProof, ClientError> {
+        PathfinderRpcClient::get_class_proof(self, block_number, class_hash).await
+    }
+
+    async fn get_class_proofs(
+        &self,
+        block_number: u64,
+        class_hashes: &[Felt],
+    ) -> Result<Vec<Result<PathfinderClassProof, ClientError>>, ClientError> {
+        PathfinderRpcClient::get_class_proofs(self, block_number, class_hashes).await
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+/// Version of the recording archive format.
+/// Bump it whenever the layout of `RpcRecording` changes, older archives will then be rejected
+/// on load instead of being replayed incorrectly.
+pub const RPC_RECORDING_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordingError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Unsupported recording version {found} (expected {expected})")]
+    UnsupportedVersion { found: u32, expected: u32 },
+}
+
+/// A single JSON-RPC exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub method: String,
+    pub params: serde_json::Value,
+    /// The full JSON-RPC response body. JSON-RPC errors (ex: `ContractNotFound`) are part of the
+    /// recording as well, as callers rely on them to detect missing contracts.
+    pub response: serde_json::Value,
+}
+
+/// An archive of all the RPC responses used for a given run.
+///
+/// A recording is produced by an `RpcClient` built with `RpcClient::new_recording` and can then
+/// be fed to `RpcClient::from_recording` to answer the same requests without network access.
+/// Archives are stored as gzipped JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRecording {
+    version: u32,
+    /// Recorded calls, indexed by request key (see `request_key`).
+    /// A `BTreeMap` keeps the archive content stable across runs.
+    calls: BTreeMap<String, RecordedCall>,
+}
+
+impl Default for RpcRecording {
+    fn default() -> Self {
+        Self { version: RPC_RECORDING_VERSION, calls: Default::default() }
+    }
+}
+
+/// Builds the key identifying a request in the recording.
+fn request_key(method: &str, params: &serde_json::Value) -> String {
+    format!("{method}:{params}")
+}
+
+impl RpcRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    pub fn calls(&self) -> impl Iterator<Item = &RecordedCall> {
+        self.calls.values()
+    }
+
+    /// Stores the response to a request. Later responses to the same request override
+    /// earlier ones.
+    pub fn insert(&mut self, method: &str, params: serde_json::Value, response: serde_json::Value) {
+        let key = request_key(method, &params);
+        self.calls.insert(key, RecordedCall { method: method.to_string(), params, response });
+    }
+
+    /// Returns the recorded response for a request, if any.
+    pub fn get(&self, method: &str, params: &serde_json::Value) -> Option<&serde_json::Value> {
+        self.calls.get(&request_key(method, params)).map(|call| &call.response)
+    }
+
+    /// Merges another recording into this one.
+    pub fn extend(&mut self, other: RpcRecording) {
+        self.calls.extend(other.calls);
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), RecordingError> {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        serde_json::to_writer(&mut encoder, self)?;
+        encoder.finish()?;
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, RecordingError> {
+        let recording: Self = serde_json::from_reader(GzDecoder::new(reader))?;
+        if recording.version != RPC_RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion {
+                found: recording.version,
+                expected: RPC_RECORDING_VERSION,
+            });
+        }
+
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), RecordingError> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, RecordingError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_recording_roundtrip() {
+        let mut recording = RpcRecording::new();
+        let params = json!({ "block_id": { "block_number": 1 } });
+        let response = json!({ "jsonrpc": "2.0", "id": 0, "result": "0x1" });
+        recording.insert("starknet_getBlockWithTxs", params.clone(), response.clone());
+
+        let mut bytes = vec![];
+        recording.write_to(&mut bytes).unwrap();
+        let loaded = RpcRecording::read_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get("starknet_getBlockWithTxs", &params), Some(&response));
+        assert_eq!(loaded.get("starknet_getBlockWithTxHashes", &params), None);
+    }
+
+    #[test]
+    fn test_recording_rejects_unknown_version() {
+        let recording = RpcRecording { version: RPC_RECORDING_VERSION + 1, calls: Default::default() };
+
+        let mut bytes = vec![];
+        recording.write_to(&mut bytes).unwrap();
+
+        assert!(matches!(RpcRecording::read_from(bytes.as_slice()), Err(RecordingError::UnsupportedVersion { .. })));
+    }
+}
@@ -0,0 +1,393 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+/// Version of the Starknet RPC spec implemented by the node.
+///
+/// starknet-rs deserializes responses with the v0.7 schema. Responses of nodes using another
+/// version are converted to this schema by `RpcSpecVersion::normalize_response` before reaching
+/// starknet-rs, see the method for the fields that differ.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RpcSpecVersion {
+    V0_6,
+    #[default]
+    V0_7,
+    V0_8,
+}
+
+impl RpcSpecVersion {
+    /// Path of the endpoint of this version on Pathfinder and Juno nodes, ex: `rpc/v0_7`.
+    pub fn path(&self) -> &'static str {
+        match self {
+            Self::V0_6 => "rpc/v0_6",
+            Self::V0_7 => "rpc/v0_7",
+            Self::V0_8 => "rpc/v0_8",
+        }
+    }
+
+    /// Converts the response to a request in this version to the v0.7 schema.
+    ///
+    /// * v0.6 blocks have no L1 data gas price nor DA mode: the price is set to zero and the DA mode
+    ///   to `CALLDATA`. Receipts and traces have no data availability resources, they are set to
+    ///   zero as well, and traces have no execution resources at the transaction level.
+    /// * v0.8 resource bounds have an L1 data gas bound that v0.7 does not support. Execution
+    ///   resources are expressed in gas instead of Cairo resources: the L1 gas and L1 data gas are
+    ///   kept as data availability resources and Cairo resources are set to zero. As the Cairo
+    ///   resources cannot be derived from the L2 gas, responses with a non-zero L1 data gas bound or
+    ///   a non-zero L2 gas are rejected with a `LossyConversion` error instead of being converted.
+    ///
+    /// Error responses and responses to other methods are left untouched.
+    pub fn normalize_response(&self, method: &str, response: &mut Value) -> Result<(), LossyConversion> {
+        if *self == Self::V0_7 {
+            return Ok(());
+        }
+        let Some(result) = response.get_mut("result") else {
+            return Ok(());
+        };
+
+        match method {
+            "starknet_getBlockWithTxHashes" | "starknet_getBlockWithTxs" | "starknet_getBlockWithReceipts" => {
+                self.normalize_block(result)
+            }
+            "starknet_getTransactionByHash" | "starknet_getTransactionByBlockIdAndIndex" => {
+                self.normalize_transaction(result)
+            }
+            "starknet_getTransactionReceipt" => self.normalize_receipt(result),
+            "starknet_traceTransaction" => self.normalize_trace(result, None),
+            "starknet_traceBlockTransactions" => {
+                for trace in as_array_mut(result) {
+                    let transaction_hash = trace.get("transaction_hash").cloned();
+                    if let Some(trace_root) = trace.get_mut("trace_root") {
+                        self.normalize_trace(trace_root, transaction_hash)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn normalize_block(&self, block: &mut Value) -> Result<(), LossyConversion> {
+        if let (Self::V0_6, Some(header)) = (self, block.as_object_mut()) {
+            header
+                .entry("l1_data_gas_price")
+                .or_insert_with(|| json!({ "price_in_fri": "0x0", "price_in_wei": "0x0" }));
+            header.entry("l1_da_mode").or_insert_with(|| json!("CALLDATA"));
+        }
+
+        for transaction in block.get_mut("transactions").map(as_array_mut).unwrap_or_default() {
+            // starknet_getBlockWithReceipts returns transactions along with their receipt
+            if transaction.get("receipt").is_some() {
+                self.normalize_transaction(&mut transaction["transaction"])?;
+                self.normalize_receipt(&mut transaction["receipt"])?;
+            } else {
+                self.normalize_transaction(transaction)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn normalize_transaction(&self, transaction: &mut Value) -> Result<(), LossyConversion> {
+        let transaction_hash = transaction.get("transaction_hash").cloned();
+        if let (Self::V0_8, Some(Value::Object(resource_bounds))) = (self, transaction.get_mut("resource_bounds")) {
+            // The bound is part of the transaction hash, dropping it would change the hash computed by the OS
+            if let Some(l1_data_gas) = resource_bounds.remove("l1_data_gas") {
+                if ["max_amount", "max_price_per_unit"].iter().any(|field| !is_zero(&l1_data_gas[field])) {
+                    return Err(LossyConversion {
+                        field: "resource_bounds.l1_data_gas",
+                        value: l1_data_gas,
+                        transaction_hash,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn normalize_receipt(&self, receipt: &mut Value) -> Result<(), LossyConversion> {
+        let transaction_hash = receipt.get("transaction_hash").cloned();
+        match receipt.get_mut("execution_resources") {
+            Some(execution_resources) => self.normalize_execution_resources(execution_resources, transaction_hash),
+            None => Ok(()),
+        }
+    }
+
+    fn normalize_trace(&self, trace: &mut Value, transaction_hash: Option<Value>) -> Result<(), LossyConversion> {
+        let Some(trace) = trace.as_object_mut() else {
+            return Ok(());
+        };
+
+        for invocation_field in [
+            "validate_invocation",
+            "execute_invocation",
+            "constructor_invocation",
+            "function_invocation",
+            "fee_transfer_invocation",
+        ] {
+            if let Some(invocation) = trace.get_mut(invocation_field) {
+                self.normalize_function_invocation(invocation, &transaction_hash)?;
+            }
+        }
+
+        let execution_resources = trace.entry("execution_resources").or_insert_with(|| json!({ "steps": 0 }));
+        self.normalize_execution_resources(execution_resources, transaction_hash)
+    }
+
+    fn normalize_function_invocation(
+        &self,
+        invocation: &mut Value,
+        transaction_hash: &Option<Value>,
+    ) -> Result<(), LossyConversion> {
+        let Some(invocation) = invocation.as_object_mut() else {
+            return Ok(());
+        };
+        // Reverted executions only hold a revert reason
+        if invocation.contains_key("revert_reason") {
+            return Ok(());
+        }
+
+        let computation_resources = invocation.entry("execution_resources").or_insert_with(|| json!({ "steps": 0 }));
+        if *self == Self::V0_8 {
+            check_no_l2_gas(computation_resources, transaction_hash.clone())?;
+            *computation_resources = json!({ "steps": 0 });
+        }
+
+        for call in invocation.get_mut("calls").map(as_array_mut).unwrap_or_default() {
+            self.normalize_function_invocation(call, transaction_hash)?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts the execution resources of a receipt or of a transaction trace.
+    fn normalize_execution_resources(
+        &self,
+        execution_resources: &mut Value,
+        transaction_hash: Option<Value>,
+    ) -> Result<(), LossyConversion> {
+        match self {
+            Self::V0_6 => {
+                if let Some(resources) = execution_resources.as_object_mut() {
+                    resources.entry("data_availability").or_insert_with(|| json!({ "l1_gas": 0, "l1_data_gas": 0 }));
+                }
+            }
+            Self::V0_7 => {}
+            Self::V0_8 => {
+                check_no_l2_gas(execution_resources, transaction_hash)?;
+                let l1_gas = execution_resources.get("l1_gas").cloned().unwrap_or_else(|| json!(0));
+                let l1_data_gas = execution_resources.get("l1_data_gas").cloned().unwrap_or_else(|| json!(0));
+                *execution_resources =
+                    json!({ "steps": 0, "data_availability": { "l1_gas": l1_gas, "l1_data_gas": l1_data_gas } });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A v0.8 response that cannot be converted to the v0.7 schema without losing information,
+/// see `RpcSpecVersion::normalize_response`.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Cannot convert {field} = {value} (transaction {}) to the RPC v0.7 schema, use a v0.7 endpoint",
+    transaction_hash.as_ref().map(Value::to_string).unwrap_or_else(|| "unknown".to_string())
+)]
+pub struct LossyConversion {
+    pub field: &'static str,
+    pub value: Value,
+    pub transaction_hash: Option<Value>,
+}
+
+/// v0.8 execution resources hold the L2 gas consumed instead of Cairo resources. The Cairo
+/// resources cannot be derived from it, they can only be set to zero if no L2 gas was consumed.
+fn check_no_l2_gas(execution_resources: &Value, transaction_hash: Option<Value>) -> Result<(), LossyConversion> {
+    match execution_resources.get("l2_gas") {
+        Some(l2_gas) if !is_zero(l2_gas) => {
+            Err(LossyConversion { field: "execution_resources.l2_gas", value: l2_gas.clone(), transaction_hash })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether a JSON amount is zero: amounts are either numbers or hex strings depending on the field.
+fn is_zero(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Number(number) => number.as_u64() == Some(0),
+        Value::String(hex) => hex.trim_start_matches("0x").trim_start_matches('0').is_empty(),
+        _ => false,
+    }
+}
+
+fn as_array_mut(value: &mut Value) -> std::slice::IterMut<'_, Value> {
+    match value {
+        Value::Array(values) => values.iter_mut(),
+        _ => Default::default(),
+    }
+}
+
+impl fmt::Display for RpcSpecVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = match self {
+            Self::V0_6 => "0.6",
+            Self::V0_7 => "0.7",
+            Self::V0_8 => "0.8",
+        };
+        write!(f, "{version}")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unsupported RPC spec version: {0}, expected 0.6, 0.7 or 0.8")]
+pub struct UnsupportedSpecVersion(String);
+
+impl FromStr for RpcSpecVersion {
+    type Err = UnsupportedSpecVersion;
+
+    /// Parses `0.7`, `v0.7`, `0_7` or `v0_7`, as well as full versions such as `0.7.1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let version = s.trim_start_matches('v').replace('_', ".");
+        let mut parts = version.split('.');
+        match (parts.next(), parts.next()) {
+            (Some("0"), Some("6")) => Ok(Self::V0_6),
+            (Some("0"), Some("7")) => Ok(Self::V0_7),
+            (Some("0"), Some("8")) => Ok(Self::V0_8),
+            _ => Err(UnsupportedSpecVersion(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("0.6", RpcSpecVersion::V0_6)]
+    #[case("v0_7", RpcSpecVersion::V0_7)]
+    #[case("0.8.0", RpcSpecVersion::V0_8)]
+    fn test_parse_spec_version(#[case] version: &str, #[case] expected: RpcSpecVersion) {
+        assert_eq!(version.parse::<RpcSpecVersion>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_unsupported_spec_version() {
+        assert!("0.5".parse::<RpcSpecVersion>().is_err());
+        assert!("1.0".parse::<RpcSpecVersion>().is_err());
+    }
+
+    #[test]
+    fn test_normalize_v0_6_block() {
+        let mut response = json!({ "result": { "block_number": 1, "transactions": [] } });
+        RpcSpecVersion::V0_6.normalize_response("starknet_getBlockWithTxs", &mut response).unwrap();
+
+        assert_eq!(response["result"]["l1_data_gas_price"], json!({ "price_in_fri": "0x0", "price_in_wei": "0x0" }));
+        assert_eq!(response["result"]["l1_da_mode"], json!("CALLDATA"));
+    }
+
+    #[test]
+    fn test_normalize_v0_8_block_with_receipts() {
+        let mut response = json!({ "result": { "transactions": [{
+            "transaction": {
+                "resource_bounds": {
+                    "l1_gas": { "max_amount": "0x1", "max_price_per_unit": "0x2" },
+                    "l1_data_gas": { "max_amount": "0x0", "max_price_per_unit": "0x0" },
+                    "l2_gas": { "max_amount": "0x0", "max_price_per_unit": "0x0" }
+                }
+            },
+            "receipt": { "execution_resources": { "l1_gas": 5, "l1_data_gas": 6, "l2_gas": 0 } }
+        }] } });
+        RpcSpecVersion::V0_8.normalize_response("starknet_getBlockWithReceipts", &mut response).unwrap();
+
+        let transaction = &response["result"]["transactions"][0];
+        assert_eq!(transaction["transaction"]["resource_bounds"].get("l1_data_gas"), None);
+        assert_eq!(transaction["transaction"]["resource_bounds"]["l1_gas"]["max_amount"], json!("0x1"));
+        assert_eq!(
+            transaction["receipt"]["execution_resources"],
+            json!({ "steps": 0, "data_availability": { "l1_gas": 5, "l1_data_gas": 6 } })
+        );
+    }
+
+    #[test]
+    fn test_normalize_v0_8_trace() {
+        let invocation = |calls: Vec<Value>| json!({ "execution_resources": { "l1_gas": 1, "l2_gas": 0 }, "is_reverted": false, "calls": calls });
+        let mut response = json!({ "result": [{
+            "transaction_hash": "0x1",
+            "trace_root": {
+                "type": "INVOKE",
+                "validate_invocation": invocation(vec![]),
+                "execute_invocation": { "revert_reason": "Out of gas" },
+                "fee_transfer_invocation": invocation(vec![invocation(vec![])]),
+                "execution_resources": { "l1_gas": 3, "l1_data_gas": 4, "l2_gas": "0x0" }
+            }
+        }] });
+        RpcSpecVersion::V0_8.normalize_response("starknet_traceBlockTransactions", &mut response).unwrap();
+
+        let trace = &response["result"][0]["trace_root"];
+        assert_eq!(trace["validate_invocation"]["execution_resources"], json!({ "steps": 0 }));
+        assert_eq!(trace["execute_invocation"], json!({ "revert_reason": "Out of gas" }));
+        assert_eq!(trace["fee_transfer_invocation"]["calls"][0]["execution_resources"], json!({ "steps": 0 }));
+        assert_eq!(
+            trace["execution_resources"],
+            json!({ "steps": 0, "data_availability": { "l1_gas": 3, "l1_data_gas": 4 } })
+        );
+    }
+
+    #[test]
+    fn test_normalize_error_response() {
+        let error = json!({ "error": { "code": 20, "message": "Contract not found" } });
+        let mut response = error.clone();
+        RpcSpecVersion::V0_6.normalize_response("starknet_getTransactionReceipt", &mut response).unwrap();
+
+        assert_eq!(response, error);
+    }
+
+    #[rstest]
+    #[case::l1_data_gas_bound(
+        "starknet_getTransactionByHash",
+        json!({ "result": {
+            "transaction_hash": "0x1",
+            "resource_bounds": {
+                "l1_gas": { "max_amount": "0x1", "max_price_per_unit": "0x2" },
+                "l1_data_gas": { "max_amount": "0x3", "max_price_per_unit": "0x0" },
+                "l2_gas": { "max_amount": "0x0", "max_price_per_unit": "0x0" }
+            }
+        } }),
+        "resource_bounds.l1_data_gas"
+    )]
+    #[case::receipt_l2_gas(
+        "starknet_getTransactionReceipt",
+        json!({ "result": {
+            "transaction_hash": "0x1",
+            "execution_resources": { "l1_gas": 5, "l1_data_gas": 6, "l2_gas": 7 }
+        } }),
+        "execution_resources.l2_gas"
+    )]
+    #[case::invocation_l2_gas(
+        "starknet_traceBlockTransactions",
+        json!({ "result": [{
+            "transaction_hash": "0x1",
+            "trace_root": {
+                "type": "INVOKE",
+                "execute_invocation": { "execution_resources": { "l1_gas": 0, "l2_gas": "0x2" }, "calls": [] },
+                "execution_resources": { "l1_gas": 3, "l1_data_gas": 4, "l2_gas": 0 }
+            }
+        }] }),
+        "execution_resources.l2_gas"
+    )]
+    fn test_normalize_v0_8_lossy_conversion(
+        #[case] method: &str,
+        #[case] response: Value,
+        #[case] expected_field: &str,
+    ) {
+        let mut response = response;
+        let error = RpcSpecVersion::V0_8.normalize_response(method, &mut response).unwrap_err();
+
+        assert_eq!(error.field, expected_field);
+        assert_eq!(error.transaction_hash, Some(json!("0x1")));
+    }
+}
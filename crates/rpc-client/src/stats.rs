@@ -0,0 +1,63 @@
+use std::ops::{Add, Sub};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+/// Counters of the requests sent by an `RpcClient`, shared by all its transports.
+#[derive(Debug, Default)]
+pub struct RpcStats {
+    requests: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+/// Values of the `RpcStats` counters at a given time.
+/// Subtract two snapshots to get the traffic in between.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RpcStatsSnapshot {
+    pub requests: u64,
+    /// Size of the HTTP request bodies, 0 when replaying a recording.
+    pub bytes_sent: u64,
+    /// Size of the HTTP response bodies, 0 when replaying a recording.
+    pub bytes_received: u64,
+}
+
+impl RpcStats {
+    pub fn record_request(&self, bytes_sent: usize, bytes_received: usize) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes_sent as u64, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes_received as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> RpcStatsSnapshot {
+        RpcStatsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Add for RpcStatsSnapshot {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            requests: self.requests + rhs.requests,
+            bytes_sent: self.bytes_sent + rhs.bytes_sent,
+            bytes_received: self.bytes_received + rhs.bytes_received,
+        }
+    }
+}
+
+impl Sub for RpcStatsSnapshot {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            requests: self.requests.saturating_sub(rhs.requests),
+            bytes_sent: self.bytes_sent.saturating_sub(rhs.bytes_sent),
+            bytes_received: self.bytes_received.saturating_sub(rhs.bytes_received),
+        }
+    }
+}
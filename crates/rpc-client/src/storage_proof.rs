@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use starknet_os::config::GLOBAL_STATE_VERSION;
+use starknet_os::crypto::poseidon::poseidon_hash_many_bytes;
+use starknet_types_core::felt::Felt;
+
+use crate::pathfinder::client::ClientError;
+use crate::pathfinder::proofs::{ContractData, EdgePath, PathfinderClassProof, PathfinderProof, TrieNode};
+use crate::proof_provider::ProofProvider;
+use crate::transport::RpcTransport;
+
+/// A Merkle-Patricia trie node, as returned by `starknet_getStorageProof`.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MerkleNode {
+    Binary { left: Felt, right: Felt },
+    Edge { path: Felt, length: u64, child: Felt },
+}
+
+impl From<MerkleNode> for TrieNode {
+    fn from(node: MerkleNode) -> Self {
+        match node {
+            MerkleNode::Binary { left, right } => TrieNode::Binary { left, right },
+            MerkleNode::Edge { path, length, child } => {
+                TrieNode::Edge { child, path: EdgePath { len: length, value: path } }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NodeHashToNode {
+    pub node_hash: Felt,
+    pub node: MerkleNode,
+}
+
+/// Contract state values required to compute the leaf of a contract in the contract trie.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContractLeafData {
+    pub nonce: Felt,
+    pub class_hash: Felt,
+    pub storage_root: Felt,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContractsProof {
+    /// Nodes of the contract trie, for all the requested contracts.
+    pub nodes: Vec<NodeHashToNode>,
+    /// Leaf data of each requested contract, in the order of the request.
+    pub contract_leaves_data: Vec<ContractLeafData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GlobalRoots {
+    pub contracts_tree_root: Felt,
+    pub classes_tree_root: Felt,
+    pub block_hash: Felt,
+}
+
+/// Response to a `starknet_getStorageProof` call.
+///
+/// Unlike Pathfinder proofs, nodes are not ordered from the root to the leaf but returned as
+/// a set of nodes indexed by their hash, shared between all the requested keys.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageProof {
+    pub classes_proof: Vec<NodeHashToNode>,
+    pub contracts_proof: ContractsProof,
+    /// Nodes of the storage trie of each requested contract, in the order of the request.
+    pub contracts_storage_proofs: Vec<Vec<NodeHashToNode>>,
+    pub global_roots: GlobalRoots,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractStorageKeys {
+    pub contract_address: Felt,
+    pub storage_keys: Vec<Felt>,
+}
+
+/// Height of the contract, class and storage tries.
+const TREE_HEIGHT: usize = 251;
+
+fn nodes_by_hash(nodes: &[NodeHashToNode]) -> HashMap<Felt, TrieNode> {
+    nodes.iter().map(|node| (node.node_hash, node.node.clone().into())).collect()
+}
+
+/// Rebuilds the path from the root of a trie to the specified key, in the same format as
+/// Pathfinder proofs. The path stops at the leaf or at the first edge node that diverges from
+/// the key, in which case this is a proof of non-membership.
+/// The nodes come from the node and are not trusted: edges that go past the leaves or do not go
+/// down the trie are rejected, and the path never exceeds the height of the trie.
+fn path_to_key(nodes: &HashMap<Felt, TrieNode>, root: Felt, key: Felt) -> Result<Vec<TrieNode>, ClientError> {
+    let bits = key.to_bits_be();
+
+    // The tree height is 251, so the first 5 bits are ignored.
+    let mut index = bits.len() - TREE_HEIGHT;
+    let mut node_hash = root;
+    let mut path = vec![];
+
+    // Each node goes at least one level down, so a valid path has at most `TREE_HEIGHT` nodes
+    for _ in 0..TREE_HEIGHT {
+        let Some(node) = nodes.get(&node_hash) else {
+            break;
+        };
+        path.push(node.clone());
+        match node {
+            TrieNode::Binary { left, right } => {
+                node_hash = if bits[index] { *right } else { *left };
+                index += 1;
+            }
+            TrieNode::Edge { child, path: edge_path } => {
+                let edge_len = edge_path.len as usize;
+                let height = bits.len() - index;
+                if edge_len == 0 || edge_len > height {
+                    return Err(ClientError::CustomError(format!(
+                        "Invalid proof for key 0x{key:x}: edge of length {} at height {height}",
+                        edge_path.len
+                    )));
+                }
+                let edge_bits = edge_path.value.to_bits_be();
+                if edge_bits[edge_bits.len() - edge_len..] != bits[index..index + edge_len] {
+                    break;
+                }
+                node_hash = *child;
+                index += edge_len;
+            }
+        }
+
+        if index >= bits.len() {
+            break;
+        }
+    }
+
+    Ok(path)
+}
+
+/// Computes the global state commitment from the roots of the contract and class tries.
+pub fn compute_state_commitment(contracts_tree_root: Felt, classes_tree_root: Felt) -> Felt {
+    // Backward compatibility, blocks from before the introduction of the class trie only
+    // commit to the contract trie.
+    if classes_tree_root == Felt::ZERO {
+        return contracts_tree_root;
+    }
+
+    let hash = poseidon_hash_many_bytes(&[
+        &Felt::from_bytes_be_slice(GLOBAL_STATE_VERSION).to_bytes_be(),
+        &contracts_tree_root.to_bytes_be(),
+        &classes_tree_root.to_bytes_be(),
+    ])
+    .expect("felts are valid field elements");
+
+    Felt::from_bytes_be_slice(&hash)
+}
+
+impl StorageProof {
+    /// Converts the proof of a contract into the Pathfinder format.
+    /// `contract_index` is the position of the contract in `contract_addresses`, `storage_index`
+    /// its position in `contracts_storage_keys` if storage keys were requested for this contract,
+    /// `keys` the storage keys to extract from the proof.
+    pub fn to_pathfinder_proof(
+        &self,
+        contract_index: usize,
+        storage_index: Option<usize>,
+        contract_address: Felt,
+        keys: &[Felt],
+    ) -> Result<PathfinderProof, ClientError> {
+        let global_roots = &self.global_roots;
+        let contract_nodes = nodes_by_hash(&self.contracts_proof.nodes);
+        let contract_proof = path_to_key(&contract_nodes, global_roots.contracts_tree_root, contract_address)?;
+
+        let contract_data = self
+            .contracts_proof
+            .contract_leaves_data
+            .get(contract_index)
+            // Non-existing contracts are returned with zero values
+            .filter(|leaf| leaf.class_hash != Felt::ZERO || leaf.nonce != Felt::ZERO || leaf.storage_root != Felt::ZERO)
+            .map(|leaf| {
+                let storage_nodes = storage_index
+                    .and_then(|index| self.contracts_storage_proofs.get(index))
+                    .map(|nodes| nodes_by_hash(nodes))
+                    .unwrap_or_default();
+                let storage_proofs = keys
+                    .iter()
+                    .map(|key| path_to_key(&storage_nodes, leaf.storage_root, *key))
+                    .collect::<Result<_, _>>()?;
+                Ok::<_, ClientError>(ContractData { root: leaf.storage_root, storage_proofs })
+            })
+            .transpose()?;
+
+        Ok(PathfinderProof {
+            state_commitment: compute_state_commitment(
+                global_roots.contracts_tree_root,
+                global_roots.classes_tree_root,
+            ),
+            class_commitment: Some(global_roots.classes_tree_root),
+            contract_proof,
+            contract_data,
+        })
+    }
+
+    /// Converts the proof of a class into the Pathfinder format.
+    pub fn to_pathfinder_class_proof(&self, class_hash: Felt) -> Result<PathfinderClassProof, ClientError> {
+        let class_nodes = nodes_by_hash(&self.classes_proof);
+        let class_proof = path_to_key(&class_nodes, self.global_roots.classes_tree_root, class_hash)?;
+
+        Ok(PathfinderClassProof { class_commitment: self.global_roots.classes_tree_root, class_proof })
+    }
+}
+
+/// Client for the `starknet_getStorageProof` method, introduced in v0.8 of the Starknet RPC spec
+/// and supported by most node implementations (Juno, Madara, Pathfinder, ...).
+pub struct StorageProofRpcClient {
+    transport: RpcTransport,
+}
+
+impl StorageProofRpcClient {
+    pub fn new(base_url: &str) -> Self {
+        let rpc_url = format!("{}/rpc/v0_8", base_url);
+        let url = Url::parse(&rpc_url).unwrap_or_else(|e| panic!("Could not parse RPC URL ({}): {}", rpc_url, e));
+
+        Self::with_transport(RpcTransport::new_http(url))
+    }
+
+    pub fn with_transport(transport: RpcTransport) -> Self {
+        Self { transport }
+    }
+
+    /// Fetches the proofs of all the specified classes, contracts and storage keys in one call.
+    pub async fn get_storage_proof(
+        &self,
+        block_number: u64,
+        class_hashes: &[Felt],
+        contract_addresses: &[Felt],
+        contracts_storage_keys: &[ContractStorageKeys],
+    ) -> Result<StorageProof, ClientError> {
+        self.transport
+            .request(
+                "starknet_getStorageProof",
+                json!({
+                    "block_id": { "block_number": block_number },
+                    "class_hashes": class_hashes,
+                    "contract_addresses": contract_addresses,
+                    "contracts_storage_keys": contracts_storage_keys,
+                }),
+            )
+            .await
+    }
+}
+
+#[async_trait]
+impl ProofProvider for StorageProofRpcClient {
+    async fn get_proof(
+        &self,
+        block_number: u64,
+        contract_address: Felt,
+        keys: &[Felt],
+    ) -> Result<PathfinderProof, ClientError> {
+        let mut proofs = self.get_proofs(block_number, &[(contract_address, keys.to_vec())]).await?;
+        proofs.remove(0)
+    }
+
+    /// Fetches the proofs of all the requests in one call. The keys requested for the same
+    /// contract are merged, the proof of each request is extracted from the shared nodes.
+    async fn get_proofs(
+        &self,
+        block_number: u64,
+        requests: &[(Felt, Vec<Felt>)],
+    ) -> Result<Vec<Result<PathfinderProof, ClientError>>, ClientError> {
+        let mut contract_addresses: Vec<Felt> = vec![];
+        let mut contracts_storage_keys: Vec<ContractStorageKeys> = vec![];
+        for (contract_address, keys) in requests {
+            if !contract_addresses.contains(contract_address) {
+                contract_addresses.push(*contract_address);
+            }
+            if keys.is_empty() {
+                continue;
+            }
+            match contracts_storage_keys
+                .iter_mut()
+                .find(|storage_keys| storage_keys.contract_address == *contract_address)
+            {
+                Some(storage_keys) => storage_keys.storage_keys.extend(keys),
+                None => contracts_storage_keys
+                    .push(ContractStorageKeys { contract_address: *contract_address, storage_keys: keys.clone() }),
+            }
+        }
+
+        let storage_proof =
+            self.get_storage_proof(block_number, &[], &contract_addresses, &contracts_storage_keys).await?;
+
+        let proofs = requests
+            .iter()
+            .map(|(contract_address, keys)| {
+                let contract_index = contract_addresses.iter().position(|address| address == contract_address);
+                let storage_index = contracts_storage_keys
+                    .iter()
+                    .position(|storage_keys| storage_keys.contract_address == *contract_address);
+                storage_proof.to_pathfinder_proof(
+                    contract_index.expect("all the contracts are requested"),
+                    storage_index,
+                    *contract_address,
+                    keys,
+                )
+            })
+            .collect();
+        Ok(proofs)
+    }
+
+    async fn get_class_proof(&self, block_number: u64, class_hash: &Felt) -> Result<PathfinderClassProof, ClientError> {
+        let mut proofs = self.get_class_proofs(block_number, &[*class_hash]).await?;
+        proofs.remove(0)
+    }
+
+    /// Fetches the proofs of all the classes in one call.
+    async fn get_class_proofs(
+        &self,
+        block_number: u64,
+        class_hashes: &[Felt],
+    ) -> Result<Vec<Result<PathfinderClassProof, ClientError>>, ClientError> {
+        let storage_proof = self.get_storage_proof(block_number, class_hashes, &[], &[]).await?;
+
+        Ok(class_hashes.iter().map(|class_hash| storage_proof.to_pathfinder_class_proof(*class_hash)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet_os::crypto::pedersen::PedersenHash;
+    use starknet_os::crypto::poseidon::PoseidonHash;
+
+    use super::*;
+    use crate::pathfinder::proofs::{verify_proof, ProofOutcome};
+
+    /// Builds a trie with a single leaf, i.e. a single edge node going from the root to the leaf.
+    fn single_leaf_trie<H: starknet_os::storage::storage::HashFunctionType>(
+        key: Felt,
+        value: Felt,
+    ) -> (Felt, NodeHashToNode) {
+        let node = MerkleNode::Edge { path: key, length: 251, child: value };
+        let node_hash = TrieNode::from(node.clone()).hash::<H>();
+        (node_hash, NodeHashToNode { node_hash, node })
+    }
+
+    #[test]
+    fn test_merkle_node_deserialization() {
+        let binary: MerkleNode = serde_json::from_value(json!({ "left": "0x1", "right": "0x2" })).unwrap();
+        assert_eq!(binary, MerkleNode::Binary { left: Felt::ONE, right: Felt::TWO });
+
+        let edge: MerkleNode = serde_json::from_value(json!({ "path": "0x3", "length": 2, "child": "0x4" })).unwrap();
+        assert_eq!(edge, MerkleNode::Edge { path: Felt::THREE, length: 2, child: Felt::from(4) });
+    }
+
+    #[test]
+    fn test_convert_to_pathfinder_proofs() {
+        let contract_address = Felt::from(0x1234);
+        let storage_key = Felt::from(0x10);
+        let class_hash = Felt::from(0xabcd);
+
+        let (storage_root, storage_node) = single_leaf_trie::<PedersenHash>(storage_key, Felt::from(42));
+        let (contracts_tree_root, contract_node) = single_leaf_trie::<PedersenHash>(contract_address, Felt::from(7));
+        let (classes_tree_root, class_node) = single_leaf_trie::<PoseidonHash>(class_hash, Felt::from(8));
+
+        let storage_proof = StorageProof {
+            classes_proof: vec![class_node],
+            contracts_proof: ContractsProof {
+                nodes: vec![contract_node],
+                contract_leaves_data: vec![ContractLeafData { nonce: Felt::ONE, class_hash, storage_root }],
+            },
+            contracts_storage_proofs: vec![vec![storage_node]],
+            global_roots: GlobalRoots { contracts_tree_root, classes_tree_root, block_hash: Felt::ZERO },
+        };
+
+        let missing_key = Felt::from(0x11);
+        let proof =
+            storage_proof.to_pathfinder_proof(0, Some(0), contract_address, &[storage_key, missing_key]).unwrap();
+        assert_eq!(proof.class_commitment, Some(classes_tree_root));
+        assert_eq!(
+            verify_proof::<PedersenHash>(contract_address, contracts_tree_root, &proof.contract_proof),
+            ProofOutcome::Member { leaf_value: Felt::from(7) }
+        );
+
+        let contract_data = proof.contract_data.unwrap();
+        assert_eq!(contract_data.root, storage_root);
+        assert_eq!(
+            verify_proof::<PedersenHash>(storage_key, storage_root, &contract_data.storage_proofs[0]),
+            ProofOutcome::Member { leaf_value: Felt::from(42) }
+        );
+        // The edge node diverging from the missing key proves that the key is not in the trie
+        assert!(matches!(
+            verify_proof::<PedersenHash>(missing_key, storage_root, &contract_data.storage_proofs[1]),
+            ProofOutcome::NonMember { witness: Some(_) }
+        ));
+
+        let class_proof = storage_proof.to_pathfinder_class_proof(class_hash).unwrap();
+        assert_eq!(class_proof.verify(class_hash), ProofOutcome::Member { leaf_value: Felt::from(8) });
+    }
+
+    fn class_proof_with_nodes(classes_tree_root: Felt, nodes: Vec<NodeHashToNode>) -> StorageProof {
+        StorageProof {
+            classes_proof: nodes,
+            contracts_proof: ContractsProof { nodes: vec![], contract_leaves_data: vec![] },
+            contracts_storage_proofs: vec![],
+            global_roots: GlobalRoots { contracts_tree_root: Felt::ZERO, classes_tree_root, block_hash: Felt::ZERO },
+        }
+    }
+
+    #[test]
+    fn test_invalid_nodes_are_rejected() {
+        let root = Felt::from(0x1234);
+        let class_hash = Felt::from(0xabcd);
+
+        // An edge of length 0 pointing to itself would loop forever, an edge longer than
+        // the remaining height would go past the leaves
+        for node in [
+            MerkleNode::Edge { path: Felt::ZERO, length: 0, child: root },
+            MerkleNode::Edge { path: Felt::ZERO, length: 252, child: Felt::ONE },
+            MerkleNode::Edge { path: Felt::ZERO, length: u64::MAX, child: Felt::ONE },
+        ] {
+            let storage_proof = class_proof_with_nodes(root, vec![NodeHashToNode { node_hash: root, node }]);
+            let result = storage_proof.to_pathfinder_class_proof(class_hash);
+            assert!(matches!(result, Err(ClientError::CustomError(_))), "{result:?}");
+        }
+
+        // Cycles of binary nodes stop at the height of the trie
+        let node = MerkleNode::Binary { left: root, right: root };
+        let storage_proof = class_proof_with_nodes(root, vec![NodeHashToNode { node_hash: root, node }]);
+        let class_proof = storage_proof.to_pathfinder_class_proof(class_hash).unwrap();
+        assert_eq!(class_proof.class_proof.len(), TREE_HEIGHT);
+        assert!(!class_proof.verify(class_hash).is_valid());
+    }
+}
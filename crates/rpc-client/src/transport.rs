@@ -0,0 +1,365 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::{Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use starknet::providers::jsonrpc::{JsonRpcMethod, JsonRpcResponse, JsonRpcTransport};
+use tokio::sync::Semaphore;
+
+use crate::config::RpcClientConfig;
+use crate::disk_cache::DiskCache;
+use crate::pathfinder::client::ClientError;
+use crate::recording::RpcRecording;
+use crate::spec_version::RpcSpecVersion;
+use crate::stats::RpcStats;
+
+fn jsonrpc_request(id: usize, method: &str, params: &serde_json::Value) -> serde_json::Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    })
+}
+
+/// Returns the response body, or an error if the request failed.
+async fn handle_error(response: Response) -> Result<Vec<u8>, ClientError> {
+    match response.status() {
+        StatusCode::OK => Ok(response.bytes().await?.to_vec()),
+        status => {
+            let body = response.text().await?;
+            Err(ClientError::HttpError { status, body })
+        }
+    }
+}
+
+/// Where the transport gets its responses from.
+enum Backend {
+    /// Forward requests to a node over HTTP.
+    Http { http_client: reqwest::Client, url: Url },
+    /// Answer requests from a recording, without network access.
+    Replay(Arc<RpcRecording>),
+}
+
+/// JSON-RPC transport shared by the starknet-rs and Pathfinder clients.
+///
+/// On top of plain HTTP, this transport can record every response it receives or replay
+/// responses from a previous recording. HTTP requests that fail with a transient error are
+/// retried as specified by the `RpcClientConfig` of the transport.
+pub struct RpcTransport {
+    backend: Backend,
+    config: RpcClientConfig,
+    /// If set, requests wait for a permit before being sent.
+    concurrency_limit: Option<Arc<Semaphore>>,
+    /// Version of the RPC spec of the node, responses are converted to the v0.7 schema if needed.
+    spec_version: RpcSpecVersion,
+    /// If set, responses to requests for immutable data are read from and written to this cache
+    /// instead of being fetched from the node every time.
+    disk_cache: Option<Arc<DiskCache>>,
+    /// If set, every response received from the backend is stored in this recording.
+    recorder: Option<Arc<Mutex<RpcRecording>>>,
+    /// If set, every request is counted in these stats.
+    stats: Option<Arc<RpcStats>>,
+}
+
+impl RpcTransport {
+    pub fn new_http(url: Url) -> Self {
+        Self::new_http_with_config(url, RpcClientConfig::default())
+    }
+
+    pub fn new_http_with_config(url: Url, config: RpcClientConfig) -> Self {
+        let mut builder = reqwest::ClientBuilder::new();
+        if let Some(request_timeout) = config.request_timeout {
+            builder = builder.timeout(request_timeout);
+        }
+        if let Some(connect_timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        let http_client = builder.build().unwrap_or_else(|e| panic!("Could not build reqwest client: {e}"));
+
+        Self {
+            backend: Backend::Http { http_client, url },
+            config,
+            concurrency_limit: None,
+            spec_version: RpcSpecVersion::default(),
+            disk_cache: None,
+            recorder: None,
+            stats: None,
+        }
+    }
+
+    pub fn new_replay(recording: Arc<RpcRecording>) -> Self {
+        Self {
+            backend: Backend::Replay(recording),
+            config: RpcClientConfig::default(),
+            concurrency_limit: None,
+            spec_version: RpcSpecVersion::default(),
+            disk_cache: None,
+            recorder: None,
+            stats: None,
+        }
+    }
+
+    /// Limits the number of requests in flight. The semaphore can be shared between transports.
+    pub fn with_concurrency_limit(mut self, semaphore: Arc<Semaphore>) -> Self {
+        self.concurrency_limit = Some(semaphore);
+        self
+    }
+
+    /// Converts the responses of a node implementing the specified version of the Starknet RPC spec
+    /// to the schema expected by starknet-rs, see `RpcSpecVersion::normalize_response`.
+    pub fn with_spec_version(mut self, spec_version: RpcSpecVersion) -> Self {
+        self.spec_version = spec_version;
+        self
+    }
+
+    /// Serves requests for immutable data from a disk cache. The cache can be shared between transports.
+    pub fn with_disk_cache(mut self, disk_cache: Arc<DiskCache>) -> Self {
+        self.disk_cache = Some(disk_cache);
+        self
+    }
+
+    pub fn with_recorder(mut self, recorder: Arc<Mutex<RpcRecording>>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn with_stats(mut self, stats: Arc<RpcStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Posts a request body, retrying on transient failures.
+    async fn post_with_retries(
+        &self,
+        http_client: &reqwest::Client,
+        url: &Url,
+        method: &str,
+        request: Vec<u8>,
+    ) -> Result<Vec<u8>, ClientError> {
+        let mut retry = 0;
+        loop {
+            let result = async {
+                let _permit = match &self.concurrency_limit {
+                    Some(semaphore) => Some(semaphore.acquire().await.expect("semaphore is never closed")),
+                    None => None,
+                };
+                let response = http_client
+                    .post(url.clone())
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(request.clone())
+                    .send()
+                    .await?;
+                handle_error(response).await
+            }
+            .await;
+
+            match result {
+                Err(e) if e.is_retryable() && retry < self.config.max_retries => {
+                    let backoff = self.config.backoff(retry);
+                    tracing::warn!("{} request failed ({}), retrying in {:?}", method, e, backoff);
+                    tokio::time::sleep(backoff).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends a JSON-RPC request and returns the raw response body.
+    pub async fn send_raw(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value, ClientError> {
+        let (response, bytes_sent, bytes_received) = match &self.backend {
+            Backend::Http { http_client, url } => {
+                let disk_cache = self.disk_cache.as_ref().filter(|disk_cache| disk_cache.is_cacheable(method, &params));
+                if let Some(response) = disk_cache.and_then(|disk_cache| disk_cache.get(url.as_str(), method, &params))
+                {
+                    // Cache hits do not reach the node, they are not counted in the stats
+                    if let Some(recorder) = &self.recorder {
+                        recorder.lock().expect("recorder lock is poisoned").insert(method, params, response.clone());
+                    }
+                    return Ok(response);
+                }
+
+                let request = serde_json::to_vec(&jsonrpc_request(0, method, &params))?;
+                let bytes_sent = request.len();
+                let body = self.post_with_retries(http_client, url, method, request).await?;
+                let mut response: serde_json::Value = serde_json::from_slice(&body)?;
+                // Cached and recorded responses are normalized, they do not depend on the node version
+                self.spec_version.normalize_response(method, &mut response)?;
+
+                // JSON-RPC errors are not cached, they may be transient
+                if let Some(disk_cache) = disk_cache {
+                    if response.get("result").is_some() {
+                        disk_cache.insert(url.as_str(), method, &params, &response);
+                    }
+                }
+                (response, bytes_sent, body.len())
+            }
+            Backend::Replay(recording) => {
+                let response = recording.get(method, &params).cloned().ok_or_else(|| {
+                    ClientError::MissingRecordedResponse { method: method.to_string(), params: params.to_string() }
+                })?;
+                (response, 0, 0)
+            }
+        };
+
+        if let Some(stats) = &self.stats {
+            stats.record_request(bytes_sent, bytes_received);
+        }
+
+        if let Some(recorder) = &self.recorder {
+            recorder.lock().expect("recorder lock is poisoned").insert(method, params, response.clone());
+        }
+
+        Ok(response)
+    }
+
+    /// Sends JSON-RPC requests in a single HTTP request (JSON-RPC batch) and returns the raw
+    /// response body of each request, in the order of `requests`.
+    ///
+    /// Requests found in the disk cache are not sent. The batch is retried as a whole on
+    /// transient failures and counts as a single request in the stats.
+    pub async fn send_raw_batch(
+        &self,
+        requests: &[(&str, serde_json::Value)],
+    ) -> Result<Vec<serde_json::Value>, ClientError> {
+        let (http_client, url) = match &self.backend {
+            Backend::Http { http_client, url } => (http_client, url),
+            // Recordings hold the responses of individual requests
+            Backend::Replay(_) => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for (method, params) in requests {
+                    responses.push(self.send_raw(method, params.clone()).await?);
+                }
+                return Ok(responses);
+            }
+        };
+
+        let mut responses: Vec<Option<serde_json::Value>> = requests
+            .iter()
+            .map(|(method, params)| {
+                self.disk_cache
+                    .as_ref()
+                    .filter(|disk_cache| disk_cache.is_cacheable(method, params))
+                    .and_then(|disk_cache| disk_cache.get(url.as_str(), method, params))
+            })
+            .collect();
+
+        // Requests are identified by their index in `requests`
+        let batch: Vec<serde_json::Value> = requests
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| responses[*index].is_none())
+            .map(|(index, (method, params))| jsonrpc_request(index, method, params))
+            .collect();
+
+        if !batch.is_empty() {
+            let request = serde_json::to_vec(&batch)?;
+            let bytes_sent = request.len();
+            let description = format!("Batch of {}", batch.len());
+            let body = self.post_with_retries(http_client, url, &description, request).await?;
+
+            // Nodes that do not support batches answer with a single error object
+            let batch_responses: Vec<serde_json::Value> = match serde_json::from_slice(&body)? {
+                serde_json::Value::Array(batch_responses) => batch_responses,
+                response => {
+                    return Err(ClientError::CustomError(format!(
+                        "Unexpected response to a batch request: {response}"
+                    )));
+                }
+            };
+            for mut response in batch_responses {
+                let index = response
+                    .get("id")
+                    .and_then(|id| id.as_u64())
+                    .map(|id| id as usize)
+                    .filter(|index| *index < requests.len())
+                    .ok_or_else(|| ClientError::CustomError(format!("Unexpected response in batch: {response}")))?;
+                let (method, params) = &requests[index];
+                self.spec_version.normalize_response(method, &mut response)?;
+
+                if let Some(disk_cache) = &self.disk_cache {
+                    if disk_cache.is_cacheable(method, params) && response.get("result").is_some() {
+                        disk_cache.insert(url.as_str(), method, params, &response);
+                    }
+                }
+                responses[index] = Some(response);
+            }
+
+            if let Some(stats) = &self.stats {
+                stats.record_request(bytes_sent, body.len());
+            }
+        }
+
+        let responses = responses
+            .into_iter()
+            .zip(requests)
+            .map(|(response, (method, _))| {
+                response.ok_or_else(|| ClientError::CustomError(format!("No response to {method} in batch")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(recorder) = &self.recorder {
+            let mut recorder = recorder.lock().expect("recorder lock is poisoned");
+            for ((method, params), response) in requests.iter().zip(&responses) {
+                recorder.insert(method, params.clone(), response.clone());
+            }
+        }
+
+        Ok(responses)
+    }
+
+    /// Sends a JSON-RPC request and extracts the result from the response.
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, ClientError> {
+        let response = self.send_raw(method, params).await?;
+        parse_response(response)
+    }
+
+    /// Sends JSON-RPC requests in a single batch and extracts the result of each request.
+    /// The outer error reports a failure of the whole batch, the inner errors the JSON-RPC
+    /// errors of individual requests.
+    pub async fn request_batch<T: DeserializeOwned>(
+        &self,
+        requests: &[(&str, serde_json::Value)],
+    ) -> Result<Vec<Result<T, ClientError>>, ClientError> {
+        let responses = self.send_raw_batch(requests).await?;
+        Ok(responses.into_iter().map(parse_response).collect())
+    }
+}
+
+/// Extracts the result of a JSON-RPC response.
+fn parse_response<T: DeserializeOwned>(response: serde_json::Value) -> Result<T, ClientError> {
+    match serde_json::from_value(response)? {
+        JsonRpcResponse::Success { result, .. } => Ok(result),
+        JsonRpcResponse::Error { error, .. } => {
+            Err(ClientError::JsonRpcError { code: error.code, message: error.message })
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcTransport for RpcTransport {
+    type Error = ClientError;
+
+    async fn send_request<P, R>(&self, method: JsonRpcMethod, params: P) -> Result<JsonRpcResponse<R>, Self::Error>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        // `JsonRpcMethod` serializes to the method name, ex: "starknet_getBlockWithTxs"
+        let method_value = serde_json::to_value(method)?;
+        let method = method_value
+            .as_str()
+            .ok_or_else(|| ClientError::CustomError(format!("Unexpected JSON-RPC method: {method_value}")))?;
+        let params = serde_json::to_value(params)?;
+
+        let response = self.send_raw(method, params).await?;
+        Ok(serde_json::from_value(response)?)
+    }
+}
@@ -6,7 +6,7 @@ use blockifier::context::{BlockContext, ChainInfo, FeeTokenAddresses};
 use blockifier::versioned_constants::VersionedConstants;
 use starknet::core::types::{BlockWithTxs, Felt, L1DataAvailabilityMode};
 use starknet_api::block::{BlockNumber, BlockTimestamp};
-use starknet_api::core::{ChainId, ContractAddress, PatriciaKey};
+use starknet_api::core::{ContractAddress, PatriciaKey};
 use starknet_api::{contract_address, felt, patricia_key};
 
 use crate::utils::{felt_to_u128, FeltConversionError};
@@ -24,10 +24,21 @@ fn felt_to_gas_price(price: &Felt) -> Result<NonZeroU128, FeltConversionError> {
     NonZeroU128::new(gas_price).ok_or(FeltConversionError::CustomError("Gas price cannot be zero".to_string()))
 }
 
+/// Fee token addresses of the public Starknet networks (mainnet and sepolia).
+pub fn starknet_fee_token_addresses() -> FeeTokenAddresses {
+    // cf. https://docs.starknet.io/tools/important-addresses/
+    FeeTokenAddresses {
+        strk_fee_token_address: contract_address!("0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"),
+        eth_fee_token_address: contract_address!("0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7"),
+    }
+}
+
+/// Builds the blockifier context of a block. `chain_info` holds the chain ID and fee token
+/// addresses of the chain, see `starknet_fee_token_addresses` for the public networks.
 pub fn build_block_context(
-    chain_id: ChainId,
+    chain_info: ChainInfo,
     block: &BlockWithTxs,
-    starknet_version: blockifier::versioned_constants::StarknetVersion,
+    versioned_constants: &VersionedConstants,
 ) -> Result<BlockContext, FeltConversionError> {
     let sequencer_address_hex = block.sequencer_address.to_hex_string();
     let sequencer_address = contract_address!(sequencer_address_hex.as_str());
@@ -49,20 +60,6 @@ pub fn build_block_context(
         use_kzg_da,
     };
 
-    let chain_info = ChainInfo {
-        chain_id,
-        // cf. https://docs.starknet.io/tools/important-addresses/
-        fee_token_addresses: FeeTokenAddresses {
-            strk_fee_token_address: contract_address!(
-                "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d"
-            ),
-            eth_fee_token_address: contract_address!(
-                "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7"
-            ),
-        },
-    };
-
-    let versioned_constants = VersionedConstants::get(starknet_version);
     let bouncer_config = BouncerConfig::max();
 
     Ok(BlockContext::new(block_info, chain_info, versioned_constants.clone(), bouncer_config))
@@ -78,7 +75,7 @@ mod tests {
 
     #[test]
     fn test_build_block_context_with_zero_gas_prices() {
-        let chain_id = ChainId::Mainnet;
+        let chain_info = ChainInfo { chain_id: ChainId::Mainnet, fee_token_addresses: starknet_fee_token_addresses() };
         // We don't really care about most of the fields.
         // What's important here is to set to zero different gas prices
         let block = BlockWithTxs {
@@ -96,10 +93,10 @@ mod tests {
             transactions: vec![],
         };
 
-        let starknet_version = blockifier::versioned_constants::StarknetVersion::Latest;
+        let versioned_constants = VersionedConstants::latest_constants();
 
         // Call this function must not fail
-        let block_context = build_block_context(chain_id, &block, starknet_version).unwrap();
+        let block_context = build_block_context(chain_info, &block, versioned_constants).unwrap();
 
         // Verify that gas prices were set to NonZeroU128::MIN
         assert_eq!(block_context.block_info().gas_prices.eth_l1_gas_price, NonZeroU128::MIN);
@@ -110,7 +107,7 @@ mod tests {
 
     #[test]
     fn test_build_block_context_with_custom_gas_prices() {
-        let chain_id = ChainId::Mainnet;
+        let chain_info = ChainInfo { chain_id: ChainId::Mainnet, fee_token_addresses: starknet_fee_token_addresses() };
 
         // Expected values for gas price
         let wei_l1_price = 1234;
@@ -139,8 +136,8 @@ mod tests {
             transactions: vec![],
         };
 
-        let starknet_version = blockifier::versioned_constants::StarknetVersion::Latest;
-        let block_context = build_block_context(chain_id, &block, starknet_version).unwrap();
+        let versioned_constants = VersionedConstants::latest_constants();
+        let block_context = build_block_context(chain_info, &block, versioned_constants).unwrap();
 
         // Verify that gas prices match our input values
         assert_eq!(block_context.block_info().gas_prices.eth_l1_gas_price, NonZeroU128::new(wei_l1_price).unwrap());
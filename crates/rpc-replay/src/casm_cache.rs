@@ -0,0 +1,134 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use cairo_lang_starknet_classes::compiler_version::current_compiler_version_id;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::Felt;
+use starknet_os_types::casm_contract_class::GenericCasmContractClass;
+use starknet_os_types::hash::{GenericClassHash, Hash};
+
+/// Version of the cache entry format, part of the key of every entry.
+/// Bump it whenever the layout of the entries or the compilation parameters change, older entries
+/// will then be ignored.
+const CASM_CACHE_VERSION: u32 = 1;
+
+/// Counter making the names of the temporary files of a process unique, see `CasmCache::insert`.
+static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize)]
+struct CasmCacheEntry {
+    class_hash: Felt,
+    compiler_version: String,
+    compiled_class_hash: Felt,
+    casm: GenericCasmContractClass,
+}
+
+/// Cache of the CASM compiled from Sierra classes, stored in a directory as one gzipped JSON
+/// file per class.
+///
+/// Entries are addressed by (Sierra class hash, compiler version): the class hash identifies the
+/// Sierra program, an entry can be used for any block of any chain. Entries compiled by another
+/// version of the compiler are ignored. Entries are never evicted, the cache only grows with the
+/// number of classes used.
+pub struct CasmCache {
+    dir: PathBuf,
+    compiler_version: String,
+}
+
+impl CasmCache {
+    /// Opens the cache stored in `dir`, creating the directory if needed.
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf(), compiler_version: current_compiler_version_id().to_string() })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, class_hash: Felt) -> PathBuf {
+        self.dir.join(format!("0x{class_hash:x}-{}-v{CASM_CACHE_VERSION}.json.gz", self.compiler_version))
+    }
+
+    /// Returns the CASM of a Sierra class, if cached. The compiled class hash of the returned
+    /// class is already computed.
+    pub fn get(&self, class_hash: Felt) -> Option<GenericCasmContractClass> {
+        let path = self.entry_path(class_hash);
+        let file = File::open(&path).ok()?;
+        let entry: CasmCacheEntry = match serde_json::from_reader(GzDecoder::new(BufReader::new(file))) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Ignoring corrupted CASM cache entry {}: {}", path.display(), e);
+                return None;
+            }
+        };
+
+        // Guard against file name collisions
+        if entry.class_hash != class_hash || entry.compiler_version != self.compiler_version {
+            return None;
+        }
+
+        Some(entry.casm.with_class_hash(GenericClassHash::new(Hash::from(entry.compiled_class_hash))))
+    }
+
+    /// Stores the CASM of a Sierra class. Failures are only logged, the cache is an optimization.
+    pub fn insert(&self, class_hash: Felt, casm: &GenericCasmContractClass) {
+        let path = self.entry_path(class_hash);
+
+        // Write to a temporary file first so that concurrent readers never see partial entries.
+        // The name is unique so that concurrent writers of the same entry, in this process or in
+        // another one sharing the directory, do not write to the same file.
+        let tmp_path =
+            self.dir.join(format!("{}.{}.tmp", std::process::id(), TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+        let write_entry = || -> Result<(), Box<dyn std::error::Error>> {
+            let entry = CasmCacheEntry {
+                class_hash,
+                compiler_version: self.compiler_version.clone(),
+                compiled_class_hash: casm.class_hash()?.into(),
+                casm: casm.clone(),
+            };
+            let mut encoder = GzEncoder::new(BufWriter::new(File::create(&tmp_path)?), Compression::default());
+            serde_json::to_writer(&mut encoder, &entry)?;
+            encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+            fs::rename(&tmp_path, &path)?;
+            Ok(())
+        };
+
+        if let Err(e) = write_entry() {
+            // Nothing else removes the temporary files
+            let _ = fs::remove_file(&tmp_path);
+            tracing::warn!("Failed to write CASM cache entry {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASM_CLASS: &[u8] = include_bytes!(
+        "../../../tests/integration/contracts/blockifier_contracts/feature_contracts/cairo1/compiled/test_contract.\
+         casm.json"
+    );
+
+    #[test]
+    fn test_casm_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CasmCache::open(dir.path()).unwrap();
+        let class_hash = Felt::from(0x1234);
+        let casm = GenericCasmContractClass::from_bytes(CASM_CLASS.to_vec());
+        assert!(cache.get(class_hash).is_none());
+
+        cache.insert(class_hash, &casm);
+        let cached_casm = CasmCache::open(dir.path()).unwrap().get(class_hash).unwrap();
+        assert_eq!(cached_casm.class_hash().unwrap(), casm.class_hash().unwrap());
+        assert_eq!(cached_casm.get_cairo_lang_contract_class().unwrap(), casm.get_cairo_lang_contract_class().unwrap());
+        assert!(cache.get(Felt::from(0x5678)).is_none());
+    }
+}
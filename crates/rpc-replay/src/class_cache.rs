@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rpc_client::RpcClient;
+use starknet::core::types::{BlockId, BlockTag, Felt};
+use starknet::providers::{Provider, ProviderError};
+use starknet_os_types::class_hash_utils::ContractClassComponentHashes;
+use starknet_os_types::compiled_class::GenericCompiledClass;
+use starknet_os_types::deprecated_compiled_class::GenericDeprecatedCompiledClass;
+use starknet_os_types::error::ContractClassError;
+use starknet_os_types::sierra_contract_class::GenericSierraContractClass;
+use starknet_os_types::starknet_core_addons::LegacyContractDecompressionError;
+
+use crate::casm_cache::CasmCache;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ClassCacheError {
+    #[error("RPC Error: {0}")]
+    RpcError(#[from] ProviderError),
+    #[error("OS Contract Class Error: {0}")]
+    ContractClassError(#[from] ContractClassError),
+    #[error("Legacy Contract Decompression Error: {0}")]
+    LegacyContractDecompressionError(#[from] LegacyContractDecompressionError),
+}
+
+/// A compiled class, along with the sizes of its Sierra class required to build a `ClassInfo`
+/// and the component hashes of its Sierra class required by the OS for declared classes.
+#[derive(Clone)]
+pub struct CachedClass {
+    pub compiled_class: GenericCompiledClass,
+    /// Length of the Sierra program, 0 for Cairo 0 classes.
+    pub sierra_program_length: usize,
+    /// Length of the ABI, 0 for Cairo 0 classes.
+    pub abi_length: usize,
+    /// Component hashes of the Sierra class, `None` for Cairo 0 classes.
+    pub component_hashes: Option<ContractClassComponentHashes>,
+}
+
+struct CacheEntry {
+    class: CachedClass,
+    /// Lowest block at which the class is known to be declared. The class exists at any later block.
+    declared_at: Option<u64>,
+}
+
+/// Compiled classes, indexed by class hash.
+///
+/// Classes are immutable, a class compiled once can be used for any block. The cache can be shared
+/// between the state readers of several blocks and the code building the OS input, so that each
+/// class is fetched and compiled only once.
+/// A class is only returned for blocks where it is known to be declared: blockifier must still see
+/// the classes declared by a block as undeclared when reading the state before the block.
+#[derive(Default)]
+pub struct ClassCache {
+    classes: Mutex<HashMap<Felt, CacheEntry>>,
+    /// CASM compiled by previous runs, used instead of compiling Sierra classes.
+    casm_cache: Option<CasmCache>,
+    /// Total time spent compiling the classes, including loading them from the CASM cache.
+    compilation_time: Mutex<Duration>,
+}
+
+impl ClassCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the CASM of Sierra classes from `casm_cache` instead of compiling them when possible,
+    /// and stores the CASM of the classes compiled.
+    pub fn with_casm_cache(self, casm_cache: CasmCache) -> Self {
+        Self { casm_cache: Some(casm_cache), ..self }
+    }
+
+    /// Total time spent compiling the classes of the cache.
+    pub fn compilation_time(&self) -> Duration {
+        *self.compilation_time.lock().expect("class cache lock is poisoned")
+    }
+
+    /// Returns the class declared at `block_id`, from the cache if the class is known to be
+    /// declared at this block, from the node otherwise. Classes are only compiled once.
+    pub async fn get_class(
+        &self,
+        rpc_client: &RpcClient,
+        block_id: BlockId,
+        class_hash: Felt,
+    ) -> Result<CachedClass, ClassCacheError> {
+        if let Some(class) = self.get_declared_class(block_id, class_hash) {
+            return Ok(class);
+        }
+
+        // The class must be fetched even if it was already compiled, to check that it is declared
+        let contract_class = rpc_client.starknet_rpc().get_class(block_id, class_hash).await?;
+        let block_number = match block_id {
+            BlockId::Number(block_number) => Some(block_number),
+            _ => None,
+        };
+
+        if let Some(entry) = self.classes.lock().expect("class cache lock is poisoned").get_mut(&class_hash) {
+            entry.declared_at = match (entry.declared_at, block_number) {
+                (Some(declared_at), Some(block_number)) => Some(declared_at.min(block_number)),
+                (declared_at, block_number) => declared_at.or(block_number),
+            };
+            return Ok(entry.class.clone());
+        }
+
+        let start = Instant::now();
+        let class = compile_contract_class(contract_class, class_hash, self.casm_cache.as_ref())?;
+        *self.compilation_time.lock().expect("class cache lock is poisoned") += start.elapsed();
+
+        self.classes
+            .lock()
+            .expect("class cache lock is poisoned")
+            .insert(class_hash, CacheEntry { class: class.clone(), declared_at: block_number });
+        Ok(class)
+    }
+
+    /// Same as `get_class`, only returns the compiled class.
+    pub async fn get_compiled_class(
+        &self,
+        rpc_client: &RpcClient,
+        block_id: BlockId,
+        class_hash: Felt,
+    ) -> Result<GenericCompiledClass, ClassCacheError> {
+        Ok(self.get_class(rpc_client, block_id, class_hash).await?.compiled_class)
+    }
+
+    fn get_declared_class(&self, block_id: BlockId, class_hash: Felt) -> Option<CachedClass> {
+        let classes = self.classes.lock().expect("class cache lock is poisoned");
+        let entry = classes.get(&class_hash)?;
+        let declared_at = entry.declared_at?;
+        let is_declared = match block_id {
+            BlockId::Number(block_number) => declared_at <= block_number,
+            BlockId::Tag(BlockTag::Latest | BlockTag::Pending) => true,
+            BlockId::Hash(_) => false,
+        };
+
+        is_declared.then(|| entry.class.clone())
+    }
+}
+
+/// Compiles the class to CASM if it is a Sierra class and its CASM is not in `casm_cache`,
+/// decompresses it if it is a Cairo 0 class.
+fn compile_contract_class(
+    contract_class: starknet::core::types::ContractClass,
+    class_hash: Felt,
+    casm_cache: Option<&CasmCache>,
+) -> Result<CachedClass, ClassCacheError> {
+    let class = match contract_class {
+        starknet::core::types::ContractClass::Sierra(sierra_class) => {
+            let sierra_program_length = sierra_class.sierra_program.len();
+            let abi_length = sierra_class.abi.len();
+            let component_hashes = ContractClassComponentHashes::from(&sierra_class);
+            let compiled_class = match casm_cache.and_then(|casm_cache| casm_cache.get(class_hash)) {
+                Some(compiled_class) => compiled_class,
+                None => {
+                    let compiled_class = GenericSierraContractClass::from(sierra_class).compile()?;
+                    if let Some(casm_cache) = casm_cache {
+                        casm_cache.insert(class_hash, &compiled_class);
+                    }
+                    compiled_class
+                }
+            };
+            CachedClass {
+                compiled_class: GenericCompiledClass::Cairo1(compiled_class),
+                sierra_program_length,
+                abi_length,
+                component_hashes: Some(component_hashes),
+            }
+        }
+        starknet::core::types::ContractClass::Legacy(legacy_class) => {
+            let compiled_class = GenericDeprecatedCompiledClass::try_from(legacy_class)?;
+            CachedClass {
+                compiled_class: GenericCompiledClass::Cairo0(compiled_class),
+                sierra_program_length: 0,
+                abi_length: 0,
+                component_hashes: None,
+            }
+        }
+    };
+
+    Ok(class)
+}
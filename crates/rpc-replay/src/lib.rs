@@ -1,4 +1,6 @@
 pub mod block_context;
+pub mod casm_cache;
+pub mod class_cache;
 pub mod rpc_state_reader;
 pub mod transactions;
 pub mod utils;
@@ -1,25 +1,170 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
 use blockifier::execution::contract_class::ContractClass;
 use blockifier::state::errors::StateError;
 use blockifier::state::state_api::{StateReader, StateResult};
-use rpc_client::client::RpcClient;
-use starknet::core::types::{BlockId, Felt, StarknetError};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use rpc_client::client::{is_retryable_provider_error, RpcClient};
+use rpc_client::pathfinder::client::ClientError;
+use starknet::core::types::{BlockId, Felt, StarknetError, TransactionTrace, TransactionTraceWithHash};
 use starknet::providers::{Provider, ProviderError};
-use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce};
+use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce, PatriciaKey};
 use starknet_api::state::StorageKey;
-use starknet_os_types::deprecated_compiled_class::GenericDeprecatedCompiledClass;
-use starknet_os_types::hash::GenericClassHash;
-use starknet_os_types::sierra_contract_class::GenericSierraContractClass;
+use starknet_api::StarknetApiError;
+use starknet_os_types::compiled_class::GenericCompiledClass;
+
+use crate::class_cache::{ClassCache, ClassCacheError};
+use crate::utils::{execute_coroutine, get_subcalled_contracts_from_tx_traces};
+
+/// Keys of the state to fetch before executing a block, see `AsyncRpcStateReader::prefetch`.
+#[derive(Debug, Clone, Default)]
+pub struct PrefetchKeys {
+    pub storage: Vec<(ContractAddress, StorageKey)>,
+    /// Contracts whose nonce and class hash are fetched.
+    pub contracts: Vec<ContractAddress>,
+    pub classes: Vec<ClassHash>,
+}
+
+impl PrefetchKeys {
+    /// Extracts the keys accessed by a block from its traces: the contracts called, their classes
+    /// and the storage keys written. Storage keys that are only read are not part of the traces.
+    pub fn from_traces(traces: &[TransactionTraceWithHash]) -> Result<Self, StarknetApiError> {
+        let (contracts, classes) = get_subcalled_contracts_from_tx_traces(traces);
+
+        let mut storage = vec![];
+        for trace in traces {
+            let state_diff = match &trace.trace_root {
+                TransactionTrace::Invoke(trace) => &trace.state_diff,
+                TransactionTrace::DeployAccount(trace) => &trace.state_diff,
+                TransactionTrace::L1Handler(trace) => &trace.state_diff,
+                TransactionTrace::Declare(trace) => &trace.state_diff,
+            };
+            for storage_diff in state_diff.iter().flat_map(|state_diff| &state_diff.storage_diffs) {
+                let contract_address = ContractAddress(PatriciaKey::try_from(storage_diff.address)?);
+                for storage_entry in &storage_diff.storage_entries {
+                    storage.push((contract_address, StorageKey(PatriciaKey::try_from(storage_entry.key)?)));
+                }
+            }
+        }
+        storage.sort();
+        storage.dedup();
 
-use crate::utils::execute_coroutine;
+        let mut contracts = contracts
+            .into_iter()
+            .map(|address| PatriciaKey::try_from(address).map(ContractAddress))
+            .collect::<Result<Vec<_>, _>>()?;
+        contracts.sort();
+        let mut classes: Vec<_> = classes.into_iter().map(ClassHash).collect();
+        classes.sort();
 
+        Ok(Self { storage, contracts, classes })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PrefetchError {
+    #[error("Failed to fetch storage values: {0}")]
+    StorageFetchError(#[from] ClientError),
+    #[error("RPC Error: {0}")]
+    RpcError(#[from] ProviderError),
+    #[error("Failed to fetch class: {0}")]
+    ClassCacheError(#[from] ClassCacheError),
+}
+
+impl PrefetchError {
+    /// Whether prefetching again could succeed, i.e. the error was caused by the node or the network.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::StorageFetchError(e) => e.is_retryable(),
+            Self::RpcError(e) | Self::ClassCacheError(ClassCacheError::RpcError(e)) => is_retryable_provider_error(e),
+            Self::ClassCacheError(_) => false,
+        }
+    }
+}
+
+/// Values read by an `AsyncRpcStateReader`, each value is only fetched once.
+#[derive(Default)]
+struct StateCache {
+    storage: HashMap<(ContractAddress, StorageKey), Felt>,
+    nonces: HashMap<ContractAddress, Nonce>,
+    class_hashes: HashMap<ContractAddress, ClassHash>,
+    compiled_class_hashes: HashMap<ClassHash, CompiledClassHash>,
+}
+
+/// Blockifier state reader that reads the state at the end of a block from the node.
+///
+/// Values are memoized, so each one is only fetched once, and can be fetched ahead of the
+/// execution with `prefetch`. Compiled classes are stored in a `ClassCache` that can be shared
+/// with other readers and with the code building the OS input.
 pub struct AsyncRpcStateReader {
     rpc_client: RpcClient,
     block_id: BlockId,
+    cache: Mutex<StateCache>,
+    class_cache: Arc<ClassCache>,
 }
 
 impl AsyncRpcStateReader {
     pub fn new(rpc_client: RpcClient, block_id: BlockId) -> Self {
-        Self { rpc_client, block_id }
+        Self {
+            rpc_client,
+            block_id,
+            cache: Mutex::new(StateCache::default()),
+            class_cache: Arc::new(ClassCache::new()),
+        }
+    }
+
+    /// Uses the specified cache for the compiled classes.
+    pub fn with_class_cache(mut self, class_cache: Arc<ClassCache>) -> Self {
+        self.class_cache = class_cache;
+        self
+    }
+
+    fn state_cache(&self) -> std::sync::MutexGuard<'_, StateCache> {
+        self.cache.lock().expect("state cache lock is poisoned")
+    }
+
+    /// Fetches the values of the specified storage keys with batched requests, so that reading
+    /// them during the execution does not require one request per key.
+    pub async fn prefetch_storage(&self, keys: &[(ContractAddress, StorageKey)]) -> Result<(), PrefetchError> {
+        let felt_keys: Vec<(Felt, Felt)> =
+            keys.iter().map(|(contract_address, key)| (*contract_address.key(), *key.0.key())).collect();
+        let values = self.rpc_client.get_storage_values(self.block_id, &felt_keys).await?;
+
+        self.state_cache().storage.extend(keys.iter().copied().zip(values));
+        Ok(())
+    }
+
+    /// Fetches the storage values, nonces, class hashes and classes that the execution will read,
+    /// with at most `max_concurrent_requests` contracts or classes processed at a time.
+    /// Classes that are not declared at the block of the reader, i.e. declared by the block being
+    /// executed, are skipped.
+    pub async fn prefetch(&self, keys: &PrefetchKeys, max_concurrent_requests: usize) -> Result<(), PrefetchError> {
+        self.prefetch_storage(&keys.storage).await?;
+
+        stream::iter(&keys.contracts)
+            .map(|contract_address| async move {
+                self.fetch_nonce(*contract_address).await?;
+                self.fetch_class_hash(*contract_address).await
+            })
+            .buffered(max_concurrent_requests.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        stream::iter(&keys.classes)
+            .map(|class_hash| async move {
+                match self.class_cache.get_class(&self.rpc_client, self.block_id, class_hash.0).await {
+                    Err(ClassCacheError::RpcError(ProviderError::StarknetError(StarknetError::ClassHashNotFound))) => {
+                        Ok(())
+                    }
+                    result => result.map(|_| ()),
+                }
+            })
+            .buffered(max_concurrent_requests.max(1))
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -33,6 +178,10 @@ fn to_state_err<E: ToString>(e: E) -> StateError {
 
 impl AsyncRpcStateReader {
     pub async fn get_storage_at_async(&self, contract_address: ContractAddress, key: StorageKey) -> StateResult<Felt> {
+        if let Some(value) = self.state_cache().storage.get(&(contract_address, key)) {
+            return Ok(*value);
+        }
+
         let storage_value = match self
             .rpc_client
             .starknet_rpc()
@@ -44,52 +193,70 @@ impl AsyncRpcStateReader {
             Err(e) => Err(provider_error_to_state_error(e)),
         }?;
 
+        self.state_cache().storage.insert((contract_address, key), storage_value);
         Ok(storage_value)
     }
 
     pub async fn get_nonce_at_async(&self, contract_address: ContractAddress) -> StateResult<Nonce> {
+        self.fetch_nonce(contract_address).await.map_err(provider_error_to_state_error)
+    }
+
+    async fn fetch_nonce(&self, contract_address: ContractAddress) -> Result<Nonce, ProviderError> {
+        if let Some(nonce) = self.state_cache().nonces.get(&contract_address) {
+            return Ok(*nonce);
+        }
+
         let res = self.rpc_client.starknet_rpc().get_nonce(self.block_id, *contract_address.key()).await;
         let nonce = match res {
             Ok(value) => Ok(value),
             Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(Felt::ZERO),
-            Err(e) => Err(provider_error_to_state_error(e)),
+            Err(e) => Err(e),
         }?;
+
+        self.state_cache().nonces.insert(contract_address, Nonce(nonce));
         Ok(Nonce(nonce))
     }
 
     pub async fn get_class_hash_at_async(&self, contract_address: ContractAddress) -> StateResult<ClassHash> {
+        self.fetch_class_hash(contract_address).await.map_err(provider_error_to_state_error)
+    }
+
+    async fn fetch_class_hash(&self, contract_address: ContractAddress) -> Result<ClassHash, ProviderError> {
+        if let Some(class_hash) = self.state_cache().class_hashes.get(&contract_address) {
+            return Ok(*class_hash);
+        }
+
         let class_hash =
             match self.rpc_client.starknet_rpc().get_class_hash_at(self.block_id, *contract_address.key()).await {
                 Ok(class_hash) => Ok(class_hash),
                 Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(ClassHash::default().0),
-                Err(e) => Err(provider_error_to_state_error(e)),
+                Err(e) => Err(e),
             }?;
 
+        self.state_cache().class_hashes.insert(contract_address, ClassHash(class_hash));
         Ok(ClassHash(class_hash))
     }
 
     pub async fn get_compiled_contract_class_async(&self, class_hash: ClassHash) -> StateResult<ContractClass> {
-        let contract_class = match self.rpc_client.starknet_rpc().get_class(self.block_id, class_hash.0).await {
-            Ok(contract_class) => Ok(contract_class),
-            // If the ContractClass is declared in the current block,
-            // might trigger this error when trying to get it on the previous block.
-            // Returning a `UndeclaredClassHash` allows blockifier to continue execution
-            // Reference: https://github.com/starkware-libs/sequencer/blob/1ade15c645882e3a0bd70ef8f79b23fc66a517e0/crates/blockifier/src/state/cached_state.rs#L178-L200
-            Err(ProviderError::StarknetError(StarknetError::ClassHashNotFound)) => {
-                Err(StateError::UndeclaredClassHash(ClassHash(class_hash.0)))
-            }
-            Err(e) => Err(provider_error_to_state_error(e)),
-        }?;
+        let compiled_class =
+            match self.class_cache.get_compiled_class(&self.rpc_client, self.block_id, class_hash.0).await {
+                Ok(compiled_class) => Ok(compiled_class),
+                // If the ContractClass is declared in the current block,
+                // might trigger this error when trying to get it on the previous block.
+                // Returning a `UndeclaredClassHash` allows blockifier to continue execution
+                // Reference: https://github.com/starkware-libs/sequencer/blob/1ade15c645882e3a0bd70ef8f79b23fc66a517e0/crates/blockifier/src/state/cached_state.rs#L178-L200
+                Err(ClassCacheError::RpcError(ProviderError::StarknetError(StarknetError::ClassHashNotFound))) => {
+                    Err(StateError::UndeclaredClassHash(ClassHash(class_hash.0)))
+                }
+                Err(e) => Err(to_state_err(e)),
+            }?;
 
-        let contract_class: ContractClass = match contract_class {
-            starknet::core::types::ContractClass::Sierra(sierra_class) => {
-                let contract_class = GenericSierraContractClass::from(sierra_class);
-                let compiled_class = contract_class.compile().map_err(to_state_err)?;
+        let contract_class: ContractClass = match compiled_class {
+            GenericCompiledClass::Cairo1(compiled_class) => {
                 compiled_class.to_blockifier_contract_class().map(Into::into).map_err(to_state_err)?
             }
-            starknet::core::types::ContractClass::Legacy(legacy_class) => {
-                let contract_class = GenericDeprecatedCompiledClass::try_from(legacy_class).map_err(to_state_err)?;
-                contract_class.to_blockifier_contract_class().map(Into::into).map_err(to_state_err)?
+            GenericCompiledClass::Cairo0(compiled_class) => {
+                compiled_class.to_blockifier_contract_class().map(Into::into).map_err(to_state_err)?
             }
         };
 
@@ -97,26 +264,19 @@ impl AsyncRpcStateReader {
     }
 
     pub async fn get_compiled_class_hash_async(&self, class_hash: ClassHash) -> StateResult<CompiledClassHash> {
-        let contract_class = self
-            .rpc_client
-            .starknet_rpc()
-            .get_class(self.block_id, class_hash.0)
-            .await
-            .map_err(provider_error_to_state_error)?;
+        if let Some(compiled_class_hash) = self.state_cache().compiled_class_hashes.get(&class_hash) {
+            return Ok(*compiled_class_hash);
+        }
 
-        let class_hash: GenericClassHash = match contract_class {
-            starknet::core::types::ContractClass::Sierra(sierra_class) => {
-                let contract_class = GenericSierraContractClass::from(sierra_class);
-                let compiled_class = contract_class.compile().map_err(to_state_err)?;
-                compiled_class.class_hash().map_err(to_state_err)?
-            }
-            starknet::core::types::ContractClass::Legacy(legacy_class) => {
-                let contract_class = GenericDeprecatedCompiledClass::try_from(legacy_class).map_err(to_state_err)?;
-                contract_class.class_hash().map_err(to_state_err)?
-            }
-        };
+        let compiled_class = self
+            .class_cache
+            .get_compiled_class(&self.rpc_client, self.block_id, class_hash.0)
+            .await
+            .map_err(to_state_err)?;
+        let compiled_class_hash: CompiledClassHash = compiled_class.class_hash().map_err(to_state_err)?.into();
 
-        Ok(class_hash.into())
+        self.state_cache().compiled_class_hashes.insert(class_hash, compiled_class_hash);
+        Ok(compiled_class_hash)
     }
 }
 
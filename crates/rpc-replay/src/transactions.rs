@@ -12,15 +12,15 @@ use starknet::core::types::{
     InvokeTransactionV1, InvokeTransactionV3, L1HandlerTransaction, ResourceBoundsMapping, Transaction,
     TransactionTrace, TransactionTraceWithHash,
 };
-use starknet::providers::{Provider, ProviderError};
+use starknet::providers::ProviderError;
 use starknet_api::core::{calculate_contract_address, ContractAddress, PatriciaKey};
 use starknet_api::transaction::{Fee, TransactionHash};
 use starknet_api::StarknetApiError;
-use starknet_os_types::deprecated_compiled_class::GenericDeprecatedCompiledClass;
-use starknet_os_types::sierra_contract_class::GenericSierraContractClass;
+use starknet_os_types::compiled_class::GenericCompiledClass;
 use starknet_os_types::starknet_core_addons::LegacyContractDecompressionError;
 use thiserror::Error;
 
+use crate::class_cache::{ClassCache, ClassCacheError};
 use crate::utils::{felt_to_u128, FeltConversionError};
 
 #[derive(Error, Debug)]
@@ -33,12 +33,16 @@ pub enum ToBlockifierError {
     BlockifierContractClassError(#[from] blockifier::execution::errors::ContractClassError),
     #[error("Legacy Contract Decompression Error: {0}")]
     LegacyContractDecompressionError(#[from] LegacyContractDecompressionError),
+    #[error("Class Error: {0}")]
+    ClassCacheError(#[from] ClassCacheError),
     #[error("Starknet API Error: {0}")]
     StarknetApiError(#[from] StarknetApiError),
     #[error("Transaction Execution Error: {0}")]
     TransactionExecutionError(#[from] TransactionExecutionError),
     #[error("Felt Conversion Error: {0}")]
     FeltConversionError(#[from] FeltConversionError),
+    #[error("Unsupported transaction 0x{tx_hash:x}: {tx_type} transactions are not supported")]
+    UnsupportedTransaction { tx_hash: Felt, tx_type: &'static str },
 }
 
 pub fn resource_bounds_core_to_api(
@@ -112,45 +116,34 @@ fn invoke_v3_to_blockifier(
     )))
 }
 
-/// Creates a ClassInfo instance from the given class hash by retrieving the contract class
-/// from the Starknet RPC client and converting it to a Blockifier-compatible format.
+/// Creates a ClassInfo instance from the given class hash by retrieving the compiled class
+/// from the class cache, or from the Starknet RPC client if the class is not cached yet,
+/// and converting it to a Blockifier-compatible format.
 /// Handle both Sierra and Legacy classes
 async fn create_class_info(
     class_hash: Felt,
     client: &RpcClient,
+    class_cache: &ClassCache,
     block_number: u64,
 ) -> Result<ClassInfo, ToBlockifierError> {
-    // TODO: improve this to avoid retrieving this twice. Already done in lib.rs from prove_block
-    let starknet_contract_class: starknet::core::types::ContractClass =
-        client.starknet_rpc().get_class(BlockId::Number(block_number), class_hash).await?;
-
-    let (blockifier_contract_class, program_length, abi_length) = match starknet_contract_class {
-        starknet::core::types::ContractClass::Sierra(sierra) => {
-            let generic_sierra = GenericSierraContractClass::from(sierra);
-            let flattened_sierra = generic_sierra.clone().to_starknet_core_contract_class()?;
-            let contract_class = blockifier::execution::contract_class::ContractClass::V1(
-                generic_sierra.compile()?.to_blockifier_contract_class()?,
-            );
-
-            (contract_class, flattened_sierra.sierra_program.len(), flattened_sierra.abi.len())
-        }
-
-        starknet::core::types::ContractClass::Legacy(legacy) => {
-            let generic_legacy = GenericDeprecatedCompiledClass::try_from(legacy)?;
-            let contract_class = blockifier::execution::contract_class::ContractClass::V0(
-                generic_legacy.to_blockifier_contract_class()?,
-            );
+    let class = class_cache.get_class(client, BlockId::Number(block_number), class_hash).await?;
 
-            (contract_class, 0, 0)
+    let blockifier_contract_class = match class.compiled_class {
+        GenericCompiledClass::Cairo1(compiled_class) => {
+            blockifier::execution::contract_class::ContractClass::V1(compiled_class.to_blockifier_contract_class()?)
+        }
+        GenericCompiledClass::Cairo0(compiled_class) => {
+            blockifier::execution::contract_class::ContractClass::V0(compiled_class.to_blockifier_contract_class()?)
         }
     };
 
-    Ok(ClassInfo::new(&blockifier_contract_class, program_length, abi_length)?)
+    Ok(ClassInfo::new(&blockifier_contract_class, class.sierra_program_length, class.abi_length)?)
 }
 
 async fn declare_v1_to_blockifier(
     tx: &DeclareTransactionV1,
     client: &RpcClient,
+    class_cache: &ClassCache,
     block_number: u64,
 ) -> Result<blockifier::transaction::transaction_execution::Transaction, ToBlockifierError> {
     let tx_hash = TransactionHash(tx.transaction_hash);
@@ -161,7 +154,7 @@ async fn declare_v1_to_blockifier(
         class_hash: starknet_api::core::ClassHash(tx.class_hash),
         sender_address: starknet_api::core::ContractAddress(PatriciaKey::try_from(tx.sender_address)?),
     });
-    let class_info = create_class_info(tx.class_hash, client, block_number).await?;
+    let class_info = create_class_info(tx.class_hash, client, class_cache, block_number).await?;
     let declare = blockifier::transaction::transactions::DeclareTransaction::new(api_tx, tx_hash, class_info)?;
 
     Ok(blockifier::transaction::transaction_execution::Transaction::AccountTransaction(AccountTransaction::Declare(
@@ -172,6 +165,7 @@ async fn declare_v1_to_blockifier(
 async fn declare_v2_to_blockifier(
     tx: &DeclareTransactionV2,
     client: &RpcClient,
+    class_cache: &ClassCache,
     block_number: u64,
 ) -> Result<blockifier::transaction::transaction_execution::Transaction, ToBlockifierError> {
     let tx_hash = TransactionHash(tx.transaction_hash);
@@ -183,7 +177,7 @@ async fn declare_v2_to_blockifier(
         compiled_class_hash: starknet_api::core::CompiledClassHash(tx.compiled_class_hash),
         sender_address: starknet_api::core::ContractAddress(PatriciaKey::try_from(tx.sender_address)?),
     });
-    let class_info = create_class_info(tx.class_hash, client, block_number).await?;
+    let class_info = create_class_info(tx.class_hash, client, class_cache, block_number).await?;
     let declare = blockifier::transaction::transactions::DeclareTransaction::new(api_tx, tx_hash, class_info)?;
 
     Ok(blockifier::transaction::transaction_execution::Transaction::AccountTransaction(AccountTransaction::Declare(
@@ -194,6 +188,7 @@ async fn declare_v2_to_blockifier(
 async fn declare_v3_to_blockifier(
     tx: &DeclareTransactionV3,
     client: &RpcClient,
+    class_cache: &ClassCache,
     block_number: u64,
 ) -> Result<blockifier::transaction::transaction_execution::Transaction, ToBlockifierError> {
     let tx_hash = TransactionHash(tx.transaction_hash);
@@ -210,7 +205,7 @@ async fn declare_v3_to_blockifier(
         paymaster_data: starknet_api::transaction::PaymasterData(tx.paymaster_data.clone()),
         account_deployment_data: starknet_api::transaction::AccountDeploymentData(tx.account_deployment_data.clone()),
     });
-    let class_info = create_class_info(tx.class_hash, client, block_number).await?;
+    let class_info = create_class_info(tx.class_hash, client, class_cache, block_number).await?;
     let declare = blockifier::transaction::transactions::DeclareTransaction::new(api_tx, tx_hash, class_info)?;
 
     Ok(blockifier::transaction::transaction_execution::Transaction::AccountTransaction(AccountTransaction::Declare(
@@ -356,19 +351,30 @@ pub async fn starknet_rs_to_blockifier(
     trace: &TransactionTraceWithHash,
     gas_prices: &GasPrices,
     client: &RpcClient,
+    class_cache: &ClassCache,
     block_number: u64,
 ) -> Result<blockifier::transaction::transaction_execution::Transaction, ToBlockifierError> {
     let blockifier_tx = match sn_core_tx {
         Transaction::Invoke(tx) => match tx {
-            InvokeTransaction::V0(_) => unimplemented!("starknet_rs_to_blockifier with InvokeTransaction::V0"),
+            InvokeTransaction::V0(tx) => {
+                return Err(ToBlockifierError::UnsupportedTransaction {
+                    tx_hash: tx.transaction_hash,
+                    tx_type: "invoke v0",
+                });
+            }
             InvokeTransaction::V1(tx) => invoke_v1_to_blockifier(tx)?,
             InvokeTransaction::V3(tx) => invoke_v3_to_blockifier(tx)?,
         },
         Transaction::Declare(tx) => match tx {
-            DeclareTransaction::V0(_) => unimplemented!("starknet_rs_to_blockifier with DeclareTransaction::V0"),
-            DeclareTransaction::V1(tx) => declare_v1_to_blockifier(tx, client, block_number).await?,
-            DeclareTransaction::V2(tx) => declare_v2_to_blockifier(tx, client, block_number).await?,
-            DeclareTransaction::V3(tx) => declare_v3_to_blockifier(tx, client, block_number).await?,
+            DeclareTransaction::V0(tx) => {
+                return Err(ToBlockifierError::UnsupportedTransaction {
+                    tx_hash: tx.transaction_hash,
+                    tx_type: "declare v0",
+                });
+            }
+            DeclareTransaction::V1(tx) => declare_v1_to_blockifier(tx, client, class_cache, block_number).await?,
+            DeclareTransaction::V2(tx) => declare_v2_to_blockifier(tx, client, class_cache, block_number).await?,
+            DeclareTransaction::V3(tx) => declare_v3_to_blockifier(tx, client, class_cache, block_number).await?,
         },
         Transaction::L1Handler(tx) => l1_handler_to_blockifier(tx, trace, gas_prices)?,
         Transaction::DeployAccount(tx) => match tx {
@@ -376,8 +382,12 @@ pub async fn starknet_rs_to_blockifier(
             DeployAccountTransaction::V3(tx) => deploy_account_v3_to_blockifier(tx)?,
         },
 
-        Transaction::Deploy(_) => {
-            unimplemented!("we do not plan to support deprecated deploy txs, only deploy_account")
+        // We do not plan to support deprecated deploy txs, only deploy_account
+        Transaction::Deploy(tx) => {
+            return Err(ToBlockifierError::UnsupportedTransaction {
+                tx_hash: tx.transaction_hash,
+                tx_type: "deprecated deploy",
+            });
         }
     };
 
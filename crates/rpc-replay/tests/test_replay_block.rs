@@ -1,9 +1,13 @@
+use std::sync::Arc;
+
 use blockifier::blockifier::block::GasPrices;
+use blockifier::context::ChainInfo;
 use blockifier::state::cached_state::CachedState;
 use blockifier::transaction::transactions::ExecutableTransaction as _;
-use blockifier::versioned_constants::StarknetVersion;
+use blockifier::versioned_constants::{StarknetVersion, VersionedConstants};
 use rpc_client::RpcClient;
-use rpc_replay::block_context::build_block_context;
+use rpc_replay::block_context::{build_block_context, starknet_fee_token_addresses};
+use rpc_replay::class_cache::ClassCache;
 use rpc_replay::rpc_state_reader::AsyncRpcStateReader;
 use rpc_replay::transactions::starknet_rs_to_blockifier;
 use rstest::rstest;
@@ -25,11 +29,15 @@ async fn test_replay_block() {
     let rpc_client = RpcClient::new(rpc_provider);
     let previous_block_number = block_with_txs.block_number - 1;
     let previous_block_id = BlockId::Number(previous_block_number);
-    let state_reader = AsyncRpcStateReader::new(rpc_client.clone(), previous_block_id);
+    let class_cache = Arc::new(ClassCache::new());
+    let state_reader =
+        AsyncRpcStateReader::new(rpc_client.clone(), previous_block_id).with_class_cache(class_cache.clone());
     let mut state = CachedState::from(state_reader);
 
-    let block_context = build_block_context(ChainId::Sepolia, &block_with_txs, StarknetVersion::V0_13_1)
-        .expect("Failed to build block context");
+    let chain_info = ChainInfo { chain_id: ChainId::Sepolia, fee_token_addresses: starknet_fee_token_addresses() };
+    let block_context =
+        build_block_context(chain_info, &block_with_txs, VersionedConstants::get(StarknetVersion::V0_13_1))
+            .expect("Failed to build block context");
 
     let traces = rpc_client
         .starknet_rpc()
@@ -45,7 +53,9 @@ async fn test_replay_block() {
 
     for (tx, trace) in block_with_txs.transactions.iter().zip(traces.iter()) {
         let blockifier_tx =
-            starknet_rs_to_blockifier(tx, trace, &gas_prices, &rpc_client, previous_block_number).await.unwrap();
+            starknet_rs_to_blockifier(tx, trace, &gas_prices, &rpc_client, &class_cache, previous_block_number)
+                .await
+                .unwrap();
         let tx_result = blockifier_tx.execute(&mut state, &block_context, true, true);
 
         match tx_result {
@@ -0,0 +1,106 @@
+//! Mock Starknet/Pathfinder RPC server, to test `RpcClient` and the code built on top of it
+//! without a node.
+//!
+//! The server answers JSON-RPC requests, single or batched, from a `SharedState` that tests
+//! fill with responses (or with a recording made with `RpcClient::new_recording`) and in which
+//! they can inject faults: HTTP errors, JSON-RPC errors, slow responses, malformed bodies and
+//! modified results. All the endpoints (`/rpc/v0_7`, `/rpc/pathfinder/v0.1`, ...) are served
+//! at any path, requests are routed by method name only.
+
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use tokio::sync::oneshot;
+
+mod state;
+
+pub use state::{Fault, FaultKind, SharedState, NO_MOCK_RESPONSE_ERROR_CODE};
+
+/// Mock RPC server listening on a random localhost port. The server stops when dropped.
+pub struct MockRpcServer {
+    address: SocketAddr,
+    state: SharedState,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockRpcServer {
+    /// Starts a server answering from `state`. Must be called from a tokio runtime.
+    pub fn start(state: SharedState) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle_request(state.clone(), request))) }
+        });
+        let server = Server::from_tcp(listener).map_err(std::io::Error::other)?.serve(make_service);
+
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            shutdown_receiver.await.ok();
+        }));
+
+        Ok(Self { address, state, shutdown: Some(shutdown_sender) })
+    }
+
+    /// Base URL of the server, to pass to `RpcClient::new`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn state(&self) -> &SharedState {
+        &self.state
+    }
+}
+
+impl Drop for MockRpcServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+fn response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .expect("mock response should be valid")
+}
+
+async fn handle_request(state: SharedState, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return Ok(response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+    let (requests, is_batch) = match serde_json::from_slice(&body) {
+        Ok(serde_json::Value::Array(requests)) => (requests, true),
+        Ok(request) => (vec![request], false),
+        Err(e) => return Ok(response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+
+    let answer = state.answer(&requests);
+    if !answer.delay.is_zero() {
+        tokio::time::sleep(answer.delay).await;
+    }
+    if let Some(status) = answer.http_status {
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        return Ok(response(status, format!("Injected HTTP {status}")));
+    }
+    if answer.malformed_body {
+        return Ok(response(StatusCode::OK, r#"{"jsonrpc": "2.0", "result": "#.to_string()));
+    }
+
+    let body = if is_batch {
+        serde_json::Value::Array(answer.responses)
+    } else {
+        answer.responses.into_iter().next().unwrap_or_default()
+    };
+    Ok(response(StatusCode::OK, body.to_string()))
+}
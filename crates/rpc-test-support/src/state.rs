@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rpc_client::recording::{RecordingError, RpcRecording};
+use serde_json::{json, Value};
+
+/// JSON-RPC error code returned for requests without a mock response.
+pub const NO_MOCK_RESPONSE_ERROR_CODE: i64 = -32099;
+
+/// Failure injected by the mock server, see `SharedState::inject_fault`.
+#[derive(Clone)]
+pub enum FaultKind {
+    /// Answer with an HTTP error status, ex: 503, instead of a JSON-RPC response.
+    HttpStatus(u16),
+    /// Answer with a JSON-RPC error instead of the mock response.
+    JsonRpcError { code: i64, message: String },
+    /// Wait before answering.
+    Delay(Duration),
+    /// Answer with a body that is not valid JSON.
+    MalformedBody,
+    /// Modify the result of the mock response before answering, ex: to corrupt a proof.
+    ModifyResult(Arc<dyn Fn(&mut Value) + Send + Sync>),
+}
+
+/// A fault and the requests it applies to.
+#[derive(Clone)]
+pub struct Fault {
+    kind: FaultKind,
+    /// Only requests to this method are affected if set.
+    method: Option<String>,
+    /// Number of requests still affected, unlimited if not set.
+    remaining: Option<usize>,
+}
+
+impl Fault {
+    /// A fault affecting all the requests.
+    pub fn new(kind: FaultKind) -> Self {
+        Self { kind, method: None, remaining: None }
+    }
+
+    /// Only affects requests to the specified method.
+    pub fn for_method(mut self, method: &str) -> Self {
+        self.method = Some(method.to_string());
+        self
+    }
+
+    /// Only affects the next `n` matching requests.
+    pub fn times(mut self, n: usize) -> Self {
+        self.remaining = Some(n);
+        self
+    }
+
+    /// Consumes one occurrence of the fault if it applies to the method.
+    fn apply_to(&mut self, method: &str) -> bool {
+        if self.method.as_deref().is_some_and(|fault_method| fault_method != method) {
+            return false;
+        }
+        match &mut self.remaining {
+            Some(0) => false,
+            Some(remaining) => {
+                *remaining -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    /// Responses to specific requests.
+    responses: RpcRecording,
+    /// Results returned for any parameters, for requests not found in `responses`.
+    method_results: HashMap<String, Value>,
+    faults: Vec<Fault>,
+    /// Requests received by the server, in order.
+    received_requests: Vec<(String, Value)>,
+}
+
+/// What the server must answer to an HTTP request.
+pub(crate) struct Answer {
+    pub(crate) delay: Duration,
+    pub(crate) http_status: Option<u16>,
+    pub(crate) malformed_body: bool,
+    /// One JSON-RPC response per request of the HTTP request.
+    pub(crate) responses: Vec<Value>,
+}
+
+/// Responses and faults of a mock server, shared with the tests. Changes are visible to the
+/// server immediately, including after it started.
+#[derive(Clone, Default)]
+pub struct SharedState {
+    inner: Arc<Mutex<MockState>>,
+}
+
+impl SharedState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the responses of a recording, see `RpcClient::new_recording`.
+    pub fn from_recording(recording: RpcRecording) -> Self {
+        let state = Self::new();
+        state.lock().responses = recording;
+        state
+    }
+
+    /// Serves the responses of a recording archive, ex: a fixture recorded with `prove_block --record`.
+    pub fn load_fixture(path: &Path) -> Result<Self, RecordingError> {
+        Ok(Self::from_recording(RpcRecording::load(path)?))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.inner.lock().expect("mock state lock is poisoned")
+    }
+
+    /// Answers requests to `method` with exactly these parameters with `result`.
+    pub fn set_result(&self, method: &str, params: Value, result: Value) {
+        self.lock().responses.insert(method, params, json!({ "jsonrpc": "2.0", "id": 0, "result": result }));
+    }
+
+    /// Answers requests to `method` with exactly these parameters with a JSON-RPC error.
+    pub fn set_error(&self, method: &str, params: Value, code: i64, message: &str) {
+        let response = json!({ "jsonrpc": "2.0", "id": 0, "error": { "code": code, "message": message } });
+        self.lock().responses.insert(method, params, response);
+    }
+
+    /// Answers requests to `method` with `result` whatever their parameters, unless a response
+    /// was set for the specific parameters.
+    pub fn set_method_result(&self, method: &str, result: Value) {
+        self.lock().method_results.insert(method.to_string(), result);
+    }
+
+    /// Injects a fault in the next responses. Faults apply in the order they were injected.
+    pub fn inject_fault(&self, fault: Fault) {
+        self.lock().faults.push(fault);
+    }
+
+    pub fn clear_faults(&self) {
+        self.lock().faults.clear();
+    }
+
+    /// Returns the method and parameters of the requests received so far. The requests of a batch
+    /// are listed individually.
+    pub fn received_requests(&self) -> Vec<(String, Value)> {
+        self.lock().received_requests.clone()
+    }
+
+    /// Computes the answer to the JSON-RPC requests of an HTTP request.
+    pub(crate) fn answer(&self, requests: &[Value]) -> Answer {
+        let mut state = self.lock();
+        let mut answer = Answer { delay: Duration::ZERO, http_status: None, malformed_body: false, responses: vec![] };
+
+        for request in requests {
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            state.received_requests.push((method.clone(), params.clone()));
+
+            let mut response = match (state.responses.get(&method, &params), state.method_results.get(&method)) {
+                (Some(response), _) => response.clone(),
+                (None, Some(result)) => json!({ "jsonrpc": "2.0", "result": result }),
+                (None, None) => json!({
+                    "jsonrpc": "2.0",
+                    "error": {
+                        "code": NO_MOCK_RESPONSE_ERROR_CODE,
+                        "message": format!("No mock response for {method} with params {params}"),
+                    },
+                }),
+            };
+
+            let faults: Vec<FaultKind> = state
+                .faults
+                .iter_mut()
+                .filter_map(|fault| fault.apply_to(&method).then(|| fault.kind.clone()))
+                .collect();
+            for fault in faults {
+                match fault {
+                    FaultKind::HttpStatus(status) => answer.http_status = Some(status),
+                    FaultKind::JsonRpcError { code, message } => {
+                        response = json!({ "jsonrpc": "2.0", "error": { "code": code, "message": message } });
+                    }
+                    FaultKind::Delay(delay) => answer.delay = answer.delay.max(delay),
+                    FaultKind::MalformedBody => answer.malformed_body = true,
+                    FaultKind::ModifyResult(modify) => {
+                        if let Some(result) = response.get_mut("result") {
+                            modify(result);
+                        }
+                    }
+                }
+            }
+
+            response["id"] = id;
+            answer.responses.push(response);
+        }
+
+        answer
+    }
+}
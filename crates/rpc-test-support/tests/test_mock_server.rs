@@ -0,0 +1,212 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rpc_client::pathfinder::client::ClientError;
+use rpc_client::pathfinder::proofs::{
+    verify_proof, ContractData, EdgePath, PathfinderProof, ProofOutcome, ProofVerificationError, TrieNode,
+};
+use rpc_client::proof_provider::ProofApi;
+use rpc_client::{RpcClient, RpcClientConfig};
+use rpc_replay::rpc_state_reader::AsyncRpcStateReader;
+use rpc_test_support::{Fault, FaultKind, MockRpcServer, SharedState};
+use serde_json::json;
+use starknet::core::types::BlockId;
+use starknet::providers::jsonrpc::JsonRpcClientError;
+use starknet::providers::{Provider, ProviderError};
+use starknet_api::core::{ContractAddress, PatriciaKey};
+use starknet_api::state::StorageKey;
+use starknet_os::crypto::pedersen::PedersenHash;
+use starknet_types_core::felt::Felt;
+
+const BLOCK_ID: BlockId = BlockId::Number(10);
+
+fn storage_at_params(contract_address: u64, key: u64) -> serde_json::Value {
+    json!({ "contract_address": Felt::from(contract_address), "key": Felt::from(key), "block_id": BLOCK_ID })
+}
+
+/// Client that retries quickly, to keep the tests fast.
+fn new_rpc_client(server: &MockRpcServer, config: RpcClientConfig) -> RpcClient {
+    let config = RpcClientConfig { initial_backoff: Duration::from_millis(1), ..config };
+    RpcClient::new_with_config(&server.url(), ProofApi::Pathfinder, config)
+}
+
+#[tokio::test]
+async fn test_storage_reads() {
+    let state = SharedState::new();
+    state.set_result("starknet_getStorageAt", storage_at_params(1, 2), json!("0x5"));
+    state.set_error("starknet_getStorageAt", storage_at_params(3, 2), 20, "Contract not found");
+    let server = MockRpcServer::start(state.clone()).unwrap();
+    let rpc_client = new_rpc_client(&server, RpcClientConfig::default());
+
+    let value = rpc_client.starknet_rpc().get_storage_at(Felt::from(1), Felt::from(2), BLOCK_ID).await.unwrap();
+    assert_eq!(value, Felt::from(5));
+
+    // Both keys are read with a single batch
+    let keys = [(Felt::from(1), Felt::from(2)), (Felt::from(3), Felt::from(2))];
+    let values = rpc_client.get_storage_values(BLOCK_ID, &keys).await.unwrap();
+    assert_eq!(values, vec![Felt::from(5), Felt::ZERO]);
+    assert_eq!(state.received_requests().len(), 3);
+    assert_eq!(rpc_client.stats().requests, 2);
+
+    let state_reader = AsyncRpcStateReader::new(rpc_client.clone(), BLOCK_ID);
+    let contract_address = ContractAddress(PatriciaKey::try_from(Felt::from(1)).unwrap());
+    let key = StorageKey(PatriciaKey::try_from(Felt::from(2)).unwrap());
+    state_reader.prefetch_storage(&[(contract_address, key)]).await.unwrap();
+    let n_requests = state.received_requests().len();
+    assert_eq!(state_reader.get_storage_at_async(contract_address, key).await.unwrap(), Felt::from(5));
+    assert_eq!(state.received_requests().len(), n_requests);
+
+    // Reads are memoized, contracts missing from the state have a nonce of 0
+    state.set_error(
+        "starknet_getNonce",
+        json!({ "block_id": BLOCK_ID, "contract_address": Felt::from(3) }),
+        20,
+        "Contract not found",
+    );
+    let contract_address = ContractAddress(PatriciaKey::try_from(Felt::from(3)).unwrap());
+    assert_eq!(state_reader.get_nonce_at_async(contract_address).await.unwrap().0, Felt::ZERO);
+    assert_eq!(state_reader.get_nonce_at_async(contract_address).await.unwrap().0, Felt::ZERO);
+    assert_eq!(state.received_requests().len(), n_requests + 1);
+}
+
+#[tokio::test]
+async fn test_retry_transient_errors() {
+    let state = SharedState::new();
+    state.set_method_result("starknet_chainId", json!("0x534e5f5345504f4c4941"));
+    state.inject_fault(Fault::new(FaultKind::HttpStatus(503)).times(2));
+    let server = MockRpcServer::start(state.clone()).unwrap();
+
+    let rpc_client = new_rpc_client(&server, RpcClientConfig::default());
+    rpc_client.starknet_rpc().chain_id().await.unwrap();
+    assert_eq!(state.received_requests().len(), 3);
+
+    // JSON-RPC errors are not retried
+    state.inject_fault(Fault::new(FaultKind::JsonRpcError { code: -32603, message: "Internal error".to_string() }));
+    let result = rpc_client.starknet_rpc().chain_id().await;
+    let Err(ProviderError::Other(error)) = result else { panic!("Expected an RPC error, got {result:?}") };
+    match error.as_any().downcast_ref::<JsonRpcClientError<ClientError>>() {
+        Some(JsonRpcClientError::JsonRpcError(error)) => assert_eq!(error.code, -32603),
+        _ => panic!("Expected a JSON-RPC error, got {error:?}"),
+    }
+    assert_eq!(state.received_requests().len(), 4);
+}
+
+#[tokio::test]
+async fn test_slow_and_malformed_responses() {
+    let state = SharedState::new();
+    state.set_method_result("starknet_blockNumber", json!(10));
+    let server = MockRpcServer::start(state.clone()).unwrap();
+    let config =
+        RpcClientConfig { request_timeout: Some(Duration::from_millis(50)), max_retries: 0, ..Default::default() };
+    let rpc_client = new_rpc_client(&server, config);
+
+    state.inject_fault(Fault::new(FaultKind::Delay(Duration::from_millis(500))).times(1));
+    assert!(rpc_client.starknet_rpc().block_number().await.is_err());
+    assert_eq!(rpc_client.starknet_rpc().block_number().await.unwrap(), 10);
+
+    state.inject_fault(Fault::new(FaultKind::MalformedBody).for_method("starknet_blockNumber").times(1));
+    assert!(rpc_client.starknet_rpc().block_number().await.is_err());
+}
+
+#[tokio::test]
+async fn test_malformed_proof() {
+    let node = TrieNode::Binary { left: Felt::from(1), right: Felt::from(2) };
+    let proof = PathfinderProof {
+        state_commitment: Felt::from(0x100),
+        class_commitment: None,
+        contract_proof: vec![],
+        contract_data: Some(ContractData { root: node.hash::<PedersenHash>(), storage_proofs: vec![vec![node]] }),
+    };
+    let state = SharedState::new();
+    state.set_method_result("pathfinder_getProof", serde_json::to_value(&proof).unwrap());
+    let server = MockRpcServer::start(state.clone()).unwrap();
+    let rpc_client = new_rpc_client(&server, RpcClientConfig::default());
+    let key = Felt::from(0x10);
+
+    let proof = rpc_client.proof_rpc().get_proof(10, Felt::from(1), &[key]).await.unwrap();
+    assert!(proof.contract_data.unwrap().verify(&[key]).iter().all(ProofOutcome::is_valid));
+
+    state.inject_fault(Fault::new(FaultKind::ModifyResult(Arc::new(|result| {
+        result["contract_data"]["root"] = json!("0x123");
+    }))));
+    let proof = rpc_client.proof_rpc().get_proof(10, Felt::from(1), &[key]).await.unwrap();
+    let contract_data = proof.contract_data.unwrap();
+    let outcomes = contract_data.verify(&[key]);
+    assert!(matches!(outcomes[0], ProofOutcome::Invalid(ProofVerificationError::InvalidChildNodeHash { .. })));
+
+    state.inject_fault(Fault::new(FaultKind::ModifyResult(Arc::new(|result| {
+        result["contract_proof"] = json!("not a proof");
+    }))));
+    let result = rpc_client.proof_rpc().get_proof(10, Felt::from(1), &[key]).await;
+    assert!(matches!(result, Err(ClientError::SerdeError(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn test_proof_batch_with_failed_request() {
+    let proof = PathfinderProof {
+        state_commitment: Felt::from(0x100),
+        class_commitment: None,
+        contract_proof: vec![],
+        contract_data: None,
+    };
+    let state = SharedState::new();
+    state.set_method_result("pathfinder_getProof", serde_json::to_value(&proof).unwrap());
+    state.set_error(
+        "pathfinder_getProof",
+        json!({ "block_id": { "block_number": 10 }, "contract_address": Felt::from(2), "keys": [] }),
+        -32603,
+        "Internal error",
+    );
+    let server = MockRpcServer::start(state.clone()).unwrap();
+    let rpc_client = new_rpc_client(&server, RpcClientConfig::default());
+
+    // The error is reported for the request that failed only
+    let requests = [(Felt::from(1), vec![]), (Felt::from(2), vec![]), (Felt::from(3), vec![])];
+    let results = rpc_client.proof_rpc().get_proofs(10, &requests).await.unwrap();
+    assert!(results[0].is_ok() && results[2].is_ok());
+    assert!(matches!(results[1], Err(ClientError::JsonRpcError { code: -32603, .. })), "{:?}", results[1]);
+}
+
+#[tokio::test]
+async fn test_storage_proof_api_fetches_batches_in_one_call() {
+    let contract_address = Felt::from(0x1234);
+    let leaf_value = Felt::from(7);
+    let contracts_tree_root =
+        TrieNode::Edge { child: leaf_value, path: EdgePath { len: 251, value: contract_address } }
+            .hash::<PedersenHash>();
+    let state = SharedState::new();
+    state.set_method_result(
+        "starknet_getStorageProof",
+        json!({
+            "classes_proof": [],
+            "contracts_proof": {
+                "nodes": [{
+                    "node_hash": contracts_tree_root,
+                    "node": { "path": contract_address, "length": 251, "child": leaf_value },
+                }],
+                "contract_leaves_data": [
+                    { "nonce": "0x1", "class_hash": "0xabcd", "storage_root": "0x0" },
+                    { "nonce": "0x0", "class_hash": "0x0", "storage_root": "0x0" },
+                ],
+            },
+            "contracts_storage_proofs": [],
+            "global_roots": { "contracts_tree_root": contracts_tree_root, "classes_tree_root": "0x0", "block_hash": "0x0" },
+        }),
+    );
+    let server = MockRpcServer::start(state.clone()).unwrap();
+    let config = RpcClientConfig { initial_backoff: Duration::from_millis(1), ..RpcClientConfig::default() };
+    let rpc_client = RpcClient::new_with_config(&server.url(), ProofApi::Starknet, config);
+
+    let requests = [(contract_address, vec![]), (Felt::from(0x5678), vec![])];
+    let proofs = rpc_client.proof_rpc().get_proofs(10, &requests).await.unwrap();
+    assert_eq!(state.received_requests().len(), 1);
+
+    let proofs: Vec<PathfinderProof> = proofs.into_iter().map(Result::unwrap).collect();
+    assert_eq!(
+        verify_proof::<PedersenHash>(contract_address, contracts_tree_root, &proofs[0].contract_proof),
+        ProofOutcome::Member { leaf_value }
+    );
+    assert!(proofs[0].contract_data.is_some());
+    // Contracts missing from the state come with zero values
+    assert!(proofs[1].contract_data.is_none());
+}
@@ -103,6 +103,12 @@ impl GenericCasmContractClass {
     pub fn class_hash(&self) -> Result<GenericClassHash, ContractClassError> {
         self.class_hash.get_or_try_init(|| self.compute_class_hash()).copied()
     }
+
+    /// Sets the compiled class hash when it is already known, ex: when loading the class from
+    /// a cache. The hash is not checked against the class.
+    pub fn with_class_hash(self, class_hash: GenericClassHash) -> Self {
+        Self { class_hash: OnceCell::from(class_hash), ..self }
+    }
 }
 
 impl Serialize for GenericCasmContractClass {
@@ -56,10 +56,16 @@ impl ContractClassComponentHashes {
 
 impl From<starknet_core::types::FlattenedSierraClass> for ContractClassComponentHashes {
     fn from(sierra_class: starknet_core::types::FlattenedSierraClass) -> Self {
+        Self::from(&sierra_class)
+    }
+}
+
+impl From<&starknet_core::types::FlattenedSierraClass> for ContractClassComponentHashes {
+    fn from(sierra_class: &starknet_core::types::FlattenedSierraClass) -> Self {
         let version_str = format!("{CLASS_VERSION_PREFIX}{}", sierra_class.contract_class_version);
         let contract_class_version = Felt::from_bytes_be_slice(version_str.as_bytes());
 
-        let sierra_program_hash = poseidon_hash_many_felts(sierra_class.sierra_program.into_iter());
+        let sierra_program_hash = poseidon_hash_many_felts(sierra_class.sierra_program.iter().copied());
 
         Self {
             contract_class_version,
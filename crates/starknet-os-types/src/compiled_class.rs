@@ -4,6 +4,7 @@ use crate::error::ContractClassError;
 use crate::hash::GenericClassHash;
 
 /// A generic compiled class encapsulating Cairo 0 or Cairo 1 classes.
+#[derive(Clone)]
 pub enum GenericCompiledClass {
     Cairo0(GenericDeprecatedCompiledClass),
     Cairo1(GenericCasmContractClass),
@@ -21,6 +21,8 @@ pub enum SnOsError {
     IO(#[from] std::io::Error),
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
+    #[error("Unsupported OS run bundle version {found} (expected {expected})")]
+    UnsupportedBundleVersion { found: u32, expected: u32 },
     #[error("SnOs Deprecated Syscall Error: {0}")]
     InvalidDeprecatedSyscallSelector(Felt252),
 }
@@ -0,0 +1,261 @@
+use std::io::{BufWriter, Write};
+use std::num::NonZeroU128;
+use std::rc::Rc;
+use std::{fs, path};
+
+use blockifier::blockifier::block::{BlockInfo, GasPrices};
+use blockifier::bouncer::BouncerConfig;
+use blockifier::context::{BlockContext, ChainInfo, FeeTokenAddresses};
+use blockifier::transaction::objects::TransactionExecutionInfo;
+use blockifier::versioned_constants::VersionedConstants;
+use cairo_vm::Felt252;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use starknet_api::block::{BlockNumber, BlockTimestamp};
+use starknet_api::core::{ChainId, ContractAddress};
+
+use crate::error::SnOsError;
+use crate::execution::helper::{ContractStorageMap, ExecutionHelperWrapper};
+use crate::io::input::StarknetOsInput;
+use crate::starknet::starknet_storage::PerContractStorage;
+
+/// Version of the bundle file format.
+/// Bump it whenever the layout of `OsRunBundle` changes, older bundles will then be rejected on
+/// load instead of being run with wrong inputs.
+pub const OS_RUN_BUNDLE_VERSION: u32 = 2;
+
+/// Everything `run_os` consumes, in a form that can be saved to a file and run again on a machine
+/// without access to a node, e.g. to reproduce a failing run. See `run_os_from_bundle`.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "PCS: Serialize", deserialize = "PCS: DeserializeOwned"))]
+pub struct OsRunBundle<PCS> {
+    version: u32,
+    /// Starknet version of the block, used to find the versioned constants and the OS program
+    /// the block must be run with.
+    pub starknet_version: String,
+    pub os_input: StarknetOsInput,
+    /// Only the block info and chain info are saved, see `SerializableBlockContext`. Once loaded,
+    /// the context must be given the versioned constants of `starknet_version` with
+    /// `with_versioned_constants`.
+    #[serde(serialize_with = "serialize_block_context", deserialize_with = "deserialize_block_context")]
+    pub block_context: BlockContext,
+    pub tx_execution_infos: Vec<TransactionExecutionInfo>,
+    /// Number and hash of the block written to the block hash contract by this block.
+    pub old_block_number: Felt252,
+    pub old_block_hash: Felt252,
+    /// Storage of the contracts accessed in the block. The values read by the OS must be readable
+    /// once the bundle is loaded, without a node.
+    pub contract_storages: ContractStorageMap<PCS>,
+}
+
+impl<PCS> OsRunBundle<PCS> {
+    pub fn new(
+        starknet_version: String,
+        os_input: StarknetOsInput,
+        block_context: BlockContext,
+        tx_execution_infos: Vec<TransactionExecutionInfo>,
+        old_block_number: Felt252,
+        old_block_hash: Felt252,
+        contract_storages: ContractStorageMap<PCS>,
+    ) -> Self {
+        Self {
+            version: OS_RUN_BUNDLE_VERSION,
+            starknet_version,
+            os_input,
+            block_context,
+            tx_execution_infos,
+            old_block_number,
+            old_block_hash,
+            contract_storages,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Replaces the versioned constants of the block context, see `SerializableBlockContext`.
+    pub fn with_versioned_constants(self, versioned_constants: VersionedConstants) -> Self {
+        let block_context = BlockContext::new(
+            self.block_context.block_info().clone(),
+            self.block_context.chain_info().clone(),
+            versioned_constants,
+            BouncerConfig::max(),
+        );
+        Self { block_context, ..self }
+    }
+
+    pub fn load(path: &path::Path) -> Result<Self, SnOsError>
+    where
+        PCS: DeserializeOwned,
+    {
+        let raw_bundle = fs::read(path)?;
+        let bundle: Self = serde_json::from_slice(&raw_bundle)?;
+        if bundle.version != OS_RUN_BUNDLE_VERSION {
+            return Err(SnOsError::UnsupportedBundleVersion { found: bundle.version, expected: OS_RUN_BUNDLE_VERSION });
+        }
+
+        Ok(bundle)
+    }
+
+    pub fn dump(&self, path: &path::Path) -> Result<(), SnOsError>
+    where
+        PCS: Serialize,
+    {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Splits the bundle into the arguments of `run_os`.
+    pub fn into_run_args(self) -> (Rc<StarknetOsInput>, BlockContext, ExecutionHelperWrapper<PCS>)
+    where
+        PCS: PerContractStorage + 'static,
+    {
+        let os_input = Rc::new(self.os_input);
+        let execution_helper = ExecutionHelperWrapper::new(
+            self.contract_storages,
+            self.tx_execution_infos,
+            &self.block_context,
+            Some(os_input.clone()),
+            (self.old_block_number, self.old_block_hash),
+        );
+
+        (os_input, self.block_context, execution_helper)
+    }
+}
+
+/// Serializable part of a `BlockContext`.
+///
+/// The versioned constants and the bouncer config are not saved: the versioned constants are
+/// identified by the Starknet version of the bundle, the bouncer config is only used by blockifier.
+/// A loaded context uses the latest versioned constants until they are replaced with
+/// `OsRunBundle::with_versioned_constants`.
+#[derive(Serialize, Deserialize)]
+struct SerializableBlockContext {
+    block_number: BlockNumber,
+    block_timestamp: BlockTimestamp,
+    sequencer_address: ContractAddress,
+    eth_l1_gas_price: NonZeroU128,
+    strk_l1_gas_price: NonZeroU128,
+    eth_l1_data_gas_price: NonZeroU128,
+    strk_l1_data_gas_price: NonZeroU128,
+    use_kzg_da: bool,
+    chain_id: ChainId,
+    strk_fee_token_address: ContractAddress,
+    eth_fee_token_address: ContractAddress,
+}
+
+impl From<&BlockContext> for SerializableBlockContext {
+    fn from(block_context: &BlockContext) -> Self {
+        let block_info = block_context.block_info();
+        let chain_info = block_context.chain_info();
+        Self {
+            block_number: block_info.block_number,
+            block_timestamp: block_info.block_timestamp,
+            sequencer_address: block_info.sequencer_address,
+            eth_l1_gas_price: block_info.gas_prices.eth_l1_gas_price,
+            strk_l1_gas_price: block_info.gas_prices.strk_l1_gas_price,
+            eth_l1_data_gas_price: block_info.gas_prices.eth_l1_data_gas_price,
+            strk_l1_data_gas_price: block_info.gas_prices.strk_l1_data_gas_price,
+            use_kzg_da: block_info.use_kzg_da,
+            chain_id: chain_info.chain_id.clone(),
+            strk_fee_token_address: chain_info.fee_token_addresses.strk_fee_token_address,
+            eth_fee_token_address: chain_info.fee_token_addresses.eth_fee_token_address,
+        }
+    }
+}
+
+impl From<SerializableBlockContext> for BlockContext {
+    fn from(block_context: SerializableBlockContext) -> Self {
+        let block_info = BlockInfo {
+            block_number: block_context.block_number,
+            block_timestamp: block_context.block_timestamp,
+            sequencer_address: block_context.sequencer_address,
+            gas_prices: GasPrices {
+                eth_l1_gas_price: block_context.eth_l1_gas_price,
+                strk_l1_gas_price: block_context.strk_l1_gas_price,
+                eth_l1_data_gas_price: block_context.eth_l1_data_gas_price,
+                strk_l1_data_gas_price: block_context.strk_l1_data_gas_price,
+            },
+            use_kzg_da: block_context.use_kzg_da,
+        };
+        let chain_info = ChainInfo {
+            chain_id: block_context.chain_id,
+            fee_token_addresses: FeeTokenAddresses {
+                strk_fee_token_address: block_context.strk_fee_token_address,
+                eth_fee_token_address: block_context.eth_fee_token_address,
+            },
+        };
+
+        BlockContext::new(block_info, chain_info, VersionedConstants::latest_constants().clone(), BouncerConfig::max())
+    }
+}
+
+fn serialize_block_context<S: Serializer>(block_context: &BlockContext, serializer: S) -> Result<S::Ok, S::Error> {
+    SerializableBlockContext::from(block_context).serialize(serializer)
+}
+
+fn deserialize_block_context<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BlockContext, D::Error> {
+    SerializableBlockContext::deserialize(deserializer).map(BlockContext::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StarknetGeneralConfig;
+
+    #[test]
+    fn test_bundle_serialization() {
+        let general_config = StarknetGeneralConfig::default();
+        let bundle = OsRunBundle::<()>::new(
+            "0.13.2".to_string(),
+            StarknetOsInput { general_config: general_config.clone(), ..Default::default() },
+            general_config.empty_block_context(),
+            vec![TransactionExecutionInfo::default()],
+            Felt252::from(10),
+            Felt252::from(0x1234),
+            [(Felt252::from(0x100), ())].into_iter().collect(),
+        );
+
+        let serialized = serde_json::to_string(&bundle).unwrap();
+        let loaded: OsRunBundle<()> = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(loaded.version(), OS_RUN_BUNDLE_VERSION);
+        assert_eq!(loaded.starknet_version, bundle.starknet_version);
+        assert_eq!(loaded.os_input.general_config, general_config);
+        assert_eq!(loaded.block_context.block_info().sequencer_address, general_config.sequencer_address);
+        assert_eq!(loaded.block_context.chain_info().chain_id, general_config.starknet_os_config.chain_id);
+        assert_eq!(loaded.tx_execution_infos.len(), 1);
+        assert_eq!(loaded.old_block_hash, bundle.old_block_hash);
+        assert!(loaded.contract_storages.contains_key(&Felt252::from(0x100)));
+    }
+
+    #[test]
+    fn test_bundle_rejects_unknown_version() {
+        let general_config = StarknetGeneralConfig::default();
+        let mut bundle = OsRunBundle::<()>::new(
+            "0.13.2".to_string(),
+            StarknetOsInput { general_config: general_config.clone(), ..Default::default() },
+            general_config.empty_block_context(),
+            vec![],
+            Felt252::from(10),
+            Felt252::from(0x1234),
+            Default::default(),
+        );
+        bundle.version = OS_RUN_BUNDLE_VERSION + 1;
+
+        let path = std::env::temp_dir().join(format!("snos-bundle-version-test-{}.json", std::process::id()));
+        bundle.dump(&path).unwrap();
+        let result = OsRunBundle::<()>::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(SnOsError::UnsupportedBundleVersion { found, expected: OS_RUN_BUNDLE_VERSION })
+                if found == OS_RUN_BUNDLE_VERSION + 1
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod bundle;
 pub mod classes;
 pub mod input;
 pub mod output;
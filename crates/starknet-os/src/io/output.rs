@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
 use cairo_vm::types::relocatable::MaybeRelocatable;
 use cairo_vm::vm::runners::builtin_runner::BuiltinRunner;
@@ -20,6 +21,7 @@ const CONFIG_HASH_OFFSET: usize = 7;
 const USE_KZG_DA_OFFSET: usize = 8;
 const FULL_OUTPUT_OFFSET: usize = 9;
 const HEADER_SIZE: usize = 10;
+const KZG_HEADER_SIZE: usize = 2;
 const KZG_N_BLOBS_OFFSET: usize = 1;
 
 /// Represents the changes in a contract instance.
@@ -261,6 +263,17 @@ fn read_segment<I: Iterator<Item = Felt252>>(
     Ok(segment)
 }
 
+/// Offsets of the KZG data (header, commitments and evaluations) in the output of an OS run using
+/// KZG data availability, skipped by `deserialize_os_output`. `None` if the output does not use KZG.
+pub fn kzg_data_offsets(output: &[Felt252]) -> Option<Range<usize>> {
+    if output.get(USE_KZG_DA_OFFSET)?.is_zero() {
+        return None;
+    }
+    let n_blobs: usize = output.get(HEADER_SIZE + KZG_N_BLOBS_OFFSET)?.to_biguint().try_into().ok()?;
+
+    Some(HEADER_SIZE..HEADER_SIZE + KZG_HEADER_SIZE + 2 * 2 * n_blobs)
+}
+
 // Reverse of serialize_os_output in os/output.cairo
 pub fn deserialize_os_output<I>(output_iter: &mut I) -> Result<StarknetOsOutput, SnOsError>
 where
@@ -272,7 +285,7 @@ where
 
     if !use_kzg_da.is_zero() {
         // Skip KZG data.
-        let kzg_segment: Vec<_> = output_iter.by_ref().take(2).collect();
+        let kzg_segment: Vec<_> = output_iter.by_ref().take(KZG_HEADER_SIZE).collect();
         let n_blobs: usize = kzg_segment
             .get(KZG_N_BLOBS_OFFSET)
             .expect("Should have n_blobs in header when using kzg da")
@@ -15,6 +15,7 @@ use io::output::StarknetOsOutput;
 use crate::execution::syscall_handler::OsSyscallHandlerWrapper;
 use crate::hints::types::{PatriciaSkipValidationRunner, PatriciaTreeMode};
 use crate::hints::vars;
+use crate::io::bundle::OsRunBundle;
 use crate::io::input::StarknetOsInput;
 use crate::starknet::starknet_storage::PerContractStorage;
 
@@ -38,6 +39,38 @@ pub fn run_os<PCS>(
     block_context: BlockContext,
     execution_helper: ExecutionHelperWrapper<PCS>,
 ) -> Result<(CairoPie, StarknetOsOutput), SnOsError>
+where
+    PCS: PerContractStorage + 'static,
+{
+    let (cairo_runner, os_output) = run_os_vm(compiled_os, layout, os_input, block_context, execution_helper)?;
+    let pie = get_os_pie(&cairo_runner)?;
+
+    Ok((pie, os_output))
+}
+
+/// Runs the OS on a bundle saved with `OsRunBundle::dump`, without access to a node.
+pub fn run_os_from_bundle<PCS>(
+    compiled_os: &[u8],
+    layout: LayoutName,
+    bundle: OsRunBundle<PCS>,
+) -> Result<(CairoPie, StarknetOsOutput), SnOsError>
+where
+    PCS: PerContractStorage + 'static,
+{
+    let (os_input, block_context, execution_helper) = bundle.into_run_args();
+    run_os(compiled_os, layout, os_input, block_context, execution_helper)
+}
+
+/// Runs the OS in the Cairo VM, up to the relocation of the memory.
+/// Returns the runner, from which the PIE can be extracted with `get_os_pie`, and the OS output.
+/// `run_os` performs both steps.
+pub fn run_os_vm<PCS>(
+    compiled_os: &[u8],
+    layout: LayoutName,
+    os_input: Rc<StarknetOsInput>,
+    block_context: BlockContext,
+    execution_helper: ExecutionHelperWrapper<PCS>,
+) -> Result<(CairoRunner, StarknetOsOutput), SnOsError>
 where
     PCS: PerContractStorage + 'static,
 {
@@ -107,8 +140,10 @@ where
     cairo_runner.read_return_values(allow_missing_builtins).map_err(|e| SnOsError::Runner(e.into()))?;
     cairo_runner.relocate(cairo_run_config.relocate_mem).map_err(|e| SnOsError::Runner(e.into()))?;
 
-    // Parse the Cairo VM output
-    let pie = cairo_runner.get_cairo_pie().map_err(|e| SnOsError::PieParsing(format!("{e}")))?;
+    Ok((cairo_runner, os_output))
+}
 
-    Ok((pie, os_output))
+/// Extracts the PIE of an OS run, see `run_os_vm`.
+pub fn get_os_pie(cairo_runner: &CairoRunner) -> Result<CairoPie, SnOsError> {
+    cairo_runner.get_cairo_pie().map_err(|e| SnOsError::PieParsing(format!("{e}")))
 }